@@ -18,7 +18,32 @@ pub const REG_PRODUCT_NAME: &str = r"Software\Microsoft\Windows NT\CurrentVersio
 pub const REG_SUBKEY_CRATES: &str = r"Software\Monoslab\PassKeeper";
 pub const REG_SUBKEY_POS: &str = r"Software\Monoslab\PassKeeper\Pos";
 pub const REG_SUBKEY_SETTINGS: &str = r"Software\Monoslab\PassKeeper\Settings";
+pub const REG_SUBKEY_GENERATIONS: &str = r"Software\Monoslab\PassKeeper\Generations";
+pub const REG_SUBKEY_VAULT_IDS: &str = r"Software\Monoslab\PassKeeper\VaultIds";
 
 // minimum window size
 pub const MIN_WIDTH: i32 = 600;
-pub const MIN_HEIGHT: i32 = 400;
\ No newline at end of file
+pub const MIN_HEIGHT: i32 = 400;
+
+// key derivation (Argon2id)
+pub const KDF_MEMORY_KIB: u32 = 19456;
+pub const KDF_ITERATIONS: u32 = 2;
+pub const KDF_PARALLELISM: u32 = 1;
+pub const KDF_MAX_MEMORY_KIB: u32 = 1024 * 1024;
+pub const KDF_MAX_ITERATIONS: u32 = 64;
+pub const KDF_MAX_PARALLELISM: u32 = 16;
+pub const KDF_TARGET_MS: u32 = 500;
+
+// quick-unlock PIN
+pub const PIN_MIN_LENGTH: usize = 4;
+pub const PIN_MAX_LENGTH: usize = 8;
+pub const PIN_MAX_ATTEMPTS: u32 = 3;
+
+// password history
+pub const PASSWORD_HISTORY_SIZE: u32 = 10;
+
+// trash
+pub const TRASH_RETENTION_DAYS: u32 = 30;
+
+// undo
+pub const UNDO_DEPTH: usize = 50;
\ No newline at end of file
@@ -1,26 +1,75 @@
 pub mod module;
 use common::define;
 use base64::{engine::general_purpose, Engine as _};
+#[cfg(windows)]
+use module::registry::{
+    self,
+    HKEY_CURRENT_USER
+};
 use module::{
-    registry::{
-        self,
-        HKEY_CURRENT_USER
-    },
     crypto::{
-        myhash::hash_data,
-        mycrypt::{
-            encrypt_data,
-            decrypt_data
+        mykdf::{
+            derive_key_with,
+            calibrate,
+            KdfParams,
         },
-    }
+    },
+    session::{
+        self,
+        PinError,
+        VaultKey,
+    },
+    settings,
+    keyring,
+    vault::{
+        self,
+        Payload,
+        PayloadError,
+        Slot,
+        SlotKdf,
+        VaultFile,
+        make_skey,
+        make_wipe_skey,
+        new_entry_id,
+    },
+    selftest,
+    generation,
+    store::{
+        self,
+        StoreError,
+        StoreKind,
+        VaultStore,
+    },
+    cache::{Lookup, VaultCache},
+    sync::{self, Journal},
+    watcher,
+    vaults::{
+        self,
+        VaultsError,
+        DEFAULT_VAULT,
+    },
+    share::{self, ShareError},
+    teams::{self, Role, TeamsError},
+    admin::{self, AdminConfig, AdminError},
+    entry::EntryFields,
+    folders::{self, FolderError, ROOT_FOLDER},
+    types,
+    history::{self, HistoryError, MAX_HISTORY},
+    timestamps::{self, SortKey},
+    trash,
+    undo::{self, Change, Operation},
+    search::{self, SearchFilters},
+    decoy,
+    companion::{self, Other},
 };
 use std::{
+    fmt,
     fs,
     env::current_dir,
-    path::Path,
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use serde::Serialize;
 use serde_json::{
     json,
     Value,
@@ -29,7 +78,9 @@ use serde_json::{
 };
 use tauri::{
     AppHandle,
+    Emitter,
     Manager,
+    State,
     command,
 };
 use once_cell::sync::OnceCell;
@@ -49,12 +100,73 @@ enum PassKeeperError {
     DuplicateEntry = 5,
     EncryptionFailed = 6,
     DecryptionFailed = 7,
+    SessionLocked = 8,
+    PinNotSet = 9,
+    InvalidPin = 10,
+    PinDiscarded = 11,
+    NotSupported = 12,
+    InvalidDuressPassword = 13,
+    CryptoSelfTestFailed = 14,
+    InvalidKdfParams = 15,
+    IntegrityCheckFailed = 16,
+    RollbackDetected = 17,
+    VaultBusy = 18,
+    VaultNotFound = 19,
+    DuplicateVaultName = 20,
+    InvalidVaultName = 21,
+    RecipientNotFound = 22,
+    InvalidSignature = 23,
+    ReadOnlyVault = 24,
+    NotVaultOwner = 25,
+    NotAdmin = 26,
+    WeakPassword = 27,
+    AccountDisabled = 28,
+    KeyNotEscrowed = 29,
+    InvalidField = 30,
+    FolderNotFound = 31,
+    InvalidFolder = 32,
+    NothingToUndo = 33,
+    UndoConflict = 34,
+}
+
+/// Result of the integrity check of a vault when it is loaded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Integrity {
+    /// Authenticated and not older than the last seen generation
+    Verified,
+    /// Authenticated, but written before generations existed
+    Unversioned,
+    /// Authenticated, but older than the last seen generation
+    RolledBack { expected: u64, found: u64 },
+    /// The payload was changed or cannot be authenticated with the vault key
+    Tampered,
+    /// The payload line is missing or is not base64
+    Corrupted,
 }
 
-#[derive(Debug)]
+#[derive(Clone)]
 struct SecretData {
     skey: String,
     data: String,
+    // The slot opened by the vault key
+    slot: usize,
+    // The decrypted payload of the slot and the result of its integrity check
+    payload: Payload,
+    integrity: Integrity,
+    // Entries changed in this session, to merge with changes made by someone else
+    journal: Journal,
+}
+
+// The decrypted data and the journal hold the secrets, they are never shown
+impl fmt::Debug for SecretData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretData")
+            .field("slot", &self.slot)
+            .field("payload", &self.payload)
+            .field("integrity", &self.integrity)
+            .finish_non_exhaustive()
+    }
 }
 
 impl SecretData {
@@ -62,6 +174,10 @@ impl SecretData {
         SecretData {
             skey: String::new(),
             data: String::new(),
+            slot: 0,
+            payload: Payload::default(),
+            integrity: Integrity::Unversioned,
+            journal: Journal::default(),
         }
     }
     fn is_skey_empty(&self) -> bool {
@@ -70,27 +186,261 @@ impl SecretData {
     fn is_data_empty(&self) -> bool {
         self.data.is_empty()
     }
+    /// Returns `true` if the vault passed its integrity check, without warning the frontend.
+    fn check_integrity_quietly(&self) -> bool {
+        matches!(self.integrity, Integrity::Verified | Integrity::Unversioned)
+    }
+    /// An unreadable vault is not an empty one, it must never be written over.
+    fn is_unreadable(&self) -> bool {
+        matches!(self.integrity, Integrity::Tampered | Integrity::Corrupted)
+    }
+    /// Warns the frontend about a failed integrity check,
+    /// and returns the error code if the vault must not be used.
+    fn check_integrity(&self, name: &str) -> Result<(), u32> {
+        match self.integrity {
+            Integrity::Verified | Integrity::Unversioned => Ok(()),
+            Integrity::Tampered | Integrity::Corrupted => {
+                emit_integrity_warning(name, self.integrity);
+                Err(PassKeeperError::IntegrityCheckFailed as u32)
+            },
+            Integrity::RolledBack { .. } => {
+                emit_integrity_warning(name, self.integrity);
+                if generation::refuses_rollback() {
+                    Err(PassKeeperError::RollbackDetected as u32)
+                } else {
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+/// Decrypted vaults of unlocked users, kept in managed state
+type SecretCache = VaultCache<SecretData>;
+
+/// A vault of an unlocked user: the storage name, the storage and the vault key,
+/// and whether the user may write it
+struct OpenVault {
+    name: String,
+    store: Box<dyn VaultStore>,
+    key: VaultKey,
+    writable: bool,
+}
+
+/// Open a vault of an unlocked user, the selected vault when no id is given
+fn open_vault(username: &str, vault_id: Option<&str>) -> Result<OpenVault, u32> {
+    if !store::exists(username) {
+        return Err(PassKeeperError::FileNotFound as u32);
+    }
+    let login_key = session::vault_key(username).ok_or(PassKeeperError::SessionLocked as u32)?;
+    let vault_id = match vault_id {
+        Some(id) => id.to_string(),
+        None => session::current_vault(username).unwrap_or_else(|| DEFAULT_VAULT.to_string()),
+    };
+    let (name, key, writable) = match teams::team_id(&vault_id) {
+        Some(team_id) => {
+            let (key, role) = teams::open(team_id, username, &login_key).map_err(|e| teams_error_code(&e))?;
+            (teams::store_name(team_id), key, role.can_write())
+        },
+        None => {
+            let key = vaults::key(username, &login_key, &vault_id).ok_or(PassKeeperError::VaultNotFound as u32)?;
+            (vaults::store_name(username, &vault_id), key, true)
+        },
+    };
+    let store = store::open(&name).map_err(|_| PassKeeperError::FileNotFound as u32)?;
+    Ok(OpenVault { name, store, key, writable })
+}
+
+/// Open a vault the user may write, see `open_vault`
+fn open_vault_for_write(username: &str, vault_id: Option<&str>) -> Result<OpenVault, u32> {
+    let vault = open_vault(username, vault_id)?;
+    if !vault.writable {
+        return Err(PassKeeperError::ReadOnlyVault as u32);
+    }
+    Ok(vault)
+}
+
+/// The user and vault id of a storage name. Team vaults belong to no single user.
+fn split_store_name(name: &str) -> (&str, String) {
+    match vaults::split_store_name(name) {
+        (teams::TEAMS_DIR, team_id) => ("", teams::vault_id(team_id)),
+        (username, vault_id) => (username, vault_id.to_string()),
+    }
+}
+
+/// Drop every cached vault of a user, and the team vaults other users may hold open again
+fn evict_user(cache: &SecretCache, username: &str) {
+    cache.evict_matching(|name| {
+        let owner = vaults::split_store_name(name).0;
+        owner == username || owner == teams::TEAMS_DIR
+    });
+}
+
+fn teams_error_code(error: &TeamsError) -> u32 {
+    match error {
+        TeamsError::NotFound => PassKeeperError::VaultNotFound as u32,
+        TeamsError::NotOwner => PassKeeperError::NotVaultOwner as u32,
+        TeamsError::AlreadyMember => PassKeeperError::DuplicateEntry as u32,
+        TeamsError::OwnerRemoval => PassKeeperError::NotSupported as u32,
+        TeamsError::InvalidName => PassKeeperError::InvalidVaultName as u32,
+        TeamsError::BadSignature => PassKeeperError::InvalidSignature as u32,
+        TeamsError::Rotation => PassKeeperError::FileWriteFailed as u32,
+        TeamsError::Share(e) => share_error_code(e),
+        TeamsError::Io(e) if e.kind() == std::io::ErrorKind::WouldBlock => PassKeeperError::VaultBusy as u32,
+        TeamsError::Io(_) => PassKeeperError::FileWriteFailed as u32,
+    }
+}
+
+fn share_error_code(error: &ShareError) -> u32 {
+    match error {
+        ShareError::NoIdentity => PassKeeperError::DecryptionFailed as u32,
+        ShareError::UnknownRecipient => PassKeeperError::RecipientNotFound as u32,
+        ShareError::BadSignature => PassKeeperError::InvalidSignature as u32,
+        ShareError::Malformed => PassKeeperError::IntegrityCheckFailed as u32,
+        ShareError::Crypto => PassKeeperError::EncryptionFailed as u32,
+        ShareError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => PassKeeperError::FileNotFound as u32,
+        ShareError::Io(e) if e.kind() == std::io::ErrorKind::WouldBlock => PassKeeperError::VaultBusy as u32,
+        ShareError::Io(_) => PassKeeperError::FileWriteFailed as u32,
+    }
+}
+
+fn admin_error_code(error: &AdminError) -> u32 {
+    match error {
+        AdminError::Tampered => PassKeeperError::InvalidSignature as u32,
+        AdminError::NotAdmin => PassKeeperError::NotAdmin as u32,
+        AdminError::AlreadyClaimed => PassKeeperError::FileAlreadyExists as u32,
+        AdminError::LastAdmin => PassKeeperError::NotSupported as u32,
+        AdminError::NoEscrow => PassKeeperError::KeyNotEscrowed as u32,
+        AdminError::Share(e) => share_error_code(e),
+        AdminError::Io(e) if e.kind() == std::io::ErrorKind::WouldBlock => PassKeeperError::VaultBusy as u32,
+        AdminError::Io(_) => PassKeeperError::FileWriteFailed as u32,
+    }
+}
+
+fn folder_error_code(error: &FolderError) -> u32 {
+    match error {
+        FolderError::NotFound => PassKeeperError::FolderNotFound as u32,
+        FolderError::InvalidName => PassKeeperError::InvalidFolder as u32,
+        FolderError::DuplicateName => PassKeeperError::DuplicateEntry as u32,
+        FolderError::Cycle => PassKeeperError::InvalidFolder as u32,
+    }
 }
 
+fn vaults_error_code(error: &VaultsError) -> u32 {
+    match error {
+        VaultsError::NotFound => PassKeeperError::VaultNotFound as u32,
+        VaultsError::DuplicateName => PassKeeperError::DuplicateVaultName as u32,
+        VaultsError::InvalidName => PassKeeperError::InvalidVaultName as u32,
+        VaultsError::DefaultVault => PassKeeperError::NotSupported as u32,
+        VaultsError::Io(_) => PassKeeperError::FileWriteFailed as u32,
+        VaultsError::Crypto => PassKeeperError::EncryptionFailed as u32,
+    }
+}
+
+/// Create an account.
+/// An optional duress password opens a separate decoy vault through the same login,
+/// and with `duress_wipe` that login also wipes the real vault.
+/// When the policy restricts registration, `admin` names the unlocked administrator creating it.
 #[command]
-fn save_account(username: String, password: String) -> u32 {
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
+fn save_account(username: String, password: String, duress_password: Option<String>, duress_wipe: Option<bool>, admin: Option<String>) -> u32 {
     // Check if the directory exists, if not create it
     if fs::create_dir_all("./data").is_err() {
         return PassKeeperError::DirCreationFailed as u32;
     }
-    // Check if the file already exists
-    if Path::new(&file_path).exists() {
+    // Check if the vault already exists, in any backend.
+    // The directories of team vaults and inboxes are taken as well.
+    if store::exists(&username) || [teams::TEAMS_DIR, share::INBOX_DIR].contains(&username.as_str()) {
         return PassKeeperError::FileAlreadyExists as u32;
     }
-    // SHA512 Hash the password before storing (optional)
-    let hashed_password = hash_data(&password);
-    let mut password = hashed_password;
-    password.push_str("=="); // Append '==' to the hashed password
-    // Create the file and write the password as the first line
-    if fs::write(file_path, password).is_err() {
+    // Never create a vault with crypto that failed its self-test
+    if !selftest::is_passed() {
+        return PassKeeperError::CryptoSelfTestFailed as u32;
+    }
+    let duress_password = duress_password.filter(|dp| !dp.is_empty());
+    if duress_password.as_deref() == Some(password.as_str()) {
+        return PassKeeperError::InvalidDuressPassword as u32;
+    }
+    // The administration decides who creates accounts and how strong their passwords are
+    let config = match admin::load() {
+        Ok(config) => config,
+        Err(e) => {
+            return admin_error_code(&e);
+        }
+    };
+    if config.policy.admin_only_registration {
+        let by_admin = admin.as_deref()
+            .is_some_and(|admin| config.is_admin(admin) && session::vault_key(admin).is_some());
+        if !by_admin {
+            return PassKeeperError::NotAdmin as u32;
+        }
+    }
+    let weak = |password: &str| !config.policy.accepts_password(password);
+    if weak(&password) || duress_password.as_deref().is_some_and(weak) {
+        return PassKeeperError::WeakPassword as u32;
+    }
+    // Every slot gets the calibrated KDF parameters and its own salt
+    let kdf_params = new_kdf_params();
+    // Derive the vault key and store only its SHA512 hash
+    let real_kdf = SlotKdf::generate(kdf_params);
+    let vault_key = match derive_slot_key(&username, &password, Some(&real_kdf)) {
+        Ok(key) => key,
+        Err(_) => {
+            return PassKeeperError::EncryptionFailed as u32;
+        }
+    };
+    let real_slot = match make_slot(real_kdf, &make_skey(&vault_key), &vault_key, Payload::new()) {
+        Some(slot) => slot,
+        None => {
+            return PassKeeperError::EncryptionFailed as u32;
+        }
+    };
+    // The other slot is the decoy vault, seeded with a few logins, or filler that looks the same
+    let mut duress_key = None;
+    let mut other = Other::Filler;
+    let other_slot = match duress_password {
+        Some(dp) => {
+            let duress_kdf = SlotKdf::generate(kdf_params);
+            let key = match derive_slot_key(&username, &dp, Some(&duress_kdf)) {
+                Ok(key) => key,
+                Err(_) => {
+                    return PassKeeperError::EncryptionFailed as u32;
+                }
+            };
+            let wipe = duress_wipe.unwrap_or(false);
+            let skey = if wipe { make_wipe_skey(&key) } else { make_skey(&key) };
+            match make_slot(duress_kdf, &skey, &key, decoy::payload(&username)) {
+                Some(slot) => {
+                    duress_key = Some(key);
+                    other = Other::Decoy;
+                    slot
+                },
+                None => {
+                    return PassKeeperError::EncryptionFailed as u32;
+                }
+            }
+        },
+        None => decoy::filler_slot(&username, Some(kdf_params)),
+    };
+    let mut vault_file = VaultFile { slots: vec![other_slot.clone(), other_slot] };
+    vault_file.slots[vault::random_slot()] = real_slot;
+    // Create the vault with both slots in the backend picked for new accounts
+    let created = store::open_kind(settings::load().storage_backend, &username)
+        .and_then(|store| store.write(&vault_file));
+    if created.is_err() {
         return PassKeeperError::FileWriteFailed as u32;
     }
+    // The keys to share entries with other users, for either login.
+    // Created again on the first share if this fails.
+    if let Err(e) = share::create_identities(&username, &vault_key, duress_key.as_ref()) {
+        print_out!("Failed to create the sharing keys for user {}: {:?}", username, e);
+    }
+    // What the other slot holds, for re-keying both slots alike
+    if let Err(e) = companion::create(&username, &vault_key, &other, duress_key.as_ref()) {
+        print_out!("Failed to write the slot records for user {}: {:?}", username, e);
+    }
+    if let Err(e) = admin::escrow(&config, &username, &vault_key, true) {
+        print_out!("Failed to escrow the key of user {}: {:?}", username, e);
+    }
 
     PassKeeperError::Success as u32
 }
@@ -116,81 +466,382 @@ fn shake_window(window: tauri::WebviewWindow) {
 }
 
 #[command]
-fn validate_login(username: String, password: String) -> bool {
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
-    // Check if the file exists
-    if !Path::new(&file_path).exists() {
+fn validate_login(username: String, password: String, cache: State<'_, SecretCache>) -> bool {
+    // Check if the vault exists
+    let store = match store::open(&username) {
+        Ok(store) => store,
+        Err(_) => {
+            return false;
+        }
+    };
+    print_out!("Validating login for user: {}", username);
+    // Refuse to unlock with crypto that failed its self-test
+    if !selftest::is_passed() {
+        print_out!("Crypto self-test failed, refusing to unlock.");
+        return false;
+    }
+    // Disabled accounts stay locked, and so does everyone when the administration was tampered with
+    let config = match admin::load() {
+        Ok(config) => config,
+        Err(e) => {
+            print_out!("Refusing to unlock, the administration cannot be verified: {:?}", e);
+            return false;
+        }
+    };
+    if config.is_disabled(&username) {
+        print_out!("The account {} is disabled.", username);
         return false;
     }
-    print_out!("Validating login for user: {}", username);
 
-    // Read the stored password (first line of the file)
-    let stored_content = fs::read_to_string(&file_path).unwrap_or_default();
-    let mut lines = stored_content.lines();
-    let stored_password = lines.next().unwrap_or_default();
-    //println!("Stored password: [{}]", stored_password);
-    let hashed_password = hash_data(&password);
-    let mut password = hashed_password;
-    password.push_str("=="); // Append '==' to the hashed password
-    //println!("Provided password: [{}]", password);
-    if stored_password != password {
-        // Shake the window to indicate error
-        if let Some(app_handle) = APP_HANDLE.get() {
-            if let Some(window) = app_handle.get_webview_window("main") {
-                shake_window(window);
+    // Read the stored key hashes of both slots
+    let mut vault_file = match store.read() {
+        Ok(vf) => vf,
+        Err(_) => {
+            return false;
+        }
+    };
+    // The keyring never stands in for a typed password, see `resume_session`
+    let settings = settings::load();
+    let keyring_minutes = settings.keyring_cache_minutes;
+    // Derive a key for every slot and compare in both forms,
+    // so the real and the duress login do the same work
+    let mut matched_slot: Option<(usize, VaultKey, bool)> = None;
+    let mut derived_keys: Vec<(Option<SlotKdf>, VaultKey)> = Vec::new();
+    for (index, slot) in vault_file.slots.iter().enumerate() {
+        let slot_key = match derived_keys.iter().find(|(kdf, _)| *kdf == slot.kdf) {
+            Some((_, key)) => key.clone(),
+            None => match derive_slot_key(&username, &password, slot.kdf.as_ref()) {
+                Ok(key) => {
+                    derived_keys.push((slot.kdf.clone(), key.clone()));
+                    key
+                },
+                Err(_) => {
+                    return false;
+                }
+            },
+        };
+        if slot.skey == make_skey(&slot_key) {
+            matched_slot = Some((index, slot_key.clone(), false));
+        }
+        if slot.skey == make_wipe_skey(&slot_key) {
+            matched_slot = Some((index, slot_key, true));
+        }
+    }
+    // Files written before the vault was encrypted keep the plain password on the first line.
+    if matched_slot.is_none() && vault_file.slots[0].skey == format!("{}==", password) {
+        print_out!("Upgrading legacy data file for user: {}", username);
+        if let Some(vault_key) = upgrade_legacy_file(store.as_ref(), &username, &password, &vault_file.slots[0].payload) {
+            if let Ok(vf) = store.read() {
+                let index = vf.find_slot(&make_skey(&vault_key)).unwrap_or_default();
+                vault_file = vf;
+                matched_slot = Some((index, vault_key, false));
+            }
+        }
+    }
+    let (index, mut vault_key, wipe_other) = match matched_slot {
+        Some(matched) => matched,
+        None => {
+            // Shake the window to indicate error
+            if let Some(app_handle) = APP_HANDLE.get() {
+                if let Some(window) = app_handle.get_webview_window("main") {
+                    shake_window(window);
+                }
+            }
+            return false;
+        }
+    };
+    // Re-derive with stronger parameters when the slot falls below the policy floor
+    let kdf_floor = settings.kdf_floor;
+    let below_floor = match &vault_file.slots[index].kdf {
+        Some(kdf) => kdf.params.is_below(&kdf_floor),
+        None => true,
+    };
+    if wipe_other {
+        // Replace the other slots with filler and clear the wipe mark. The filler gets the
+        // parameters the slot has after this login, and the upgrade below writes it if due.
+        let filler_params = match &vault_file.slots[index].kdf {
+            Some(kdf) if !below_floor => kdf.params,
+            _ => new_kdf_params(),
+        };
+        for (other, slot) in vault_file.slots.iter_mut().enumerate() {
+            if other != index {
+                *slot = decoy::filler_slot(&username, Some(filler_params));
             }
         }
+        vault_file.slots[index].skey = make_skey(&vault_key);
+        if !below_floor && store.write(&vault_file).is_err() {
+            print_out!("Failed to wipe the data file for user: {}", username);
+        }
+        // The named vaults of the wiped logins go with them
+        match vaults::remove_foreign(&username, &vault_key) {
+            Ok(ids) => {
+                for id in ids {
+                    if let Ok(store) = store::open(&vaults::store_name(&username, &id)) {
+                        let _ = store.remove();
+                    }
+                }
+            },
+            Err(_) => print_out!("Failed to wipe the vault list for user: {}", username),
+        }
+        if share::remove_foreign(&username, &vault_key).is_err() {
+            print_out!("Failed to wipe the sharing keys for user: {}", username);
+        }
+        if companion::remove_foreign(&username, &vault_key).is_err() {
+            print_out!("Failed to wipe the slot records for user: {}", username);
+        }
+    }
+    if below_floor {
+        print_out!("Upgrading KDF parameters for user: {}", username);
+        match rekey_login(store.as_ref(), &mut vault_file, index, &username, &password, &vault_key) {
+            Some(new_key) => vault_key = new_key,
+            None => {
+                print_out!("Failed to upgrade KDF parameters for user: {}", username);
+                if wipe_other && store.write(&vault_file).is_err() {
+                    print_out!("Failed to wipe the data file for user: {}", username);
+                }
+            },
+        }
+    }
+    // Administrators added since the last login get the key as well. A new key replaces the old one.
+    if let Err(e) = admin::escrow(&config, &username, &vault_key, below_floor) {
+        print_out!("Failed to escrow the key of user {}: {:?}", username, e);
+    }
+    if keyring_minutes > 0 {
+        if let Err(e) = keyring::store(&username, &vault_key, keyring_minutes) {
+            print_out!("Failed to cache the vault key in the keyring: {}", e);
+        }
+    }
+    // Vaults cached for an earlier unlock may belong to another slot
+    evict_user(&cache, &username);
+    session::unlock(&username, vault_key);
+    true
+}
+
+/// Unlock with the vault key cached in the kernel keyring by an earlier login, without a password.
+/// Only available while the keyring cache is turned on.
+#[command]
+fn resume_session(username: String, cache: State<'_, SecretCache>) -> bool {
+    if settings::load().keyring_cache_minutes == 0 {
+        return false;
+    }
+    if !selftest::is_passed() {
+        print_out!("Crypto self-test failed, refusing to unlock.");
+        return false;
+    }
+    match admin::load() {
+        Ok(config) if !config.is_disabled(&username) => {},
+        _ => {
+            return false;
+        }
+    }
+    let Some(vault_key) = keyring::load(&username) else {
+        return false;
+    };
+    let found = store::open(&username).and_then(|store| store.read()).ok()
+        .and_then(|vf| vf.find_slot(&make_skey(&vault_key)));
+    if found.is_none() {
+        // Stale key, e.g. the password was changed
+        keyring::revoke(&username);
         return false;
     }
+    print_out!("Resumed the session from the keyring for user: {}", username);
+    evict_user(&cache, &username);
+    session::unlock(&username, vault_key);
     true
 }
 
 #[command]
-fn save_data(username: String, service: String, account: String, password: String) -> u32 {
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
-    // Check if the file exists
-    if !Path::new(&file_path).exists() {
-        return PassKeeperError::FileNotFound as u32;
+fn set_pin(username: &str, pin: &str) -> u32 {
+    match session::set_pin(username, pin) {
+        Ok(()) => PassKeeperError::Success as u32,
+        Err(PinError::Locked) => PassKeeperError::SessionLocked as u32,
+        Err(PinError::Crypto) => PassKeeperError::EncryptionFailed as u32,
+        Err(_) => PassKeeperError::InvalidPin as u32,
+    }
+}
+
+#[command]
+fn is_pin_set(username: &str) -> bool {
+    session::has_pin(username)
+}
+
+#[command]
+fn unlock_with_pin(username: &str, pin: &str, cache: State<'_, SecretCache>) -> u32 {
+    if !selftest::is_passed() {
+        return PassKeeperError::CryptoSelfTestFailed as u32;
+    }
+    let vault_key = match session::unlock_with_pin(username, pin) {
+        Ok(key) => key,
+        Err(PinError::Invalid) => return PassKeeperError::InvalidPin as u32,
+        Err(PinError::Discarded) => return PassKeeperError::PinDiscarded as u32,
+        Err(PinError::Crypto) => return PassKeeperError::DecryptionFailed as u32,
+        Err(_) => return PassKeeperError::PinNotSet as u32,
+    };
+    let refused = match admin::load() {
+        Ok(config) if config.is_disabled(username) => Some(PassKeeperError::AccountDisabled as u32),
+        Ok(_) => None,
+        Err(e) => Some(admin_error_code(&e)),
+    };
+    if let Some(code) = refused {
+        session::logout(username);
+        return code;
+    }
+    // The master password may have been changed since the PIN was set
+    let slot = store::open(username).and_then(|store| store.read()).ok()
+        .and_then(|vf| vf.find_slot(&make_skey(&vault_key)));
+    if slot.is_none() {
+        session::logout(username);
+        return PassKeeperError::PinNotSet as u32;
+    }
+    evict_user(&cache, username);
+    PassKeeperError::Success as u32
+}
+
+#[command]
+fn lock_vault(username: &str, cache: State<'_, SecretCache>) -> u32 {
+    session::lock(username);
+    keyring::revoke(username);
+    // The decrypted vaults must not outlive the unlock
+    evict_user(&cache, username);
+    PassKeeperError::Success as u32
+}
+
+#[command]
+fn logout(username: &str, cache: State<'_, SecretCache>) -> u32 {
+    session::logout(username);
+    keyring::revoke(username);
+    evict_user(&cache, username);
+    PassKeeperError::Success as u32
+}
+
+/// Results of the crypto self-test run at startup
+#[command]
+fn crypto_self_test() -> String {
+    to_string(selftest::report()).unwrap_or_default()
+}
+
+/// Re-run the KDF calibration for a target unlock time.
+/// Only new vaults and vaults upgraded on login use the new parameters.
+#[command]
+fn calibrate_kdf(username: &str, target_ms: u32) -> String {
+    if settings_session(username).is_err() {
+        return String::new();
+    }
+    let mut settings = settings::load();
+    settings.kdf_target_ms = target_ms;
+    let per_slot_ms = target_ms as u64 / vault::SLOT_COUNT as u64;
+    let params = calibrate(per_slot_ms, &settings.kdf_floor);
+    settings.kdf_calibrated = Some(params);
+    if settings::save(&settings).is_err() {
+        return String::new();
+    }
+    to_string(&params).unwrap_or_default()
+}
+
+/// Set the KDF policy floor. Vaults below it are upgraded on the next login.
+#[command]
+fn set_kdf_floor(username: &str, memory_kib: u32, iterations: u32, parallelism: u32) -> u32 {
+    if let Err(code) = settings_session(username) {
+        return code;
+    }
+    let floor = KdfParams { memory_kib, iterations, parallelism };
+    if !floor.is_valid() {
+        return PassKeeperError::InvalidKdfParams as u32;
+    }
+    let mut settings = settings::load();
+    settings.kdf_floor = floor;
+    if settings::save(&settings).is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    PassKeeperError::Success as u32
+}
+
+#[command]
+fn get_settings() -> String {
+    to_string(&settings::load()).unwrap_or_default()
+}
+
+/// Sets how long the vault stays unlocked across app restarts. `0` turns it off.
+#[command]
+fn set_keyring_cache(username: &str, minutes: u32) -> u32 {
+    if let Err(code) = settings_session(username) {
+        return code;
+    }
+    if minutes > 0 && !keyring::is_supported() {
+        return PassKeeperError::NotSupported as u32;
+    }
+    let mut settings = settings::load();
+    settings.keyring_cache_minutes = minutes;
+    if settings::save(&settings).is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    PassKeeperError::Success as u32
+}
+
+/// Sets how many previous passwords are kept per entry. `0` keeps none.
+/// Longer histories are shortened the next time their entry changes.
+#[command]
+fn set_password_history_size(username: &str, size: u32) -> u32 {
+    if let Err(code) = settings_session(username) {
+        return code;
+    }
+    if size > MAX_HISTORY {
+        return PassKeeperError::NotSupported as u32;
+    }
+    let mut settings = settings::load();
+    settings.password_history_size = size;
+    if settings::save(&settings).is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
     }
+    PassKeeperError::Success as u32
+}
 
+/// Add an entry. `fields` holds its URLs, notes, tags and custom fields, and `folder` the folder it goes in.
+#[command]
+#[allow(clippy::too_many_arguments)]
+fn save_data(username: String, service: String, account: String, password: String, fields: Option<EntryFields>, folder: Option<String>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(&username, "save_data");
+    let fields = match fields.unwrap_or_default().normalize() {
+        Ok(fields) => fields,
+        Err(e) => {
+            print_out!("Invalid entry fields: {:?}", e);
+            return PassKeeperError::InvalidField as u32;
+        }
+    };
+    // Check if the vault exists
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault_for_write(&username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
     // Retrieve existing secret data
-    let secure_data = get_secret_data(username.clone());
-    print_out!("Secure data retrieved: [{:?}]", secure_data);
+    let secure_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
     
     if secure_data.is_skey_empty() {
         return PassKeeperError::FileNotFound as u32;
     }
+    if let Err(code) = secure_data.check_integrity(&name) {
+        return code;
+    }
+    let folder = folder.unwrap_or_else(|| ROOT_FOLDER.to_string());
+    if !folders::exists(&secure_data.payload.entries, &folder) {
+        return PassKeeperError::FolderNotFound as u32;
+    }
     if secure_data.is_data_empty() {
         print_out!("No existing data, creating new entry.");
         // No existing data, create new entry
-        let new_entry = json!({
+        let mut new_entry = json!({
+            "id": new_entry_id(),
             "service": service,
             "account": account,
             "password": password,
         });
-        let mut new_content = String::new();
-        new_content.push_str(&secure_data.skey);
-        new_content.push('\n');
-        // Following lines are the json entries
-        let entry_str = to_string(&vec![new_entry]).unwrap_or_default();
-        print_out!("New entry string: [{}]", entry_str);
-        let encrypted_entry_str = encrypt_data(&entry_str);
-        print_out!("Encrypted entry result: [{:?}]", encrypted_entry_str);
-        match encrypted_entry_str {
-            Ok(ed) => {
-                let push_data = general_purpose::STANDARD.encode(&ed);
-                new_content.push_str(&push_data);
-            },
-            Err(_) => {
-                return PassKeeperError::EncryptionFailed as u32;
-            }
-        }
-        new_content.push('\n');
-
-        // Write back to the file
-        if fs::write(file_path, new_content).is_err() {
-            return PassKeeperError::FileWriteFailed as u32;
-        }
+        fields.apply(&mut new_entry);
+        folders::set_parent(&mut new_entry, &folder);
+        timestamps::stamp_new(&mut new_entry);
+        // Encrypt and write it to the vault
+        save_entry(&cache, &name, store.as_ref(), &secure_data, &new_entry, &vault_key)
     } else {
         print_out!("Existing data found, checking for duplicates.");
         // Parse existing data
@@ -199,7 +850,7 @@ fn save_data(username: String, service: String, account: String, password: Strin
             .filter(|v| !v.is_null())
             .collect();
         // Flatten arrays if any    
-        let mut new_data: Vec<Value> = data.into_iter().flat_map(|v| {
+        let new_data: Vec<Value> = data.into_iter().flat_map(|v| {
             if let Value::Array(arr) = v {
                 arr.into_iter()
             } else {
@@ -207,64 +858,60 @@ fn save_data(username: String, service: String, account: String, password: Strin
             }
         }).collect();
         // Check for duplicates, service & account combination
-        if new_data.is_empty() {
-            new_data = Vec::new();
-        } else {
-            for entry in &new_data {
-                if entry["service"] == service && entry["account"] == account {
-                    // Duplicate found
-                    return PassKeeperError::DuplicateEntry as u32;
-                }
+        for entry in &new_data {
+            if entry["service"] == service && entry["account"] == account && !trash::is_trashed(entry) {
+                // Duplicate found
+                return PassKeeperError::DuplicateEntry as u32;
             }
         }
 
         // Append new data
-        let new_entry = json!({
+        let mut new_entry = json!({
+            "id": new_entry_id(),
             "service": service,
             "account": account,
             "password": password,
         });
-        new_data.push(new_entry);
-        
-        // Convert back to string format
-        let mut new_content = String::new();
-        // The password
-        new_content.push_str(&secure_data.skey);
-        new_content.push('\n');
-        // Following lines are the json entries
-        let entry_str = to_string(&new_data).unwrap_or_default();
-        let encrypted_entry_str = encrypt_data(&entry_str);
-        match encrypted_entry_str {
-            Ok(ed) => {
-                let push_data = general_purpose::STANDARD.encode(&ed);
-                new_content.push_str(&push_data);
-            },
-            Err(_) => {
-                return PassKeeperError::FileWriteFailed as u32;
-            }
-        }
-        new_content.push('\n');
+        fields.apply(&mut new_entry);
+        folders::set_parent(&mut new_entry, &folder);
+        timestamps::stamp_new(&mut new_entry);
 
-        // Write back to the file
-        if fs::write(file_path, new_content).is_err() {
-            return PassKeeperError::FileWriteFailed as u32;
-        }
+        // Encrypt and write it to the vault, the other entries stay as they are
+        save_entry(&cache, &name, store.as_ref(), &secure_data, &new_entry, &vault_key)
     }
-    PassKeeperError::Success as u32
 }
 
+/// Change the password of the entry of `service` and `account`, and its other fields when `fields` is given.
+/// `new_service` and `new_account` rename it, unless another entry has those already or they are empty.
 #[command]
-fn modify_data(username: &str, service: &str, account: &str, password: &str) -> u32 {
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
-    // Check if the file exists
-    if !Path::new(&file_path).exists() {
-        return PassKeeperError::FileNotFound as u32;
+#[allow(clippy::too_many_arguments)]
+fn modify_data(username: &str, service: &str, account: &str, password: &str, fields: Option<EntryFields>, vault_id: Option<String>, new_service: Option<String>, new_account: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "modify_data");
+    let fields = match fields.map(EntryFields::normalize).transpose() {
+        Ok(fields) => fields,
+        Err(e) => {
+            print_out!("Invalid entry fields: {:?}", e);
+            return PassKeeperError::InvalidField as u32;
+        }
+    };
+    if [&new_service, &new_account].into_iter().flatten().any(|name| name.trim().is_empty()) {
+        print_out!("Refusing to rename an entry to an empty name");
+        return PassKeeperError::InvalidField as u32;
     }
-
-    let secret_data = get_secret_data(username.to_string());
+    // Check if the vault exists
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault_for_write(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
     if secret_data.is_skey_empty() || secret_data.is_data_empty() {
         return PassKeeperError::FileNotFound as u32;
     }
+    if let Err(code) = secret_data.check_integrity(&name) {
+        return code;
+    }
     // Parse existing data
     let data = secret_data.data.lines()
         .map(|line| from_str(line).unwrap_or(Value::Null))
@@ -278,46 +925,63 @@ fn modify_data(username: &str, service: &str, account: &str, password: &str) ->
             vec![v].into_iter()
         }
     ).collect();
-    // Modify the matching entry
-    for entry in &mut new_data {
-        if entry["service"] == service && entry["account"] == account {
-            entry["password"] = Value::String(password.to_string());
+    // A renamed entry must not take the names of another one
+    let new_service = new_service.unwrap_or_else(|| service.to_string());
+    let new_account = new_account.unwrap_or_else(|| account.to_string());
+    if new_service != service || new_account != account {
+        let count = |service: &str, account: &str| new_data.iter()
+            .filter(|entry| entry["service"] == service && entry["account"] == account && !trash::is_trashed(entry))
+            .count();
+        if count(&new_service, &new_account) > 0 || count(service, account) > 1 {
+            return PassKeeperError::DuplicateEntry as u32;
         }
     }
-    // Convert back to string format
-    let mut new_content = String::new();
-    // The password
-    new_content.push_str(&secret_data.skey);
-    new_content.push('\n');
-    // Following lines are the json entries
-    let entry_str = to_string(&new_data).unwrap_or_default();
-    let encrypted_entry_str = encrypt_data(&entry_str);
-    match encrypted_entry_str {
-        Ok(ed) => {
-            let push_data = general_purpose::STANDARD.encode(&ed);
-            new_content.push_str(&push_data);
-        },
-        Err(_) => {
-            return PassKeeperError::EncryptionFailed as u32;
+    // Modify the matching entry, keeping the secrets and names it replaces in its history
+    let history_size = settings::load().password_history_size.min(MAX_HISTORY) as usize;
+    let mut modified = false;
+    for entry in &mut new_data {
+        if entry["service"] == service && entry["account"] == account && !trash::is_trashed(entry) {
+            let before = entry.clone();
+            entry["service"] = Value::String(new_service.clone());
+            entry["account"] = Value::String(new_account.clone());
+            entry["password"] = Value::String(password.to_string());
+            if let Some(fields) = &fields {
+                fields.apply(entry);
+            }
+            history::record(&before, entry, history_size);
+            timestamps::stamp_modified(&before, entry);
+            // Encrypt and write it to the vault
+            let result = save_entry(&cache, &name, store.as_ref(), &secret_data, entry, &vault_key);
+            if result != PassKeeperError::Success as u32 {
+                return result;
+            }
+            modified = true;
         }
     }
-    new_content.push('\n');
-    // Write back to the file
-    if fs::write(file_path, new_content).is_err() {
-        return PassKeeperError::FileWriteFailed as u32;
+    if !modified {
+        return PassKeeperError::FileNotFound as u32;
     }
     PassKeeperError::Success as u32
 }
 
+/// Move the entries of a service to the trash
 #[command]
-fn remove_data(username: &str, service: &str) -> u32 {
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
-    // Check if the file exists
-    if !Path::new(&file_path).exists() {
+fn remove_data(username: &str, service: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "remove_data");
+    // Check if the vault exists
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault_for_write(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
         return PassKeeperError::FileNotFound as u32;
     }
-
-    let secret_data = get_secret_data(username.to_string());
+    if let Err(code) = secret_data.check_integrity(&name) {
+        return code;
+    }
     let data = secret_data.data.lines()
         .map(|line| from_str(line).unwrap_or(Value::Null))
         .filter(|v| !v.is_null())
@@ -330,84 +994,1741 @@ fn remove_data(username: &str, service: &str) -> u32 {
             vec![v].into_iter()
         }
     ).collect();
-    // Remove entries matching the service
-    new_data.retain(|entry| entry["service"] != service);
-    // Convert back to string format
-    let mut new_content = String::new();
-    // The password
-    new_content.push_str(&secret_data.skey);
-    new_content.push('\n');
-    // Following lines are the json entries
-    let entry_str = to_string(&new_data).unwrap_or_default();
-    let encrypted_entry_str = encrypt_data(&entry_str);
-    match encrypted_entry_str {
-        Ok(ed) => {
-            let push_data = general_purpose::STANDARD.encode(&ed);
-            new_content.push_str(&push_data);
-        },
-        Err(_) => {
-            return PassKeeperError::EncryptionFailed as u32;
-        }
-    }
-    new_content.push('\n');
-    // Write back to the file
-    if fs::write(file_path, new_content).is_err() {
-        return PassKeeperError::FileWriteFailed as u32;
-    }
-
-    PassKeeperError::Success as u32
+    // Trash entries matching the service
+    new_data.retain(|entry| entry["service"] == service && !folders::is_folder(entry) && !trash::is_trashed(entry));
+    new_data.iter_mut().for_each(trash::move_to_trash);
+    apply_entries(&cache, &name, store.as_ref(), &secret_data, &new_data, &[], &vault_key)
 }
 
+/// The entries of a vault as a json array: all of them, or those directly in `folder`.
+/// With `tree` the folder, or the whole vault, is returned as a tree of `{id, name, folders, entries}`.
+/// Entries are in the order they were added unless sorted by `sort`, reversed with `descending`.
 #[command]
-fn get_data(username: &str) -> String {
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
-    // Check if the file exists
-    if !Path::new(&file_path).exists() {
+#[allow(clippy::too_many_arguments)]
+fn get_data(username: &str, vault_id: Option<String>, folder: Option<String>, tree: Option<bool>, sort: Option<SortKey>, descending: Option<bool>, cache: State<'_, SecretCache>) -> String {
+    // Check if the vault exists
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() || secret_data.is_data_empty() {
         return String::new();
     }
-
-    let secret_data = get_secret_data(username.to_string());
-    if secret_data.is_skey_empty() || secret_data.is_data_empty() {
+    if secret_data.check_integrity(&name).is_err() {
         return String::new();
     }
+    // Entries in the trash for too long are purged when the vault is listed
+    if !trash::expired(&secret_data.payload.entries, settings::load().trash_retention_days).is_empty() {
+        purge_expired_trash(username, vault_id.as_deref(), &cache);
+    }
     let data = secret_data.data.lines()
         .map(|line| from_str(line).unwrap_or(Value::Null))
         .filter(|v| !v.is_null())
         .collect::<Vec<Value>>();
     // Flatten arrays if any    
-    let new_data: Vec<Value> = data.into_iter().flat_map(|v|
+    let mut new_data: Vec<Value> = data.into_iter().flat_map(|v|
         if let Value::Array(arr) = v {
             arr.into_iter()
         } else {
             vec![v].into_iter()
         }
-    ).collect();
-    // Convert back to string format
-    let entry_str = to_string(&new_data).unwrap_or_default();
-    entry_str
+    ).collect();
+    // Previous passwords are only read through get_password_history
+    new_data.iter_mut().for_each(history::strip);
+    if let Some(sort) = sort {
+        timestamps::sort(&mut new_data, sort, descending.unwrap_or(false));
+    }
+    if tree.unwrap_or(false) {
+        return folders::tree(&new_data, folder.as_deref().unwrap_or(ROOT_FOLDER))
+            .map(|node| to_string(&node).unwrap_or_default())
+            .unwrap_or_default();
+    }
+    let new_data: Vec<Value> = match folder {
+        Some(folder) => folders::contents(&new_data, &folder),
+        None => folders::entries(&new_data).cloned().collect(),
+    };
+    // Convert back to string format
+    let entry_str = to_string(&new_data).unwrap_or_default();
+    entry_str
+}
+
+/// The entries matching `query` and `filters`, best first, as a json array of `{entry, score, highlights}`.
+/// `highlights` lists the values the query was found in as `{field, value, ranges}`. Passwords are never searched.
+#[command]
+fn search_entries(username: &str, query: &str, filters: Option<SearchFilters>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() || secret_data.check_integrity(&name).is_err() {
+        return String::new();
+    }
+    let mut hits = search::search(&secret_data.payload.entries, query, &filters.unwrap_or_default());
+    hits.iter_mut().for_each(|hit| history::strip(&mut hit.entry));
+    to_string(&hits).unwrap_or_default()
+}
+
+/// The previous passwords, secrets and names of an entry as a json array of `{field, value, changed}`, newest first.
+#[command]
+fn get_password_history(username: &str, entry_id: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() || secret_data.check_integrity(&name).is_err() {
+        return String::new();
+    }
+    let items = folders::entries(&secret_data.payload.entries)
+        .find(|entry| vault::entry_id(entry) == entry_id)
+        .map(history::of);
+    items.map(|items| to_string(&items).unwrap_or_default()).unwrap_or_default()
+}
+
+/// Record that the password of an entry was revealed or copied
+#[command]
+fn record_access(username: &str, entry_id: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let (OpenVault { name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let mut entry = match folders::entries(&secret_data.payload.entries).find(|entry| vault::entry_id(entry) == entry_id) {
+        Some(entry) => entry.clone(),
+        None => {
+            return PassKeeperError::FileNotFound as u32;
+        }
+    };
+    timestamps::stamp_accessed(&mut entry);
+    save_entry(&cache, &name, store.as_ref(), &secret_data, &entry, &vault_key)
+}
+
+/// Put the value at `index` of the history of an entry back. The current value goes into the history.
+#[command]
+fn restore_password(username: &str, entry_id: &str, index: usize, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "restore_password");
+    let (OpenVault { name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let mut entry = match folders::entries(&secret_data.payload.entries).find(|entry| vault::entry_id(entry) == entry_id) {
+        Some(entry) => entry.clone(),
+        None => {
+            return PassKeeperError::FileNotFound as u32;
+        }
+    };
+    let history_size = settings::load().password_history_size.min(MAX_HISTORY) as usize;
+    let before = entry.clone();
+    if let Err(HistoryError::NotFound) = history::restore(&mut entry, index, history_size) {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    timestamps::stamp_modified(&before, &mut entry);
+    save_entry(&cache, &name, store.as_ref(), &secret_data, &entry, &vault_key)
+}
+
+/// The templates of the entry types as a json array of `{type, account, password, fields, summary, secret}`.
+#[command]
+fn get_entry_templates() -> String {
+    to_string(&types::templates()).unwrap_or_default()
+}
+
+/// An unlocked vault the user may change, with its checked secret data
+fn load_writable(username: &str, vault_id: Option<&str>, cache: &SecretCache) -> Result<(OpenVault, SecretData), u32> {
+    let vault = open_vault_for_write(username, vault_id)?;
+    let secret_data = load_secret_data(cache, &vault.name, vault.store.as_ref(), &vault.key);
+    if secret_data.is_skey_empty() {
+        return Err(PassKeeperError::FileNotFound as u32);
+    }
+    secret_data.check_integrity(&vault.name)?;
+    Ok((vault, secret_data))
+}
+
+/// Create a folder in `parent`, or at the top of the vault. Returns `{code, id}`.
+#[command]
+fn create_folder(username: &str, name: &str, parent: Option<String>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let _undo = undo::begin(username, "create_folder");
+    let result = |code: u32, id: &str| json!({ "code": code, "id": id }).to_string();
+    let (OpenVault { name: store_name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return result(code, "");
+        }
+    };
+    let records = &secret_data.payload.entries;
+    let parent = parent.unwrap_or_else(|| ROOT_FOLDER.to_string());
+    if !folders::exists(records, &parent) {
+        return result(PassKeeperError::FolderNotFound as u32, "");
+    }
+    let folder = match folders::check_name(records, name, &parent, None) {
+        Ok(name) => folders::new_folder(&name, &parent),
+        Err(e) => {
+            return result(folder_error_code(&e), "");
+        }
+    };
+    let code = save_entry(&cache, &store_name, store.as_ref(), &secret_data, &folder, &vault_key);
+    result(code, if code == PassKeeperError::Success as u32 { vault::entry_id(&folder) } else { "" })
+}
+
+#[command]
+fn rename_folder(username: &str, folder_id: &str, name: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "rename_folder");
+    let (OpenVault { name: store_name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let records = &secret_data.payload.entries;
+    let Some(folder) = folders::folders(records).find(|folder| vault::entry_id(folder) == folder_id) else {
+        return PassKeeperError::FolderNotFound as u32;
+    };
+    let mut folder = folder.clone();
+    match folders::check_name(records, name, folders::parent_of(&folder), Some(folder_id)) {
+        Ok(name) => folder["name"] = Value::String(name),
+        Err(e) => {
+            return folder_error_code(&e);
+        }
+    }
+    save_entry(&cache, &store_name, store.as_ref(), &secret_data, &folder, &vault_key)
+}
+
+/// Move a folder with everything in it into `parent`, or to the top of the vault
+#[command]
+fn move_folder(username: &str, folder_id: &str, parent: Option<String>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "move_folder");
+    let (OpenVault { name: store_name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let records = &secret_data.payload.entries;
+    let parent = parent.unwrap_or_else(|| ROOT_FOLDER.to_string());
+    if let Err(e) = folders::check_move(records, folder_id, &parent) {
+        return folder_error_code(&e);
+    }
+    let Some(folder) = folders::folders(records).find(|folder| vault::entry_id(folder) == folder_id) else {
+        return PassKeeperError::FolderNotFound as u32;
+    };
+    let name = folder["name"].as_str().unwrap_or_default();
+    if let Err(e) = folders::check_name(records, name, &parent, Some(folder_id)) {
+        return folder_error_code(&e);
+    }
+    let mut folder = folder.clone();
+    folders::set_parent(&mut folder, &parent);
+    save_entry(&cache, &store_name, store.as_ref(), &secret_data, &folder, &vault_key)
+}
+
+/// Move entries into `folder`, or to the top of the vault
+#[command]
+fn move_entries(username: &str, entry_ids: Vec<String>, folder: Option<String>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "move_entries");
+    let (OpenVault { name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let records = &secret_data.payload.entries;
+    let folder = folder.unwrap_or_else(|| ROOT_FOLDER.to_string());
+    if !folders::exists(records, &folder) {
+        return PassKeeperError::FolderNotFound as u32;
+    }
+    let mut moving: Vec<Value> = folders::entries(records)
+        .filter(|entry| entry_ids.iter().any(|id| id == vault::entry_id(entry)))
+        .cloned()
+        .collect();
+    if moving.len() != entry_ids.len() {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    for entry in &mut moving {
+        folders::set_parent(entry, &folder);
+    }
+    apply_entries(&cache, &name, store.as_ref(), &secret_data, &moving, &[], &vault_key)
+}
+
+/// Delete a folder. With `keep_contents` its entries and subfolders move up into its parent,
+/// otherwise they are deleted with it.
+#[command]
+fn delete_folder(username: &str, folder_id: &str, keep_contents: bool, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "delete_folder");
+    let (OpenVault { name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let records = &secret_data.payload.entries;
+    let Some(folder) = folders::folders(records).find(|folder| vault::entry_id(folder) == folder_id) else {
+        return PassKeeperError::FolderNotFound as u32;
+    };
+    let parent = folders::parent_of(folder).to_string();
+    if keep_contents {
+        let mut moved: Vec<Value> = Vec::new();
+        for record in records.iter().filter(|record| folders::parent_of(record) == folder_id) {
+            let mut record = record.clone();
+            if folders::is_folder(&record) {
+                // A subfolder keeps its name unless the parent has one like it
+                let taken: Vec<Value> = records.iter().chain(moved.iter()).cloned().collect();
+                let name = record["name"].as_str().unwrap_or_default().to_string();
+                record["name"] = Value::String(folders::unique_name(&taken, &name, &parent, Some(vault::entry_id(&record))));
+            }
+            folders::set_parent(&mut record, &parent);
+            moved.push(record);
+        }
+        return apply_entries(&cache, &name, store.as_ref(), &secret_data, &moved, &[folder_id.to_string()], &vault_key);
+    }
+    // The entries go to the trash and the folders are deleted in the same write
+    let subtree = folders::subtree(records, folder_id);
+    let (doomed_folders, doomed_entries): (Vec<&Value>, Vec<&Value>) = records.iter()
+        .filter(|record| {
+            let id = vault::entry_id(record);
+            subtree.iter().any(|folder| folder == id || folder == folders::parent_of(record))
+        })
+        .partition(|record| folders::is_folder(record));
+    let trashed: Vec<Value> = doomed_entries.into_iter()
+        .filter(|entry| !trash::is_trashed(entry))
+        .map(|entry| {
+            let mut entry = entry.clone();
+            trash::move_to_trash(&mut entry);
+            entry
+        })
+        .collect();
+    let deleted: Vec<String> = doomed_folders.into_iter()
+        .map(|folder| vault::entry_id(folder).to_string())
+        .collect();
+    apply_entries(&cache, &name, store.as_ref(), &secret_data, &trashed, &deleted, &vault_key)
+}
+
+/// The entries in the trash as a json array, most recently deleted first. `deleted` is when.
+#[command]
+fn list_trash(username: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() || secret_data.check_integrity(&name).is_err() {
+        return String::new();
+    }
+    let mut entries = trash::trashed(&secret_data.payload.entries);
+    entries.iter_mut().for_each(history::strip);
+    to_string(&entries).unwrap_or_default()
+}
+
+/// Take entries out of the trash. An entry is not restored over one with the same service and account.
+#[command]
+fn restore_trash(username: &str, entry_ids: Vec<String>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let _undo = undo::begin(username, "restore_trash");
+    let (OpenVault { name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let records = &secret_data.payload.entries;
+    let restoring: Vec<Value> = trash::trashed(records).into_iter()
+        .filter(|entry| entry_ids.iter().any(|id| id == vault::entry_id(entry)))
+        .collect();
+    if restoring.len() != entry_ids.len() {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    // Nothing is restored when one of them would be a duplicate
+    for (index, entry) in restoring.iter().enumerate() {
+        let duplicate = folders::entries(records).chain(restoring[..index].iter())
+            .any(|e| e["service"] == entry["service"] && e["account"] == entry["account"]);
+        if duplicate {
+            return PassKeeperError::DuplicateEntry as u32;
+        }
+    }
+    let restored: Vec<Value> = restoring.into_iter()
+        .map(|mut entry| {
+            trash::restore(records, &mut entry);
+            entry
+        })
+        .collect();
+    apply_entries(&cache, &name, store.as_ref(), &secret_data, &restored, &[], &vault_key)
+}
+
+/// Delete entries in the trash for good, or empty the trash without `entry_ids`.
+/// Not recorded for undo: taking it back would mean keeping what the user wanted gone.
+#[command]
+fn purge_trash(username: &str, entry_ids: Option<Vec<String>>, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let (OpenVault { name, store, key: vault_key, .. }, secret_data) = match load_writable(username, vault_id.as_deref(), &cache) {
+        Ok(loaded) => loaded,
+        Err(code) => {
+            return code;
+        }
+    };
+    let trashed: Vec<String> = trash::trashed(&secret_data.payload.entries).iter()
+        .map(|entry| vault::entry_id(entry).to_string())
+        .collect();
+    let purging = entry_ids.unwrap_or_else(|| trashed.clone());
+    if purging.iter().any(|id| !trashed.contains(id)) {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    apply_entries(&cache, &name, store.as_ref(), &secret_data, &[], &purging, &vault_key)
+}
+
+/// Delete the entries kept in the trash for longer than the retention setting.
+/// Members who cannot change the vault leave it to those who can.
+fn purge_expired_trash(username: &str, vault_id: Option<&str>, cache: &SecretCache) {
+    let Ok((OpenVault { name, store, key: vault_key, .. }, secret_data)) = load_writable(username, vault_id, cache) else {
+        return;
+    };
+    let expired = trash::expired(&secret_data.payload.entries, settings::load().trash_retention_days);
+    let code = apply_entries(cache, &name, store.as_ref(), &secret_data, &[], &expired, &vault_key);
+    if code != PassKeeperError::Success as u32 {
+        print_out!("Failed to purge {} entries from the trash of {}: {}", expired.len(), name, code);
+    }
+}
+
+/// Sets how many days deleted entries stay in the trash. `0` keeps them until it is emptied.
+#[command]
+fn set_trash_retention(username: &str, days: u32) -> u32 {
+    if let Err(code) = settings_session(username) {
+        return code;
+    }
+    let mut settings = settings::load();
+    settings.trash_retention_days = days;
+    if settings::save(&settings).is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    PassKeeperError::Success as u32
+}
+
+/// Undo the last change of the user to its vaults
+#[command]
+fn undo(username: &str, cache: State<'_, SecretCache>) -> u32 {
+    replay(username, false, &cache)
+}
+
+/// Make the last undone change again. A new change drops what was undone.
+#[command]
+fn redo(username: &str, cache: State<'_, SecretCache>) -> u32 {
+    replay(username, true, &cache)
+}
+
+/// Take the last operation of the undo journal, or of the redo journal with `redo`,
+/// write it and put it on the other journal. An operation that cannot be written stays where it was.
+fn replay(username: &str, redo: bool, cache: &SecretCache) -> u32 {
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    let Some(operation) = undo::pop(username, &login_key, redo) else {
+        return PassKeeperError::NothingToUndo as u32;
+    };
+    // Undoing writes the records as they were before the operation, redoing as they were after it
+    let applying = if redo { operation.clone() } else { operation.inverse() };
+    let code = apply_operation(username, &login_key, &applying, cache);
+    let to_redo = if code == PassKeeperError::Success as u32 { !redo } else { redo };
+    undo::push(username, &login_key, &operation, to_redo);
+    code
+}
+
+/// Returns `true` if a record is as a change expects it. When it was last used does not count.
+fn same_record(current: Option<&Value>, expected: Option<&Value>) -> bool {
+    let without_access = |record: &Value| {
+        let mut record = record.clone();
+        if let Some(object) = record.as_object_mut() {
+            object.remove(timestamps::ACCESSED);
+        }
+        record
+    };
+    current.map(without_access) == expected.map(without_access)
+}
+
+/// A vault an undo or redo wrote: its id, the vault, what it held before and what it holds now,
+/// and how many entries were added, updated and removed
+type WrittenVault<'a> = (&'a str, OpenVault, SecretData, Payload, (usize, usize, usize));
+
+/// Write the changes of an operation, each taking its record from `before` to `after`.
+/// Every vault is checked before one is written, and the records of a vault are written at once.
+/// When a write or rename fails, the vaults written and renamed before it are put back.
+fn apply_operation(username: &str, login_key: &VaultKey, operation: &Operation, cache: &SecretCache) -> u32 {
+    let mut vault_ids: Vec<&str> = Vec::new();
+    for change in &operation.changes {
+        if let Change::Record { vault_id, .. } = change {
+            if !vault_ids.contains(&vault_id.as_str()) {
+                vault_ids.push(vault_id);
+            }
+        }
+    }
+    let mut writes = Vec::new();
+    for vault_id in vault_ids {
+        let (vault, secret_data) = match load_writable(username, Some(vault_id), cache) {
+            Ok(loaded) => loaded,
+            Err(code) => {
+                return code;
+            }
+        };
+        let mut entries = secret_data.payload.entries.clone();
+        let mut counts = (0, 0, 0);
+        for change in &operation.changes {
+            let Change::Record { vault_id: change_vault, id, before, after } = change else {
+                continue;
+            };
+            if change_vault != vault_id {
+                continue;
+            }
+            let position = entries.iter().position(|entry| vault::entry_id(entry) == id);
+            if !same_record(position.map(|index| &entries[index]), before.as_ref()) {
+                print_out!("Cannot {} in {}: {} changed since", operation.label, vault.name, id);
+                return PassKeeperError::UndoConflict as u32;
+            }
+            match (position, after) {
+                (Some(index), Some(after)) => {
+                    let mut after = after.clone();
+                    // The record keeps when it was last used
+                    if let (Some(accessed), Some(object)) = (entries[index].get(timestamps::ACCESSED).cloned(), after.as_object_mut()) {
+                        object.insert(timestamps::ACCESSED.to_string(), accessed);
+                    }
+                    entries[index] = after;
+                    counts.1 += 1;
+                },
+                (None, Some(after)) => {
+                    entries.push(after.clone());
+                    counts.0 += 1;
+                },
+                (Some(index), None) => {
+                    entries.remove(index);
+                    counts.2 += 1;
+                },
+                (None, None) => {},
+            }
+        }
+        writes.push((vault_id, vault, secret_data, entries, counts));
+    }
+    let mut renames = Vec::new();
+    for change in &operation.changes {
+        if let Change::VaultName { vault_id, before, after } = change {
+            if vault_name(username, login_key, vault_id).as_ref() != Some(before) {
+                return PassKeeperError::UndoConflict as u32;
+            }
+            renames.push((vault_id, before, after));
+        }
+    }
+    // Every check passed. A failed write puts back what the operation wrote before it.
+    let mut written: Vec<WrittenVault> = Vec::new();
+    for (vault_id, vault, secret_data, entries, counts) in writes {
+        let mut payload = secret_data.payload.next(entries);
+        payload.generation = next_generation(&secret_data.payload);
+        if let Err(e) = vault.store.replace(secret_data.slot, &vault.key, &payload) {
+            print_out!("Failed to {} in {}: {}", operation.label, vault.name, e);
+            roll_back_writes(&written);
+            return store_error_code(&e);
+        }
+        record_write(&secret_data, payload.generation);
+        written.push((vault_id, vault, secret_data, payload, counts));
+    }
+    let mut renamed: Vec<(&str, &String)> = Vec::new();
+    for (vault_id, before, after) in renames {
+        let code = set_vault_name(username, login_key, vault_id, after);
+        if code != PassKeeperError::Success as u32 {
+            for (vault_id, before) in renamed.into_iter().rev() {
+                let _ = set_vault_name(username, login_key, vault_id, before);
+            }
+            roll_back_writes(&written);
+            return code;
+        }
+        renamed.push((vault_id, before));
+    }
+    for (vault_id, OpenVault { name, store, .. }, _, payload, (added, updated, removed)) in written {
+        cache.update(&name, store.stamp(), |cached| {
+            for change in &operation.changes {
+                if let Change::Record { vault_id: change_vault, id, .. } = change {
+                    let find = |entries: &[Value]| entries.iter().find(|entry| vault::entry_id(entry) == id).cloned();
+                    if change_vault == vault_id {
+                        cached.journal.record(id, find(&cached.payload.entries), find(&payload.entries));
+                    }
+                }
+            }
+            cached.payload.entries = payload.entries.clone();
+            cached.payload.generation = payload.generation;
+            cached.data = to_string(&cached.payload.entries).unwrap_or_default();
+        });
+        if let Some(app_handle) = APP_HANDLE.get() {
+            let _ = app_handle.emit("vault-changed", json!({
+                "username": username,
+                "vault": vault_id,
+                "added": added,
+                "updated": updated,
+                "removed": removed,
+                "conflicts": [],
+                "undo": true,
+            }));
+        }
+    }
+    PassKeeperError::Success as u32
+}
+
+/// Write the vaults an undo or redo wrote before it failed back as they were, newest first.
+fn roll_back_writes(written: &[WrittenVault]) {
+    for (_, OpenVault { name, store, key: vault_key, .. }, secret_data, payload, _) in written.iter().rev() {
+        let mut restored = secret_data.payload.next(secret_data.payload.entries.clone());
+        restored.generation = payload.generation + 1;
+        match store.replace(secret_data.slot, vault_key, &restored) {
+            Ok(()) => record_write(secret_data, restored.generation),
+            Err(e) => print_out!("Failed to roll back {}: {}", name, e),
+        }
+    }
+}
+
+/// Integrity of the vault and its generation against the last seen one
+#[command]
+fn check_vault_integrity(username: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
+        return String::new();
+    }
+    json!({
+        "integrity": secret_data.integrity,
+        "generation": secret_data.payload.generation,
+        "last_seen": generation::last_seen(&secret_data.payload.id),
+    }).to_string()
+}
+
+/// Accept an older vault, e.g. a restored backup, as the current generation
+#[command]
+fn accept_vault_rollback(username: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    // An unreadable payload can never be accepted
+    if secret_data.is_unreadable() {
+        return PassKeeperError::IntegrityCheckFailed as u32;
+    }
+    let accepted = if secret_data.payload.id.is_empty() {
+        generation::forget_vault(&vault_key)
+    } else {
+        generation::reset(&secret_data.payload.id, secret_data.payload.generation)
+            .and_then(|_| generation::record_vault(&vault_key, &secret_data.payload.id))
+    };
+    if accepted.is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    // The cached vault still carries the rollback verdict
+    cache.evict(&name);
+    PassKeeperError::Success as u32
+}
+
+/// Salvage the readable entries of a damaged vault and write them back as a new generation.
+/// A copy of the damaged vault is kept next to it, and the report lists the lost entries.
+#[command]
+fn repair_vault(username: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault_for_write(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let mut vault_file = match store.read() {
+        Ok(vf) => vf,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let slot = match vault_file.find_slot(&make_skey(&vault_key)) {
+        Some(index) => index,
+        None => {
+            return String::new();
+        }
+    };
+    let line = vault_file.slots[slot].payload.clone();
+    // Nothing to repair in a vault that opens
+    if let Ok(payload) = Payload::open(&vault_key, &line) {
+        return json!({
+            "code": PassKeeperError::Success as u32,
+            "recovered": payload.entries.len(),
+            "lost": [],
+            "manifest_lost": false,
+            "dropped": 0,
+        }).to_string();
+    }
+    let salvage = Payload::salvage(&vault_key, &line);
+    print_out!("Salvaged {} entries, lost {}, dropped {} for vault: {}", salvage.recovered, salvage.lost.len(), salvage.dropped, name);
+    // Keep the damaged vault in the data file layout, the lost entries may still be recovered by hand
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let backup_path = format!("{}/data/{}.damaged-{}", current_dir().unwrap().display(), name, seconds);
+    if vault::write(&backup_path, &vault_file).is_err() {
+        return json!({ "code": PassKeeperError::FileWriteFailed as u32 }).to_string();
+    }
+    let mut payload = salvage.payload.next(salvage.payload.entries.clone());
+    payload.generation = next_generation(&salvage.payload);
+    vault_file.slots[slot].payload = match payload.seal(&vault_key) {
+        Ok(sealed) => sealed,
+        Err(_) => {
+            return json!({ "code": PassKeeperError::EncryptionFailed as u32 }).to_string();
+        }
+    };
+    cache.evict(&name);
+    let code = match store.write(&vault_file) {
+        Ok(()) => {
+            let _ = generation::record(&payload.id, payload.generation);
+            let _ = generation::record_vault(&vault_key, &payload.id);
+            PassKeeperError::Success as u32
+        },
+        Err(_) => PassKeeperError::FileWriteFailed as u32,
+    };
+    json!({
+        "code": code,
+        "recovered": salvage.recovered,
+        "lost": salvage.lost,
+        "manifest_lost": salvage.manifest_lost,
+        "dropped": salvage.dropped,
+        "backup": backup_path,
+    }).to_string()
+}
+
+/// Refuse (`true`) or only warn about (`false`) vaults older than their last seen generation.
+/// The policy is kept outside the data directory, so restoring a data file does not change it.
+#[command]
+fn set_rollback_policy(username: &str, refuse: bool) -> u32 {
+    if let Err(code) = settings_session(username) {
+        return code;
+    }
+    if generation::set_refuse_rollback(refuse).is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    PassKeeperError::Success as u32
+}
+
+/// Backend, id, generation and entry count of the vault
+#[command]
+fn get_vault_info(username: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(_) => {
+            return String::new();
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() || secret_data.is_unreadable() {
+        return String::new();
+    }
+    match store.metadata(secret_data.slot, &vault_key) {
+        Ok(meta) => to_string(&meta).unwrap_or_default(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Move a vault to another storage backend, `"file"` or `"sqlite"`.
+/// Both slots are copied as they are, and the original is removed once the copy opens.
+#[command]
+fn migrate_vault(username: &str, backend: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    let kind = match StoreKind::from_name(backend) {
+        Some(kind) => kind,
+        None => {
+            return PassKeeperError::NotSupported as u32;
+        }
+    };
+    let OpenVault { name, store: source, key: vault_key, .. } = match open_vault_for_write(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    if source.kind() == kind {
+        return PassKeeperError::Success as u32;
+    }
+    // Never carry an unreadable or rolled back vault over
+    let secret_data = load_secret_data(&cache, &name, source.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    if let Err(code) = secret_data.check_integrity(&name) {
+        return code;
+    }
+    let vault_file = match source.read() {
+        Ok(vf) => vf,
+        Err(_) => {
+            return PassKeeperError::FileNotFound as u32;
+        }
+    };
+    let target = match store::open_kind(kind, &name) {
+        Ok(store) => store,
+        Err(_) => {
+            return PassKeeperError::FileWriteFailed as u32;
+        }
+    };
+    if target.write(&vault_file).is_err() {
+        let _ = target.remove();
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    // The copy must open with the same entries before the original goes
+    let copied = target.load(secret_data.slot, &vault_key)
+        .map(|payload| payload.entries == secret_data.payload.entries)
+        .unwrap_or(false);
+    if !copied {
+        let _ = target.remove();
+        return PassKeeperError::IntegrityCheckFailed as u32;
+    }
+    if source.remove().is_err() {
+        let _ = target.remove();
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    cache.evict(&name);
+    print_out!("Migrated the vault {} to {:?}", name, kind);
+    PassKeeperError::Success as u32
+}
+
+/// Storage backend of new accounts, `"file"` or `"sqlite"`
+#[command]
+fn set_storage_backend(username: &str, backend: &str) -> u32 {
+    if let Err(code) = settings_session(username) {
+        return code;
+    }
+    let kind = match StoreKind::from_name(backend) {
+        Some(kind) => kind,
+        None => {
+            return PassKeeperError::NotSupported as u32;
+        }
+    };
+    let mut settings = settings::load();
+    settings.storage_backend = kind;
+    if settings::save(&settings).is_err() {
+        return PassKeeperError::FileWriteFailed as u32;
+    }
+    PassKeeperError::Success as u32
+}
+
+/// Settle an entry changed both in this session and on disk.
+/// `keep_mine` writes the version of this session again, otherwise the version on disk stays.
+#[command]
+fn resolve_vault_conflict(username: &str, entry_id: &str, keep_mine: bool, vault_id: Option<String>, cache: State<'_, SecretCache>) -> u32 {
+    // Writing this session's version again is a change like any other and can be undone
+    let _undo = undo::begin(username, "resolve_vault_conflict");
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault_for_write(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    if let Err(code) = secret_data.check_integrity(&name) {
+        return code;
+    }
+    // Settled already, e.g. by a later change on disk
+    let mine = match secret_data.journal.mine(entry_id) {
+        Some(mine) => mine.cloned(),
+        None => {
+            return PassKeeperError::Success as u32;
+        }
+    };
+    if !keep_mine {
+        cache.update(&name, store.stamp(), |cached| cached.journal.settle(entry_id));
+        return PassKeeperError::Success as u32;
+    }
+    match mine {
+        Some(entry) => save_entry(&cache, &name, store.as_ref(), &secret_data, &entry, &vault_key),
+        None => delete_entry(&cache, &name, store.as_ref(), &secret_data, entry_id, &vault_key),
+    }
+}
+
+/// The vaults of the login, its own first and then the team vaults,
+/// as a json array of `{id, name, current, team, role}`
+#[command]
+fn list_vaults(username: &str) -> String {
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return String::new();
+        }
+    };
+    let current = session::current_vault(username).unwrap_or_else(|| DEFAULT_VAULT.to_string());
+    let mut list: Vec<Value> = vaults::list(username, &login_key)
+        .into_iter()
+        .map(|vault| json!({
+            "current": vault.id == current,
+            "id": vault.id,
+            "name": vault.name,
+            "role": Role::Owner,
+            "team": false,
+        }))
+        .collect();
+    list.extend(teams::list(username, &login_key).into_iter().map(|team| json!({
+        "current": team.id == current,
+        "id": team.id,
+        "name": team.name,
+        "role": team.role,
+        "team": true,
+    })));
+    to_string(&list).unwrap_or_default()
+}
+
+/// Create an empty named vault with its own key, in the backend picked for new vaults.
+/// Returns `{code, id}`.
+#[command]
+fn create_vault(username: &str, name: &str) -> String {
+    let result = |code: PassKeeperError, id: &str| json!({ "code": code as u32, "id": id }).to_string();
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return result(PassKeeperError::SessionLocked, "");
+        }
+    };
+    if !selftest::is_passed() {
+        return result(PassKeeperError::CryptoSelfTestFailed, "");
+    }
+    if vaults::create_dir(username).is_err() {
+        return result(PassKeeperError::DirCreationFailed, "");
+    }
+    let vault_id = vaults::new_id();
+    let vault_key = vaults::new_key();
+    let (store, payload) = match create_empty_store(&vaults::store_name(username, &vault_id), &vault_key) {
+        Ok(created) => created,
+        Err(code) => {
+            return result(code, "");
+        }
+    };
+    if let Err(e) = vaults::add(username, &login_key, &vault_id, name, &vault_key) {
+        let _ = store.remove();
+        return json!({ "code": vaults_error_code(&e), "id": "" }).to_string();
+    }
+    let _ = generation::record(&payload.id, payload.generation);
+    print_out!("Created vault {} for user {}", vault_id, username);
+    result(PassKeeperError::Success, &vault_id)
+}
+
+/// Write an empty vault encrypted with a random key, in the backend picked for new vaults
+fn create_empty_store(store_name: &str, vault_key: &VaultKey) -> Result<(Box<dyn VaultStore>, Payload), PassKeeperError> {
+    let payload = Payload::new();
+    let sealed = payload.seal(vault_key).map_err(|_| PassKeeperError::EncryptionFailed)?;
+    // The key is random, so the slot needs no KDF parameters
+    let slot = Slot { kdf: None, skey: make_skey(vault_key), payload: sealed };
+    let mut vault_file = VaultFile { slots: vec![vault::filler_slot(None), vault::filler_slot(None)] };
+    vault_file.slots[vault::random_slot()] = slot;
+    let store = store::open_kind(settings::load().storage_backend, store_name)
+        .map_err(|_| PassKeeperError::FileWriteFailed)?;
+    if store.write(&vault_file).is_err() {
+        let _ = store.remove();
+        return Err(PassKeeperError::FileWriteFailed);
+    }
+    Ok((store, payload))
+}
+
+/// Create an empty team vault owned by the user, who can then invite other users.
+/// Returns `{code, id}`.
+#[command]
+fn create_team_vault(username: &str, name: &str) -> String {
+    let result = |code: u32, id: &str| json!({ "code": code, "id": id }).to_string();
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return result(PassKeeperError::SessionLocked as u32, "");
+        }
+    };
+    if !selftest::is_passed() {
+        return result(PassKeeperError::CryptoSelfTestFailed as u32, "");
+    }
+    if teams::create_dir().is_err() {
+        return result(PassKeeperError::DirCreationFailed as u32, "");
+    }
+    let team_id = vaults::new_id();
+    let vault_key = vaults::new_key();
+    let (store, payload) = match create_empty_store(&teams::store_name(&team_id), &vault_key) {
+        Ok(created) => created,
+        Err(code) => {
+            return result(code as u32, "");
+        }
+    };
+    let team = match teams::create(username, &login_key, &team_id, name, &vault_key) {
+        Ok(team) => team,
+        Err(e) => {
+            let _ = store.remove();
+            return result(teams_error_code(&e), "");
+        }
+    };
+    let _ = generation::record(&payload.id, payload.generation);
+    print_out!("Created team vault {} for user {}", team_id, username);
+    result(PassKeeperError::Success as u32, &team.id)
+}
+
+/// The members of a team vault as a json array of `{username, role}`, for its members
+#[command]
+fn list_team_members(username: &str, vault_id: &str) -> String {
+    let (Some(login_key), Some(team_id)) = (session::vault_key(username), teams::team_id(vault_id)) else {
+        return String::new();
+    };
+    match teams::members(team_id, username, &login_key) {
+        Ok(members) => to_string(&members).unwrap_or_default(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Give a user with sharing keys access to a team vault as `read_write` or `read_only`.
+/// Only the owner invites.
+#[command]
+fn invite_team_member(username: &str, vault_id: &str, member: &str, role: &str) -> u32 {
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    let (Some(team_id), Some(role)) = (teams::team_id(vault_id), Role::parse(role)) else {
+        return PassKeeperError::NotSupported as u32;
+    };
+    match teams::invite(team_id, username, &login_key, member, role) {
+        Ok(()) => {
+            print_out!("Invited {} to team vault {}", member, team_id);
+            PassKeeperError::Success as u32
+        },
+        Err(e) => teams_error_code(&e),
+    }
+}
+
+/// Take access to a team vault away from a member. The vault is encrypted again with a new key
+/// that only the remaining members get. Only the owner removes members.
+#[command]
+fn remove_team_member(username: &str, vault_id: &str, member: &str, cache: State<'_, SecretCache>) -> u32 {
+    let OpenVault { name, store, .. } = match open_vault(username, Some(vault_id)) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    let Some(team_id) = teams::team_id(vault_id) else {
+        return PassKeeperError::NotSupported as u32;
+    };
+    let rotated = teams::remove_member(team_id, username, &login_key, member, |old_key, new_key| {
+        add_rotated_slot(store.as_ref(), old_key, new_key)
+    });
+    let new_key = match rotated {
+        Ok(key) => key,
+        Err(e) => {
+            return teams_error_code(&e);
+        }
+    };
+    cache.evict(&name);
+    // The members hold the new key now, the slot of the old one goes
+    if drop_other_slots(store.as_ref(), &new_key).is_err() {
+        print_out!("Failed to drop the old key of team vault {}", team_id);
+    }
+    print_out!("Removed {} from team vault {} and rotated its key", member, team_id);
+    PassKeeperError::Success as u32
+}
+
+/// Add a slot with the entries of the slot opened by `old_key`, encrypted with `new_key`
+fn add_rotated_slot(store: &dyn VaultStore, old_key: &VaultKey, new_key: &VaultKey) -> bool {
+    let Ok(mut vault_file) = store.read() else {
+        return false;
+    };
+    let Some(index) = vault_file.find_slot(&make_skey(old_key)) else {
+        return false;
+    };
+    let Ok(old_payload) = store.load(index, old_key) else {
+        return false;
+    };
+    let payload = old_payload.next(old_payload.entries.clone());
+    let Ok(sealed) = payload.seal(new_key) else {
+        return false;
+    };
+    let other = (index + 1) % vault::SLOT_COUNT;
+    vault_file.slots[other] = Slot { kdf: None, skey: make_skey(new_key), payload: sealed };
+    if store.write(&vault_file).is_err() {
+        return false;
+    }
+    let _ = generation::record(&payload.id, payload.generation);
+    true
+}
+
+/// Replace every slot but the one opened by `vault_key` with filler
+fn drop_other_slots(store: &dyn VaultStore, vault_key: &VaultKey) -> Result<(), StoreError> {
+    let mut vault_file = store.read()?;
+    let skey = make_skey(vault_key);
+    for slot in vault_file.slots.iter_mut().filter(|slot| slot.skey != skey) {
+        *slot = vault::filler_slot(None);
+    }
+    store.write(&vault_file)
+}
+
+#[command]
+fn rename_vault(username: &str, vault_id: &str, name: &str) -> u32 {
+    let _undo = undo::begin(username, "rename_vault");
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    let before = vault_name(username, &login_key, vault_id);
+    let code = set_vault_name(username, &login_key, vault_id, name);
+    if let (Some(before), true) = (before, code == PassKeeperError::Success as u32) {
+        if let Some(after) = vault_name(username, &login_key, vault_id).filter(|after| *after != before) {
+            undo::record(Change::VaultName { vault_id: vault_id.to_string(), before, after });
+        }
+    }
+    code
+}
+
+/// The name of a vault of the user, its own or a team vault
+fn vault_name(username: &str, login_key: &VaultKey, vault_id: &str) -> Option<String> {
+    if teams::team_id(vault_id).is_some() {
+        return teams::list(username, login_key).into_iter().find(|team| team.id == vault_id).map(|team| team.name);
+    }
+    vaults::list(username, login_key).into_iter().find(|vault| vault.id == vault_id).map(|vault| vault.name)
+}
+
+fn set_vault_name(username: &str, login_key: &VaultKey, vault_id: &str, name: &str) -> u32 {
+    if let Some(team_id) = teams::team_id(vault_id) {
+        return match teams::rename(team_id, username, login_key, name) {
+            Ok(()) => PassKeeperError::Success as u32,
+            Err(e) => teams_error_code(&e),
+        };
+    }
+    match vaults::rename(username, login_key, vault_id, name) {
+        Ok(()) => PassKeeperError::Success as u32,
+        Err(e) => vaults_error_code(&e),
+    }
+}
+
+/// Delete a named vault and its entries. The default vault stays.
+/// A team vault is deleted by its owner, for every member.
+#[command]
+fn delete_vault(username: &str, vault_id: &str, cache: State<'_, SecretCache>) -> u32 {
+    let OpenVault { name, store, .. } = match open_vault(username, Some(vault_id)) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return code;
+        }
+    };
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    let removed = match teams::team_id(vault_id) {
+        Some(team_id) => teams::delete(team_id, username, &login_key).map_err(|e| teams_error_code(&e)),
+        None => vaults::remove(username, &login_key, vault_id).map_err(|e| vaults_error_code(&e)),
+    };
+    if let Err(code) = removed {
+        return code;
+    }
+    cache.evict(&name);
+    if session::current_vault(username).as_deref() == Some(vault_id) {
+        session::select_vault(username, None);
+    }
+    if let Err(e) = store.remove() {
+        print_out!("Failed to remove the storage of vault {}: {}", name, e);
+    }
+    PassKeeperError::Success as u32
+}
+
+/// Select the vault the data commands use when they are not given a vault id
+#[command]
+fn switch_vault(username: &str, vault_id: &str) -> u32 {
+    if let Err(code) = open_vault(username, Some(vault_id)) {
+        return code;
+    }
+    let selected = if vault_id == DEFAULT_VAULT { None } else { Some(vault_id) };
+    session::select_vault(username, selected);
+    PassKeeperError::Success as u32
+}
+
+/// The public sharing keys of any user as `{username, x25519, ed25519, fingerprint}`,
+/// empty if the user has none
+#[command]
+fn get_public_key(username: &str) -> String {
+    match share::public_identity(username) {
+        Some(identity) => json!({
+            "fingerprint": identity.fingerprint(),
+            "username": identity.username,
+            "x25519": identity.x25519,
+            "ed25519": identity.ed25519,
+        }).to_string(),
+        None => String::new(),
+    }
+}
+
+/// Seal entries of a vault for another user and put them in their inbox.
+/// Returns `{code, path}`.
+#[command]
+fn share_entries(username: &str, entry_ids: Vec<String>, recipient: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let result = |code: u32, path: &str| json!({ "code": code, "path": path }).to_string();
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return result(code, "");
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
+        return result(PassKeeperError::FileNotFound as u32, "");
+    }
+    if let Err(code) = secret_data.check_integrity(&name) {
+        return result(code, "");
+    }
+    // The recipient gives the entries ids of their own
+    // and put them in folders of their own
+    let entries: Vec<Value> = folders::entries(&secret_data.payload.entries)
+        .filter(|entry| entry_ids.iter().any(|id| id == vault::entry_id(entry)))
+        .map(|entry| {
+            let mut entry = entry.clone();
+            if let Some(fields) = entry.as_object_mut() {
+                fields.remove("id");
+            }
+            // Previous passwords stay with the sender
+            history::strip(&mut entry);
+            folders::set_parent(&mut entry, ROOT_FOLDER);
+            entry
+        })
+        .collect();
+    if entries.is_empty() || entries.len() != entry_ids.len() {
+        return result(PassKeeperError::FileNotFound as u32, "");
+    }
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return result(PassKeeperError::SessionLocked as u32, "");
+        }
+    };
+    // Accounts created before sharing get their keys on the first share
+    if let Err(e) = share::ensure_identity(username, &login_key) {
+        return result(share_error_code(&e), "");
+    }
+    let delivered = share::seal_bundle(username, &login_key, recipient, &entries)
+        .and_then(|bundle| share::deliver(&bundle));
+    match delivered {
+        Ok(path) => {
+            print_out!("Shared {} entries of {} with {}", entries.len(), username, recipient);
+            result(PassKeeperError::Success as u32, &path.display().to_string())
+        },
+        Err(e) => result(share_error_code(&e), ""),
+    }
+}
+
+/// The bundles shared with a user as a json array of `{id, sender, created, verified}`
+#[command]
+fn list_shared_bundles(username: &str) -> String {
+    if session::vault_key(username).is_none() {
+        return String::new();
+    }
+    to_string(&share::inbox(username)).unwrap_or_default()
+}
+
+/// Rebuild an entry of a shared bundle from its service, account, password and fields,
+/// checked like a new entry from `save_data`. Anything else the sender put in it is dropped.
+/// `None` if the entry does not pass the checks.
+fn rebuild_shared_entry(entry: &Value) -> Option<Value> {
+    if !entry.is_object() || folders::is_folder(entry) {
+        return None;
+    }
+    let text = |key: &str| match &entry[key] {
+        Value::Null => Some(String::new()),
+        value => value.as_str().map(str::to_string),
+    };
+    let (service, account, password) = (text("service")?, text("account")?, text("password")?);
+    if service.trim().is_empty() {
+        return None;
+    }
+    let fields: EntryFields = serde_json::from_value(entry.clone()).ok()?;
+    let fields = fields.normalize().ok()?;
+    let mut rebuilt = json!({
+        "id": new_entry_id(),
+        "service": service,
+        "account": account,
+        "password": password,
+    });
+    fields.apply(&mut rebuilt);
+    folders::set_parent(&mut rebuilt, ROOT_FOLDER);
+    // Entries keep the times the sender had, or are new here
+    timestamps::stamp_new(&mut rebuilt);
+    let now = timestamps::now();
+    for key in [timestamps::CREATED, timestamps::MODIFIED, timestamps::PASSWORD_CHANGED] {
+        let time = timestamps::get(entry, key);
+        if time > 0 && time <= now {
+            rebuilt[key] = json!(time);
+        }
+    }
+    Some(rebuilt)
+}
+
+/// Import the entries of a shared bundle into a vault after checking the signature of the sender.
+/// Entries already in the vault with the same service and account are skipped, and entries
+/// failing the checks of a new entry are refused.
+/// Returns `{code, imported, skipped, refused}`.
+#[command]
+fn import_shared_bundle(username: &str, bundle_id: &str, vault_id: Option<String>, cache: State<'_, SecretCache>) -> String {
+    let _undo = undo::begin(username, "import_shared_bundle");
+    let mut refused = 0;
+    let result = |code: u32, imported: usize, skipped: usize, refused: usize| {
+        json!({ "code": code, "imported": imported, "skipped": skipped, "refused": refused }).to_string()
+    };
+    let OpenVault { name, store, key: vault_key, .. } = match open_vault_for_write(username, vault_id.as_deref()) {
+        Ok(vault) => vault,
+        Err(code) => {
+            return result(code, 0, 0, 0);
+        }
+    };
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return result(PassKeeperError::SessionLocked as u32, 0, 0, 0);
+        }
+    };
+    let (sender, entries) = match share::open_bundle(username, &login_key, bundle_id) {
+        Ok(opened) => opened,
+        Err(e) => {
+            return result(share_error_code(&e), 0, 0, 0);
+        }
+    };
+    let secret_data = load_secret_data(&cache, &name, store.as_ref(), &vault_key);
+    if secret_data.is_skey_empty() {
+        return result(PassKeeperError::FileNotFound as u32, 0, 0, 0);
+    }
+    if let Err(code) = secret_data.check_integrity(&name) {
+        return result(code, 0, 0, 0);
+    }
+    let mut importing: Vec<Value> = Vec::new();
+    let mut skipped = 0;
+    for entry in entries {
+        let Some(entry) = rebuild_shared_entry(&entry) else {
+            refused += 1;
+            continue;
+        };
+        let duplicate = folders::entries(&secret_data.payload.entries).chain(importing.iter())
+            .any(|e| e["service"] == entry["service"] && e["account"] == entry["account"]);
+        if duplicate {
+            skipped += 1;
+            continue;
+        }
+        importing.push(entry);
+    }
+    let code = apply_entries(&cache, &name, store.as_ref(), &secret_data, &importing, &[], &vault_key);
+    if code != PassKeeperError::Success as u32 {
+        return result(code, 0, skipped, refused);
+    }
+    let imported = importing.len();
+    if let Err(e) = share::discard(username, bundle_id) {
+        print_out!("Failed to remove imported bundle {}: {:?}", bundle_id, e);
+    }
+    print_out!("Imported {} entries shared by {} into {}, refused {}", imported, sender, name, refused);
+    result(PassKeeperError::Success as u32, imported, skipped, refused)
+}
+
+/// Remove a shared bundle without importing it
+#[command]
+fn discard_shared_bundle(username: &str, bundle_id: &str) -> u32 {
+    if session::vault_key(username).is_none() {
+        return PassKeeperError::SessionLocked as u32;
+    }
+    match share::discard(username, bundle_id) {
+        Ok(()) => PassKeeperError::Success as u32,
+        Err(e) => share_error_code(&e),
+    }
+}
+
+/// The login key of an unlocked administrator and the administration they change
+fn admin_session(username: &str) -> Result<(VaultKey, AdminConfig), u32> {
+    let login_key = session::vault_key(username).ok_or(PassKeeperError::SessionLocked as u32)?;
+    let config = admin::load().map_err(|e| admin_error_code(&e))?;
+    if !config.is_admin(username) {
+        return Err(PassKeeperError::NotAdmin as u32);
+    }
+    Ok((login_key, config))
+}
+
+/// Checks that a user may change the settings of the whole installation:
+/// an administrator once there are any, otherwise any unlocked user
+fn settings_session(username: &str) -> Result<(), u32> {
+    if session::vault_key(username).is_none() {
+        return Err(PassKeeperError::SessionLocked as u32);
+    }
+    let config = admin::load().map_err(|e| admin_error_code(&e))?;
+    if !config.admins.is_empty() && !config.is_admin(username) {
+        return Err(PassKeeperError::NotAdmin as u32);
+    }
+    Ok(())
+}
+
+/// Returns `true` if a slot of the account still has the password an administrator reset it to
+fn must_change_password(config: &AdminConfig, username: &str) -> bool {
+    let Some(reset_skey) = config.account(username).reset_skey else {
+        return false;
+    };
+    store::open(username).and_then(|store| store.read())
+        .is_ok_and(|vf| vf.find_slot(&reset_skey).is_some())
+}
+
+/// The policies in force as `{admin_only_registration, min_password_length, min_character_classes,
+/// auto_lock_minutes, key_escrow, has_admin, tampered}`.
+/// A tampered administration restricts registration until an administrator signs it again.
+#[command]
+fn get_policy() -> String {
+    let (config, tampered) = match admin::load() {
+        Ok(config) => (config, false),
+        Err(_) => (AdminConfig::default(), true),
+    };
+    let mut policy = serde_json::to_value(&config.policy).unwrap_or_default();
+    if let Some(policy) = policy.as_object_mut() {
+        if tampered {
+            policy.insert("admin_only_registration".to_string(), json!(true));
+        }
+        policy.insert("has_admin".to_string(), json!(tampered || !config.admins.is_empty()));
+        policy.insert("tampered".to_string(), json!(tampered));
+    }
+    policy.to_string()
+}
+
+/// The account of an unlocked user as `{admin, disabled, must_change_password}`
+#[command]
+fn get_account_status(username: &str) -> String {
+    if session::vault_key(username).is_none() {
+        return String::new();
+    }
+    let config = admin::load().unwrap_or_default();
+    json!({
+        "admin": config.is_admin(username),
+        "disabled": config.is_disabled(username),
+        "must_change_password": must_change_password(&config, username),
+    }).to_string()
+}
+
+/// Become the first administrator. Only possible while there is none.
+#[command]
+fn claim_admin(username: &str) -> u32 {
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    match admin::claim(username, &login_key) {
+        Ok(config) => {
+            print_out!("User {} is the administrator now", username);
+            let _ = admin::escrow(&config, username, &login_key, false);
+            PassKeeperError::Success as u32
+        },
+        Err(e) => admin_error_code(&e),
+    }
+}
+
+/// The local accounts as a json array of `{username, admin, disabled, must_change_password, escrow}`,
+/// where `escrow` tells whether the administrator can reset the password. For administrators.
+#[command]
+fn list_accounts(username: &str) -> String {
+    let Ok((_, config)) = admin_session(username) else {
+        return String::new();
+    };
+    let accounts: Vec<Value> = store::users().into_iter().map(|user| json!({
+        "admin": config.is_admin(&user),
+        "disabled": config.is_disabled(&user),
+        "escrow": admin::is_escrowed(&user, username),
+        "must_change_password": must_change_password(&config, &user),
+        "username": user,
+    })).collect();
+    to_string(&accounts).unwrap_or_default()
+}
+
+/// Make a user with sharing keys an administrator, or take the role away.
+/// Administrators cannot step down themselves. A new administrator gets the escrowed keys
+/// of the other accounts as they log in.
+#[command]
+fn set_admin(username: &str, target: &str, admin: bool) -> u32 {
+    let login_key = match admin_session(username) {
+        Ok((key, _)) => key,
+        Err(code) => {
+            return code;
+        }
+    };
+    if admin && share::public_identity(target).is_none() {
+        return PassKeeperError::RecipientNotFound as u32;
+    }
+    let updated = admin::update(username, &login_key, |config| {
+        config.admins.retain(|name| name != target);
+        if admin {
+            config.admins.push(target.to_string());
+        }
+        Ok(())
+    });
+    match updated {
+        Ok(config) => {
+            let _ = admin::escrow(&config, username, &login_key, false);
+            PassKeeperError::Success as u32
+        },
+        Err(e) => admin_error_code(&e),
+    }
+}
+
+/// Disable or enable an account. A disabled account is logged out and cannot log in.
+#[command]
+fn set_account_disabled(username: &str, target: &str, disabled: bool, cache: State<'_, SecretCache>) -> u32 {
+    let login_key = match admin_session(username) {
+        Ok((key, _)) => key,
+        Err(code) => {
+            return code;
+        }
+    };
+    if !store::exists(target) {
+        return PassKeeperError::FileNotFound as u32;
+    }
+    if disabled && target == username {
+        return PassKeeperError::NotSupported as u32;
+    }
+    let updated = admin::update(username, &login_key, |config| {
+        config.accounts.entry(target.to_string()).or_default().disabled = disabled;
+        Ok(())
+    });
+    if let Err(e) = updated {
+        return admin_error_code(&e);
+    }
+    if disabled {
+        session::logout(target);
+        keyring::revoke(target);
+        evict_user(&cache, target);
+    }
+    print_out!("Account {} {}", target, if disabled { "disabled" } else { "enabled" });
+    PassKeeperError::Success as u32
+}
+
+/// Reset the master password of an account with its escrowed key, for an administrator.
+/// The user is to change it on the next login.
+#[command]
+fn reset_master_password(username: &str, target: &str, new_password: &str, cache: State<'_, SecretCache>) -> u32 {
+    let (login_key, config) = match admin_session(username) {
+        Ok(session) => session,
+        Err(code) => {
+            return code;
+        }
+    };
+    if !config.policy.accepts_password(new_password) {
+        return PassKeeperError::WeakPassword as u32;
+    }
+    let old_key = match admin::escrowed_key(target, username, &login_key) {
+        Ok(key) => key,
+        Err(e) => {
+            return admin_error_code(&e);
+        }
+    };
+    let store = match store::open(target) {
+        Ok(store) => store,
+        Err(_) => {
+            return PassKeeperError::FileNotFound as u32;
+        }
+    };
+    let mut vault_file = match store.read() {
+        Ok(vf) => vf,
+        Err(e) => {
+            return store_error_code(&e);
+        }
+    };
+    // The escrow of an earlier password opens no slot
+    let Some(index) = vault_file.find_slot(&make_skey(&old_key)) else {
+        return PassKeeperError::KeyNotEscrowed as u32;
+    };
+    let new_key = match rekey_login(store.as_ref(), &mut vault_file, index, target, new_password, &old_key) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::EncryptionFailed as u32;
+        }
+    };
+    // Sessions and caches of the old password end here
+    session::logout(target);
+    keyring::revoke(target);
+    evict_user(&cache, target);
+    let updated = admin::update(username, &login_key, |config| {
+        config.accounts.entry(target.to_string()).or_default().reset_skey = Some(make_skey(&new_key));
+        Ok(())
+    });
+    match updated {
+        Ok(config) => {
+            if let Err(e) = admin::escrow(&config, target, &new_key, true) {
+                print_out!("Failed to escrow the key of user {}: {:?}", target, e);
+            }
+            print_out!("Reset the master password of user {}", target);
+            PassKeeperError::Success as u32
+        },
+        Err(e) => admin_error_code(&e),
+    }
+}
+
+/// Change the master password of the unlocked user.
+#[command]
+fn change_master_password(username: &str, old_password: &str, new_password: &str, cache: State<'_, SecretCache>) -> u32 {
+    let login_key = match session::vault_key(username) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::SessionLocked as u32;
+        }
+    };
+    let config = match admin::load() {
+        Ok(config) => config,
+        Err(e) => {
+            return admin_error_code(&e);
+        }
+    };
+    if !config.policy.accepts_password(new_password) || new_password == old_password {
+        return PassKeeperError::WeakPassword as u32;
+    }
+    let store = match store::open(username) {
+        Ok(store) => store,
+        Err(_) => {
+            return PassKeeperError::FileNotFound as u32;
+        }
+    };
+    let mut vault_file = match store.read() {
+        Ok(vf) => vf,
+        Err(e) => {
+            return store_error_code(&e);
+        }
+    };
+    let Some(index) = vault_file.find_slot(&make_skey(&login_key)) else {
+        return PassKeeperError::DecryptionFailed as u32;
+    };
+    // The old password must derive the key of the unlocked slot
+    let old_key = derive_slot_key(username, old_password, vault_file.slots[index].kdf.as_ref());
+    if !old_key.is_ok_and(|key| key == login_key) {
+        return PassKeeperError::DecryptionFailed as u32;
+    }
+    let new_key = match rekey_login(store.as_ref(), &mut vault_file, index, username, new_password, &login_key) {
+        Some(key) => key,
+        None => {
+            return PassKeeperError::EncryptionFailed as u32;
+        }
+    };
+    // The PIN and the keyring hold the old key
+    session::clear_pin(username);
+    keyring::revoke(username);
+    evict_user(&cache, username);
+    session::unlock(username, new_key.clone());
+    if let Err(e) = admin::escrow(&config, username, &new_key, true) {
+        print_out!("Failed to escrow the key of user {}: {:?}", username, e);
+    }
+    print_out!("Changed the master password of user {}", username);
+    PassKeeperError::Success as u32
+}
+
+/// Set the policies, for administrators. Turning key escrow on escrows the keys of the
+/// other accounts as they log in.
+#[command]
+fn set_policy(username: &str, admin_only_registration: bool, min_password_length: u32, min_character_classes: u32, auto_lock_minutes: u32, key_escrow: bool) -> u32 {
+    let login_key = match admin_session(username) {
+        Ok((key, _)) => key,
+        Err(code) => {
+            return code;
+        }
+    };
+    if min_character_classes > 4 {
+        return PassKeeperError::NotSupported as u32;
+    }
+    let updated = admin::update(username, &login_key, |config| {
+        config.policy = admin::Policy {
+            admin_only_registration,
+            min_password_length,
+            min_character_classes,
+            auto_lock_minutes,
+            key_escrow,
+        };
+        Ok(())
+    });
+    match updated {
+        Ok(config) => {
+            let _ = admin::escrow(&config, username, &login_key, false);
+            PassKeeperError::Success as u32
+        },
+        Err(e) => admin_error_code(&e),
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    // Known-answer tests of the crypto primitives, before any vault can be opened
+    let report = selftest::report();
+    // `passkeeper --self-test` prints the results and exits
+    if std::env::args().any(|arg| arg == "--self-test") {
+        println!("{}", serde_json::to_string_pretty(report).unwrap_or_default());
+        std::process::exit(if report.passed { 0 } else { 1 });
+    }
+    for result in &report.results {
+        print_out!(">> [Self-test] {}: {} ({})", result.name, result.passed, result.detail);
+    }
+
+    let builder = tauri::Builder::default();
+    // A second launch focuses the running window and hands over its arguments.
+    // Must be the first plugin, so the second process exits before anything else starts.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+        print_out!("Second instance started with {:?} in {}", args, cwd);
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+        let _ = app.emit("second-instance", json!({
+            "args": args,
+            "cwd": cwd,
+        }));
+    }));
+    builder
         .setup(|app| {
             // Store the AppHandle in a global static variable for later use
             APP_HANDLE.set(app.handle().clone()).unwrap();
+            // Reload vaults changed on disk by other instances or sync tools
+            let data_dir = current_dir().unwrap().join("data");
+            if let Err(e) = watcher::start(&data_dir, on_vault_file_changed) {
+                print_out!("Failed to watch the vault files: {}", e);
+            }
             let window = app.get_webview_window("main").unwrap();
-            let (left, top, right, bottom) = get_window_pos();
-            let _ = window.set_position(tauri::Position::Physical(tauri::PhysicalPosition { x: left, y: top }));
-            let width = (right - left).max(0) as u32;
-            let height = (bottom - top).max(0) as u32;
-            let _ = window.set_size(tauri::Size::Physical(tauri::PhysicalSize { width, height }));
+            // The window position is kept in the registry, other platforms use the default
+            #[cfg(windows)]
+            {
+                let (left, top, right, bottom) = get_window_pos();
+                let _ = window.set_position(tauri::Position::Physical(tauri::PhysicalPosition { x: left, y: top }));
+                let width = (right - left).max(0) as u32;
+                let height = (bottom - top).max(0) as u32;
+                let _ = window.set_size(tauri::Size::Physical(tauri::PhysicalSize { width, height }));
+            }
             window.show().unwrap();
             Ok(())
         })
         .on_window_event(|_window, event| {
             match event {
+                #[cfg(windows)]
                 #[allow(unused_variables)]
                 tauri::WindowEvent::Resized(size) => {
                     let _ = reg_position();
                 }
+                #[cfg(windows)]
                 #[allow(unused_variables)]
                 tauri::WindowEvent::Moved(position) => {
                     let _ = reg_position();
@@ -421,57 +2742,472 @@ pub fn run() {
             }
         })
         .plugin(tauri_plugin_opener::init())
+        .manage(SecretCache::default())
         .invoke_handler(tauri::generate_handler![
             validate_login,
+            resume_session,
             get_data,
+            search_entries,
             save_account,
             save_data,
             modify_data,
             remove_data,
+            set_pin,
+            is_pin_set,
+            unlock_with_pin,
+            lock_vault,
+            logout,
+            get_settings,
+            set_keyring_cache,
+            crypto_self_test,
+            calibrate_kdf,
+            set_kdf_floor,
+            check_vault_integrity,
+            accept_vault_rollback,
+            set_rollback_policy,
+            repair_vault,
+            get_vault_info,
+            migrate_vault,
+            set_storage_backend,
+            resolve_vault_conflict,
+            list_vaults,
+            create_vault,
+            rename_vault,
+            delete_vault,
+            switch_vault,
+            get_public_key,
+            share_entries,
+            list_shared_bundles,
+            import_shared_bundle,
+            discard_shared_bundle,
+            create_team_vault,
+            list_team_members,
+            invite_team_member,
+            remove_team_member,
+            get_policy,
+            get_account_status,
+            claim_admin,
+            list_accounts,
+            set_admin,
+            set_account_disabled,
+            reset_master_password,
+            change_master_password,
+            set_policy,
+            get_entry_templates,
+            undo,
+            redo,
+            get_password_history,
+            restore_password,
+            record_access,
+            list_trash,
+            restore_trash,
+            purge_trash,
+            set_trash_retention,
+            set_password_history_size,
+            create_folder,
+            rename_folder,
+            move_folder,
+            move_entries,
+            delete_folder,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-/// Retrieve secret data for a given username
-fn get_secret_data(username: String) -> SecretData {
-    let mut secret_data =SecretData::new();
-    let file_path = format!("{}/data/{}.dat", current_dir().unwrap().display(), username);
-    // Check if the file exists
-    if !Path::new(&file_path).exists() {
-        return secret_data;
+/// Derive the vault key of a slot from the master password.
+/// Slots without KDF parameters use the defaults and a salt made from the username.
+fn derive_slot_key(username: &str, password: &str, kdf: Option<&SlotKdf>) -> Result<VaultKey, Box<dyn std::error::Error>> {
+    match kdf {
+        Some(kdf) => derive_key_with(password, &kdf.salt, &kdf.params),
+        None => {
+            let salt = format!("{}:{}", define::APP_NAME, username);
+            derive_key_with(password, salt.as_bytes(), &KdfParams::default())
+        }
     }
-    // Read the stored content
-    let stored_content = fs::read_to_string(&file_path).unwrap_or_default();
-    let mut lines = stored_content.lines();
-    // First line is the password
-    let skey = lines.next().unwrap_or_default();
-    secret_data.skey = skey.to_string();
-    // Following lines are the encrypted json entries
-    let encrypted_line = lines.next();
-    let encrypted_data = match encrypted_line {
-        Some(el) => el,
+}
+
+/// KDF parameters for a new slot: the calibrated ones, never below the policy floor.
+/// A login derives one key per slot, so each derivation gets its share of the target time.
+fn new_kdf_params() -> KdfParams {
+    let mut settings = settings::load();
+    let params = match settings.kdf_calibrated {
+        Some(params) => params,
         None => {
-            return secret_data;
+            let target_ms = settings.kdf_target_ms as u64 / vault::SLOT_COUNT as u64;
+            let params = calibrate(target_ms, &settings.kdf_floor);
+            print_out!(">> [KDF] Calibrated: {:?}", params);
+            settings.kdf_calibrated = Some(params);
+            let _ = settings::save(&settings);
+            params
+        }
+    };
+    params.at_least(&settings.kdf_floor)
+}
+
+/// Make a slot holding a new vault
+fn make_slot(kdf: SlotKdf, skey: &str, vault_key: &VaultKey, payload: Payload) -> Option<Slot> {
+    let sealed = payload.seal(vault_key).ok()?;
+    let _ = generation::record(&payload.id, payload.generation);
+    Some(Slot {
+        kdf: Some(kdf),
+        skey: skey.to_string(),
+        payload: sealed,
+    })
+}
+
+/// Re-write a data file of the placeholder era with the key hash and an encrypted payload
+fn upgrade_legacy_file(store: &dyn VaultStore, username: &str, password: &str, encoded_data: &str) -> Option<VaultKey> {
+    // The legacy payload is plain json encoded with base64
+    let decoded = general_purpose::STANDARD.decode(encoded_data).unwrap_or_default();
+    let plain_data = String::from_utf8(decoded).unwrap_or_default();
+    let legacy = Payload::from_plain(&plain_data);
+    let payload = legacy.next(legacy.entries.clone());
+    let kdf_params = new_kdf_params();
+    let kdf = SlotKdf::generate(kdf_params);
+    let vault_key = derive_slot_key(username, password, Some(&kdf)).ok()?;
+    let real_slot = Slot {
+        kdf: Some(kdf),
+        skey: make_skey(&vault_key),
+        payload: payload.seal(&vault_key).ok()?,
+    };
+    let mut vault_file = VaultFile {
+        slots: vec![decoy::filler_slot(username, Some(kdf_params)), decoy::filler_slot(username, Some(kdf_params))],
+    };
+    vault_file.slots[vault::random_slot()] = real_slot;
+    store.write(&vault_file).ok()?;
+    let _ = generation::record(&payload.id, payload.generation);
+    if companion::create(username, &vault_key, &Other::Filler, None).is_err() {
+        print_out!("Failed to write the slot records for user: {}", username);
+    }
+    Some(vault_key)
+}
+
+/// Re-derive the key of a slot from a password with `kdf` and re-encrypt its payload.
+/// Returns the new key and the payload as written.
+fn reseal_slot(vault_file: &mut VaultFile, index: usize, username: &str, password: &str, old_key: &VaultKey, kdf: SlotKdf) -> Option<(VaultKey, Payload)> {
+    let slot = &vault_file.slots[index];
+    let old_payload = if slot.payload.is_empty() {
+        Payload::default()
+    } else {
+        Payload::open(old_key, &slot.payload).ok()?
+    };
+    let payload = old_payload.next(old_payload.entries.clone());
+    let new_key = derive_slot_key(username, password, Some(&kdf)).ok()?;
+    vault_file.slots[index] = Slot {
+        kdf: Some(kdf),
+        skey: make_skey(&new_key),
+        payload: payload.seal(&new_key).ok()?,
+    };
+    Some((new_key, payload))
+}
+
+/// Re-derive the login key of a slot from a password and re-seal everything kept under
+/// the login key. Returns the new key.
+/// Filler is re-keyed along with fresh KDF parameters. A decoy or real slot in the other place
+/// is re-keyed by its own login, as no password of it is kept: this slot takes its parameters
+/// when they meet the floor, so the headers agree again once both logins went through here.
+fn rekey_login(store: &dyn VaultStore, vault_file: &mut VaultFile, index: usize, username: &str, password: &str, login_key: &VaultKey) -> Option<VaultKey> {
+    let other = companion::other(username, login_key);
+    let rekey_filler = matches!(other, Some(Other::Filler));
+    let kdf_floor = settings::load().kdf_floor;
+    let kept_params = vault_file.slots.iter().enumerate()
+        .find_map(|(i, slot)| slot.kdf.as_ref().filter(|_| i != index).map(|kdf| kdf.params));
+    let params = match kept_params {
+        Some(params) if !rekey_filler && !params.is_below(&kdf_floor) => params,
+        _ => new_kdf_params(),
+    };
+    let (new_key, payload) = reseal_slot(vault_file, index, username, password, login_key, SlotKdf::generate(params))?;
+    if rekey_filler {
+        for (other, slot) in vault_file.slots.iter_mut().enumerate() {
+            if other != index {
+                *slot = decoy::filler_slot(username, Some(params));
+            }
+        }
+    }
+    store.write(vault_file).ok()?;
+    let _ = generation::record(&payload.id, payload.generation);
+    reseal_login(username, login_key, &new_key, other.as_ref());
+    Some(new_key)
+}
+
+/// Re-seal everything kept under the login key after the key changed.
+fn reseal_login(username: &str, old_key: &VaultKey, new_key: &VaultKey, other: Option<&Other>) {
+    // The named vaults are listed under the login key
+    if vaults::reseal(username, old_key, new_key).is_err() {
+        print_out!("Failed to re-seal the vault list for user: {}", username);
+    }
+    if share::reseal(username, old_key, new_key).is_err() {
+        print_out!("Failed to re-seal the sharing keys for user: {}", username);
+    }
+    if let Some(other) = other {
+        if companion::reseal(username, old_key, new_key, other).is_err() {
+            print_out!("Failed to re-seal the slot records for user: {}", username);
+        }
+    }
+}
+
+/// Generation of the next write: past both the loaded and the last seen generation
+fn next_generation(payload: &Payload) -> u64 {
+    let seen = generation::last_seen(&payload.id).unwrap_or_default();
+    payload.generation.max(seen) + 1
+}
+
+fn store_error_code(error: &StoreError) -> u32 {
+    match error {
+        StoreError::Backend(_) => PassKeeperError::FileWriteFailed as u32,
+        StoreError::Payload(_) => PassKeeperError::IntegrityCheckFailed as u32,
+        StoreError::Encryption => PassKeeperError::EncryptionFailed as u32,
+        StoreError::Busy => PassKeeperError::VaultBusy as u32,
+    }
+}
+
+/// Record the generation of a finished write
+fn record_write(secret_data: &SecretData, generation: u64) {
+    if let Err(e) = generation::record(&secret_data.payload.id, generation) {
+        print_out!("Failed to record the vault generation: {}", e);
+    }
+}
+
+/// Encrypt one entry into the slot of the secret data, keeping the other entries as they are
+fn save_entry(cache: &SecretCache, name: &str, store: &dyn VaultStore, secret_data: &SecretData, entry: &Value, vault_key: &VaultKey) -> u32 {
+    apply_entries(cache, name, store, secret_data, std::slice::from_ref(entry), &[], vault_key)
+}
+
+/// Delete one entry from the slot of the secret data
+fn delete_entry(cache: &SecretCache, name: &str, store: &dyn VaultStore, secret_data: &SecretData, entry_id: &str, vault_key: &VaultKey) -> u32 {
+    apply_entries(cache, name, store, secret_data, &[], &[entry_id.to_string()], vault_key)
+}
+
+/// Delete the entries with the ids in `deletes` and save the entries in `upserts`, as one write.
+/// The cached vault gets the same changes, and the operation being recorded for undo the entries.
+fn apply_entries(cache: &SecretCache, name: &str, store: &dyn VaultStore, secret_data: &SecretData, upserts: &[Value], deletes: &[String], vault_key: &VaultKey) -> u32 {
+    if upserts.is_empty() && deletes.is_empty() {
+        return PassKeeperError::Success as u32;
+    }
+    let generation = next_generation(&secret_data.payload);
+    match store.apply(secret_data.slot, vault_key, upserts, deletes, generation) {
+        Ok(()) => {
+            record_write(secret_data, generation);
+            let vault_id = split_store_name(name).1;
+            let before = |id: &str| secret_data.payload.entries.iter().find(|e| vault::entry_id(e) == id).cloned();
+            for id in deletes {
+                undo::record(Change::Record { vault_id: vault_id.clone(), id: id.clone(), before: before(id), after: None });
+            }
+            for entry in upserts {
+                let id = vault::entry_id(entry);
+                undo::record(Change::Record { vault_id: vault_id.clone(), id: id.to_string(), before: before(id), after: Some(entry.clone()) });
+            }
+            cache.update(name, store.stamp(), |cached| {
+                let entries = &mut cached.payload.entries;
+                for id in deletes {
+                    let before = entries.iter().find(|e| vault::entry_id(e) == id).cloned();
+                    entries.retain(|e| vault::entry_id(e) != id);
+                    cached.journal.record(id, before, None);
+                }
+                for entry in upserts {
+                    let id = vault::entry_id(entry);
+                    let before = match entries.iter_mut().find(|e| vault::entry_id(e) == id) {
+                        Some(existing) => Some(std::mem::replace(existing, entry.clone())),
+                        None => {
+                            entries.push(entry.clone());
+                            None
+                        },
+                    };
+                    cached.journal.record(id, before, Some(entry.clone()));
+                }
+                cached.payload.generation = generation;
+                cached.data = to_string(&cached.payload.entries).unwrap_or_default();
+            });
+            PassKeeperError::Success as u32
+        },
+        Err(e) => {
+            print_out!("Failed to write the entries: {}", e);
+            store_error_code(&e)
+        }
+    }
+}
+
+/// Tell the frontend that a vault failed its integrity check
+fn emit_integrity_warning(name: &str, integrity: Integrity) {
+    print_out!("Integrity check failed for vault {}: {:?}", name, integrity);
+    let (username, vault_id) = split_store_name(name);
+    if let Some(app_handle) = APP_HANDLE.get() {
+        let _ = app_handle.emit("vault-integrity", json!({
+            "username": username,
+            "vault": vault_id,
+            "integrity": integrity,
+        }));
+    }
+}
+
+/// The secret data of an unlocked vault: cached, or loaded from the store
+/// when nothing is cached or the storage changed since it was cached.
+/// A storage changed by someone else is merged with the changes of this session.
+fn load_secret_data(cache: &SecretCache, name: &str, store: &dyn VaultStore, vault_key: &VaultKey) -> SecretData {
+    let skey = make_skey(vault_key);
+    cache.load(name, store.stamp(), |lookup| {
+        let stale = match lookup {
+            Lookup::Fresh(secret_data) if secret_data.skey == skey => return (secret_data, None),
+            Lookup::Stale(secret_data) if secret_data.skey == skey => Some(secret_data),
+            _ => None,
+        };
+        let mut secret_data = get_secret_data(store, vault_key);
+        // Only a readable vault is worth keeping. A stale one stays cached with its changes.
+        if secret_data.is_skey_empty() || secret_data.is_unreadable() {
+            return (secret_data, None);
+        }
+        // A rolled back vault is not merged, the user decides about it first
+        if let Some(stale) = stale.filter(|_| secret_data.check_integrity_quietly()) {
+            merge_external_changes(name, store, vault_key, stale, &mut secret_data);
+        }
+        (secret_data.clone(), Some(secret_data))
+    })
+}
+
+/// Merge a vault changed by someone else with the changes of this session,
+/// write back the changes the other writer overwrote, and tell the frontend
+fn merge_external_changes(name: &str, store: &dyn VaultStore, vault_key: &VaultKey, cached: SecretData, secret_data: &mut SecretData) {
+    let mut journal = cached.journal;
+    let theirs = std::mem::take(&mut secret_data.payload.entries);
+    let mut merge = sync::merge(&mut journal, &cached.payload.entries, theirs);
+    for (id, entry) in &merge.reapply {
+        let generation = next_generation(&secret_data.payload);
+        let result = match entry {
+            Some(entry) => store.save_entry(secret_data.slot, vault_key, entry, generation),
+            None => store.delete_entry(secret_data.slot, vault_key, id, generation),
+        };
+        match result {
+            Ok(()) => {
+                record_write(secret_data, generation);
+                secret_data.payload.generation = generation;
+            },
+            Err(e) => print_out!("Failed to write back the entry {}: {}", id, e),
         }
+    }
+    secret_data.payload.entries = std::mem::take(&mut merge.entries);
+    secret_data.data = to_string(&secret_data.payload.entries).unwrap_or_default();
+    secret_data.journal = journal;
+    if merge.is_unchanged() {
+        return;
+    }
+    print_out!("Merged the vault {} changed on disk: {} added, {} updated, {} removed, {} conflicts",
+        name, merge.added, merge.updated, merge.removed, merge.conflicts.len());
+    let (username, vault_id) = split_store_name(name);
+    if let Some(app_handle) = APP_HANDLE.get() {
+        let _ = app_handle.emit("vault-changed", json!({
+            "username": username,
+            "vault": vault_id,
+            "added": merge.added,
+            "updated": merge.updated,
+            "removed": merge.removed,
+            "conflicts": merge.conflicts,
+        }));
+    }
+}
+
+/// Reload a vault of an unlocked user after its storage changed on disk.
+/// A team vault is reloaded for the first unlocked member.
+fn on_vault_file_changed(name: &str) {
+    let Some(app_handle) = APP_HANDLE.get() else {
+        return;
+    };
+    let vault = match split_store_name(name) {
+        ("", vault_id) => session::unlocked_users()
+            .iter()
+            .find_map(|username| open_vault(username, Some(&vault_id)).ok()),
+        (username, vault_id) => open_vault(username, Some(&vault_id)).ok(),
+    };
+    let Some(vault) = vault else {
+        return;
     };
-    print_out!("Encrypted data line: [{}]", encrypted_data);
-    let base64_decoded = general_purpose::STANDARD.decode(encrypted_data);
-    let decrypted_data = decrypt_data(&base64_decoded.unwrap_or_default());
-    print_out!("Decryption result: [{:?}]", decrypted_data);
-    let decrypted_line = match decrypted_data {
-        Ok(dl) => dl,
+    let cache = app_handle.state::<SecretCache>();
+    let secret_data = load_secret_data(&cache, &vault.name, vault.store.as_ref(), &vault.key);
+    if !secret_data.is_skey_empty() {
+        let _ = secret_data.check_integrity(&vault.name);
+    }
+}
+
+/// Retrieve the secret data of the slot opened by the vault key
+fn get_secret_data(store: &dyn VaultStore, vault_key: &VaultKey) -> SecretData {
+    let mut secret_data =SecretData::new();
+    // Read the stored slots
+    let vault_file = match store.read() {
+        Ok(vf) => vf,
         Err(_) => {
             return secret_data;
         }
     };
+    // The slot whose key hash matches the vault key
+    let skey = make_skey(vault_key);
+    let slot = match vault_file.find_slot(&skey) {
+        Some(index) => index,
+        None => {
+            return secret_data;
+        }
+    };
+    secret_data.skey = skey;
+    secret_data.slot = slot;
+    // The payload holds the encrypted json entries. Every vault has one, even when empty.
+    if vault_file.slots[slot].payload.is_empty() {
+        secret_data.integrity = Integrity::Corrupted;
+        return secret_data;
+    }
+    // The cipher tags authenticate the entries together with the vault id and generation
+    let mut payload = match store.load(slot, vault_key) {
+        Ok(payload) => payload,
+        Err(StoreError::Payload(PayloadError::Malformed)) => {
+            secret_data.integrity = Integrity::Corrupted;
+            return secret_data;
+        },
+        Err(StoreError::Payload(PayloadError::Unauthenticated)) => {
+            secret_data.integrity = Integrity::Tampered;
+            return secret_data;
+        },
+        Err(_) => {
+            return SecretData::new();
+        },
+    };
+    // Once the key opened a versioned payload, a payload without that id is an older copy
+    let known = generation::known_vault(vault_key);
+    secret_data.integrity = match known {
+        None if payload.id.is_empty() => Integrity::Unversioned,
+        Some(known) if known != payload.id => Integrity::RolledBack {
+            expected: generation::last_seen(&known).unwrap_or_default(),
+            found: payload.generation,
+        },
+        _ => match generation::last_seen(&payload.id) {
+            Some(seen) if payload.generation < seen => Integrity::RolledBack {
+                expected: seen,
+                found: payload.generation,
+            },
+            _ => {
+                // First sight of the vault on this machine, or a newer write
+                let _ = generation::record(&payload.id, payload.generation);
+                let _ = generation::record_vault(vault_key, &payload.id);
+                Integrity::Verified
+            },
+        },
+    };
+    // Write payloads of older formats again, so every entry has an id the store can address.
+    // A rolled back payload stays as it is until the user accepts it.
+    if payload.outdated && secret_data.check_integrity_quietly() {
+        let mut upgraded = payload.next(payload.entries.clone());
+        upgraded.generation = next_generation(&payload);
+        if store.replace(slot, vault_key, &upgraded).is_ok() {
+            let _ = generation::record(&upgraded.id, upgraded.generation);
+            let _ = generation::record_vault(vault_key, &upgraded.id);
+            payload = upgraded;
+        }
+    }
 
-    print_out!("Decrypted data: [{}]", decrypted_line);
-    secret_data.data = decrypted_line;
+    print_out!("Decrypted payload: generation {}, {} entries", payload.generation, payload.entries.len());
+    secret_data.data = to_string(&payload.entries).unwrap_or_default();
+    secret_data.payload = payload;
     secret_data
 }
 
 /// Get window position from registry
+#[cfg(windows)]
 #[allow(unused_assignments)]
 fn get_window_pos() -> (i32, i32, i32, i32) {
     // Declare variables without initial values
@@ -528,6 +3264,7 @@ fn get_window_pos() -> (i32, i32, i32, i32) {
 }
 
 /// Set window position to registry
+#[cfg(windows)]
 fn set_window_pos(left: i32, top: i32, right: i32, bottom: i32) {
     let _ = registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_POS);
 
@@ -540,6 +3277,7 @@ fn set_window_pos(left: i32, top: i32, right: i32, bottom: i32) {
 }
 
 /// Register window position on move/resize
+#[cfg(windows)]
 fn reg_position() {
     if let Some(app_handle) = APP_HANDLE.get() {
         if let Some(window) = app_handle.get_webview_window("main") {
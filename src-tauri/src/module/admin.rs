@@ -0,0 +1,341 @@
+//! # Administration Module
+//!
+//! `admin` keeps the optional administration of the accounts of a shared PC:
+//! the administrators, the accounts they disabled and the policies every account follows.
+//!
+//! Everything lives in `data/admin.json`, signed by the administrator who changed it last with
+//! the key of their identity (see `share`). A file whose signature fails is refused rather than
+//! obeyed, so disabling an account cannot be undone by editing the file. Without the file there
+//! are no administrators and no restrictions, and the first user to claim the role gets it.
+//! Like the roles of team vaults, this is enforced by PassKeeper only: a user who can delete
+//! files in `data` can delete the administration as well.
+//!
+//! With key escrow on, the login key of an account is wrapped for every administrator in
+//! `data/<username>.escrow`, so an administrator can reset a forgotten master password.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    env::current_dir,
+};
+use serde::{Deserialize, Serialize};
+use super::{
+    lock,
+    session::VaultKey,
+    share::{self, ShareError, WrappedKey},
+    vault,
+};
+
+/// Why an administration operation failed.
+#[derive(Debug)]
+pub enum AdminError {
+    /// The administration file is not signed by an administrator
+    Tampered,
+    /// The user is not an administrator
+    NotAdmin,
+    /// There is an administrator already
+    AlreadyClaimed,
+    /// The last administrator cannot step down, and administrators cannot disable themselves
+    LastAdmin,
+    /// The login key of the account is not escrowed for the administrator
+    NoEscrow,
+    /// Wrapping, opening or signing failed
+    Share(ShareError),
+    /// Reading or writing a file failed
+    Io(io::Error),
+}
+
+impl From<io::Error> for AdminError {
+    fn from(e: io::Error) -> Self {
+        AdminError::Io(e)
+    }
+}
+
+impl From<ShareError> for AdminError {
+    fn from(e: ShareError) -> Self {
+        AdminError::Share(e)
+    }
+}
+
+/// Rules every account follows. The defaults restrict nothing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    /// Only administrators create accounts
+    pub admin_only_registration: bool,
+    /// Shortest master password, in characters
+    pub min_password_length: u32,
+    /// Kinds of characters a master password mixes: lower case, upper case, digits and symbols
+    pub min_character_classes: u32,
+    /// Minutes without input before an open vault locks. `0` never locks.
+    pub auto_lock_minutes: u32,
+    /// Escrow the login keys of the accounts for the administrators
+    pub key_escrow: bool,
+}
+
+impl Policy {
+    /// Returns `true` if a master password meets the policy.
+    pub fn accepts_password(&self, password: &str) -> bool {
+        let classes = [
+            password.chars().any(|c| c.is_lowercase() || (c.is_alphabetic() && !c.is_uppercase())),
+            password.chars().any(char::is_uppercase),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        password.chars().count() >= self.min_password_length as usize
+            && classes.iter().filter(|has| **has).count() >= self.min_character_classes as usize
+    }
+}
+
+/// What the administrators decided about an account.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountState {
+    pub disabled: bool,
+    /// The `skey` of the password an administrator reset the account to.
+    /// The password is to be changed while a slot still has it.
+    pub reset_skey: Option<String>,
+}
+
+/// The administration file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub admins: Vec<String>,
+    pub policy: Policy,
+    pub accounts: BTreeMap<String, AccountState>,
+    /// The administrator who signed the file
+    signer: String,
+    /// Base64 of the signature of the signer over the file without it
+    signature: String,
+}
+
+impl AdminConfig {
+    pub fn is_admin(&self, username: &str) -> bool {
+        self.admins.iter().any(|admin| admin == username)
+    }
+
+    pub fn account(&self, username: &str) -> AccountState {
+        self.accounts.get(username).cloned().unwrap_or_default()
+    }
+
+    pub fn is_disabled(&self, username: &str) -> bool {
+        self.account(username).disabled
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        let unsigned = AdminConfig { signature: String::new(), ..self.clone() };
+        serde_json::to_vec(&unsigned).unwrap_or_default()
+    }
+
+    fn verify(&self) -> Result<(), AdminError> {
+        if !self.is_admin(&self.signer) {
+            return Err(AdminError::Tampered);
+        }
+        share::verify(&self.signer, &self.signed_message(), &self.signature).map_err(|_| AdminError::Tampered)
+    }
+}
+
+fn config_path() -> String {
+    format!("{}/data/admin.json", current_dir().unwrap().display())
+}
+
+fn escrow_path(username: &str) -> String {
+    format!("{}/data/{}.escrow", current_dir().unwrap().display(), username)
+}
+
+// Reads and verifies the file, under the lock the caller holds
+fn read_config(path: &str) -> Result<AdminConfig, AdminError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(AdminConfig::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let config: AdminConfig = serde_json::from_str(&content).map_err(|_| AdminError::Tampered)?;
+    config.verify()?;
+    Ok(config)
+}
+
+// Signs the file as an administrator and writes it, under the lock the caller holds
+fn write_config(path: &str, admin: &str, login_key: &VaultKey, config: &mut AdminConfig) -> Result<(), AdminError> {
+    config.signer = admin.to_string();
+    config.signature = share::sign(admin, login_key, &config.signed_message())?;
+    fs::create_dir_all(current_dir()?.join("data"))?;
+    vault::write_atomic(path, serde_json::to_string_pretty(config).unwrap_or_default())?;
+    Ok(())
+}
+
+/// The administration in force. The defaults if nobody claimed the role yet.
+pub fn load() -> Result<AdminConfig, AdminError> {
+    let path = config_path();
+    match lock::shared(&path) {
+        Ok(_lock) => read_config(&path),
+        // No data directory yet, so no administration either
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(AdminConfig::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Makes a user the first administrator.
+pub fn claim(username: &str, login_key: &VaultKey) -> Result<AdminConfig, AdminError> {
+    share::ensure_identity(username, login_key)?;
+    let path = config_path();
+    let _lock = lock::exclusive(&path)?;
+    let mut config = read_config(&path)?;
+    if !config.admins.is_empty() {
+        return Err(AdminError::AlreadyClaimed);
+    }
+    config.admins.push(username.to_string());
+    write_config(&path, username, login_key, &mut config)?;
+    Ok(config)
+}
+
+/// Changes the administration as an administrator and signs it again.
+pub fn update<F>(admin: &str, login_key: &VaultKey, change: F) -> Result<AdminConfig, AdminError>
+where
+    F: FnOnce(&mut AdminConfig) -> Result<(), AdminError>,
+{
+    let path = config_path();
+    let _lock = lock::exclusive(&path)?;
+    let mut config = read_config(&path)?;
+    if !config.is_admin(admin) {
+        return Err(AdminError::NotAdmin);
+    }
+    change(&mut config)?;
+    // The file is signed by the administrator changing it, so they cannot step down themselves
+    if !config.is_admin(admin) {
+        return Err(AdminError::LastAdmin);
+    }
+    write_config(&path, admin, login_key, &mut config)?;
+    Ok(config)
+}
+
+fn read_escrow(path: &str) -> io::Result<BTreeMap<String, WrappedKey>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_default()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Wraps the login key of an account for every administrator.
+/// With `replace` the key of an earlier password is dropped, otherwise only the missing
+/// administrators get it.
+pub fn escrow(config: &AdminConfig, username: &str, login_key: &VaultKey, replace: bool) -> Result<(), AdminError> {
+    if !config.policy.key_escrow || config.admins.is_empty() {
+        return Ok(());
+    }
+    let path = escrow_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut wrapped = if replace { BTreeMap::new() } else { read_escrow(&path)? };
+    wrapped.retain(|admin, _| config.is_admin(admin));
+    let missing: Vec<&String> = config.admins.iter().filter(|admin| !wrapped.contains_key(*admin)).collect();
+    if missing.is_empty() && !replace {
+        return Ok(());
+    }
+    for admin in missing {
+        // An administrator without an identity gets the key once they have one
+        if let Some(identity) = share::public_identity(admin) {
+            wrapped.insert(admin.clone(), share::wrap_key(&identity, login_key)?);
+        }
+    }
+    fs::write(&path, serde_json::to_string(&wrapped).unwrap_or_default())?;
+    Ok(())
+}
+
+/// Returns `true` if the login key of an account is escrowed for an administrator.
+pub fn is_escrowed(username: &str, admin: &str) -> bool {
+    read_escrow(&escrow_path(username)).is_ok_and(|wrapped| wrapped.contains_key(admin))
+}
+
+/// The escrowed login key of an account, opened by an administrator.
+pub fn escrowed_key(username: &str, admin: &str, login_key: &VaultKey) -> Result<VaultKey, AdminError> {
+    let path = escrow_path(username);
+    let wrapped = {
+        let _lock = lock::shared(&path)?;
+        read_escrow(&path)?
+    };
+    let key = wrapped.get(admin).ok_or(AdminError::NoEscrow)?;
+    Ok(share::unwrap_key(admin, login_key, key)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::module::{
+        testing::{data_dir, login_key, username},
+        vault::new_entry_id,
+    };
+    use super::*;
+
+    fn account(name: &str) -> (String, VaultKey) {
+        data_dir();
+        let username = username(name);
+        let key = login_key();
+        share::create_identities(&username, &key, None).unwrap();
+        (username, key)
+    }
+
+    // A signed file of its own, so the tests leave the administration in force alone
+    fn signed_config(admin: &str, login_key: &VaultKey) -> (String, AdminConfig) {
+        let path = format!("{}/data/admin-{}.json", current_dir().unwrap().display(), new_entry_id());
+        let mut config = AdminConfig { admins: vec![admin.to_string()], ..AdminConfig::default() };
+        write_config(&path, admin, login_key, &mut config).unwrap();
+        (path, config)
+    }
+
+    #[test]
+    fn a_signed_file_verifies() {
+        let (admin, admin_key) = account("admin");
+        let (path, config) = signed_config(&admin, &admin_key);
+        let read = read_config(&path).unwrap();
+        assert!(read.is_admin(&admin));
+        assert_eq!(read.policy, config.policy);
+    }
+
+    #[test]
+    fn a_tampered_file_is_refused() {
+        let (admin, admin_key) = account("admin");
+        let (path, mut config) = signed_config(&admin, &admin_key);
+        config.accounts.entry(admin.clone()).or_default().disabled = true;
+        fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+        assert!(matches!(read_config(&path), Err(AdminError::Tampered)));
+    }
+
+    #[test]
+    fn a_file_signed_by_someone_else_is_refused() {
+        let (admin, admin_key) = account("admin");
+        let (mallory, mallory_key) = account("mallory");
+        let (_, mut config) = signed_config(&admin, &admin_key);
+        config.policy.key_escrow = true;
+        // Signed with the key of another user in the name of the administrator
+        config.signature = share::sign(&mallory, &mallory_key, &config.signed_message()).unwrap();
+        assert!(matches!(config.verify(), Err(AdminError::Tampered)));
+        // Signed by a user who is no administrator
+        config.signer = mallory.clone();
+        config.signature = share::sign(&mallory, &mallory_key, &config.signed_message()).unwrap();
+        assert!(matches!(config.verify(), Err(AdminError::Tampered)));
+    }
+
+    #[test]
+    fn escrowed_keys_open_for_administrators_only() {
+        let (admin, admin_key) = account("admin");
+        let (user, user_key) = account("user");
+        let (_, mut config) = signed_config(&admin, &admin_key);
+        config.policy.key_escrow = true;
+        escrow(&config, &user, &user_key, true).unwrap();
+        assert!(is_escrowed(&user, &admin));
+        assert_eq!(escrowed_key(&user, &admin, &admin_key).unwrap(), user_key);
+        assert!(matches!(escrowed_key(&user, &user, &user_key), Err(AdminError::NoEscrow)));
+    }
+
+    #[test]
+    fn the_policy_counts_length_and_character_classes() {
+        let policy = Policy { min_password_length: 8, min_character_classes: 3, ..Policy::default() };
+        assert!(policy.accepts_password("Passw0rd"));
+        assert!(policy.accepts_password("비밀번호12!!"));
+        assert!(!policy.accepts_password("Pw0!"));
+        assert!(!policy.accepts_password("password1"));
+        assert!(Policy::default().accepts_password(""));
+    }
+}
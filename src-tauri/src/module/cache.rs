@@ -0,0 +1,78 @@
+//! # Vault Cache Module
+//!
+//! `cache` keeps the decrypted vaults of unlocked users in memory, so commands do not
+//! read and decrypt the storage again on every call.
+//!
+//! Every cached vault remembers the [`Stamp`] of its storage. A lookup with a different
+//! stamp means the storage was changed by someone else. The stale vault stays cached
+//! until it is replaced, since it may hold changes to merge with the storage.
+//! Looking up and loading a vault happen under one lock, so a vault loaded from an older
+//! storage never replaces one a write has updated meanwhile.
+
+use std::{collections::HashMap, sync::Mutex};
+use super::store::Stamp;
+
+struct Cached<T> {
+    stamp: Stamp,
+    value: T,
+}
+
+/// Result of a cache lookup.
+pub enum Lookup<T> {
+    /// Cached, and the storage is unchanged
+    Fresh(T),
+    /// Cached, but the storage changed since
+    Stale(T),
+    Missing,
+}
+
+/// Decrypted vaults by store name. Kept in Tauri managed state.
+pub struct VaultCache<T> {
+    vaults: Mutex<HashMap<String, Cached<T>>>,
+}
+
+impl<T> Default for VaultCache<T> {
+    fn default() -> Self {
+        VaultCache {
+            vaults: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> VaultCache<T> {
+    /// Looks up the cached vault, with whether the storage still has the stamp it was loaded with,
+    /// and hands it to `load`. A vault `load` returns besides its result is cached with the stamp.
+    /// The cache stays locked until then, so `load` must not use it.
+    pub fn load<R, F: FnOnce(Lookup<T>) -> (R, Option<T>)>(&self, name: &str, stamp: Stamp, load: F) -> R {
+        let mut vaults = self.vaults.lock().unwrap();
+        let lookup = match vaults.get(name) {
+            Some(cached) if cached.stamp == stamp => Lookup::Fresh(cached.value.clone()),
+            Some(cached) => Lookup::Stale(cached.value.clone()),
+            None => Lookup::Missing,
+        };
+        let (result, loaded) = load(lookup);
+        if let Some(value) = loaded {
+            vaults.insert(name.to_string(), Cached { stamp, value });
+        }
+        result
+    }
+
+    /// Applies a change already written to storage, and takes the new stamp of the storage.
+    /// Does nothing if the vault is not cached.
+    pub fn update<F: FnOnce(&mut T)>(&self, name: &str, stamp: Stamp, change: F) {
+        if let Some(cached) = self.vaults.lock().unwrap().get_mut(name) {
+            change(&mut cached.value);
+            cached.stamp = stamp;
+        }
+    }
+
+    /// Drops the cached vault, e.g. when the vault is locked.
+    pub fn evict(&self, name: &str) {
+        self.vaults.lock().unwrap().remove(name);
+    }
+
+    /// Drops every cached vault whose name matches, e.g. all vaults of a user.
+    pub fn evict_matching<F: Fn(&str) -> bool>(&self, matches: F) {
+        self.vaults.lock().unwrap().retain(|name, _| !matches(name));
+    }
+}
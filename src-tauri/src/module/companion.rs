@@ -0,0 +1,172 @@
+//! # Companion Module
+//!
+//! `companion` remembers, for every login of an account, what the other slot of its data file
+//! holds, so a login that re-derives its key can give the other slot the same KDF parameters.
+//!
+//! `data/<username>.slots` holds one record per slot, sealed with the login key and padded to
+//! the same length, in random order. A data file without a duress password gets a record
+//! sealed with a key nobody keeps, so the file looks the same either way.
+//! A record never holds a password: the decoy slot is re-keyed by the duress login itself.
+
+use std::{
+    env::current_dir,
+    fs,
+    io,
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use super::{
+    crypto::mycrypt::{decrypt_data, encrypt_data, random_bytes},
+    lock,
+    session::VaultKey,
+    vault::{self, SLOT_COUNT},
+};
+
+// Records are padded to a multiple of this many bytes before sealing
+const RECORD_BLOCK: usize = 256;
+
+/// What the other slot holds, as known to a login.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Other {
+    /// Random filler
+    Filler,
+    /// The decoy vault of the duress password
+    Decoy,
+    /// The vault of a login this one cannot re-key
+    Real,
+}
+
+fn slots_path(username: &str) -> String {
+    format!("{}/data/{}.slots", current_dir().unwrap().display(), username)
+}
+
+// Reads the records, under the lock the caller holds
+fn read_records(path: &str) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_default()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_records(path: &str, mut records: Vec<String>) -> io::Result<()> {
+    // The order tells nothing about which login wrote a record
+    records.sort();
+    vault::write_atomic(path, serde_json::to_string(&records).unwrap_or_default())
+}
+
+fn seal_record(login_key: &VaultKey, other: &Other) -> Option<String> {
+    let mut plain = Zeroizing::new(serde_json::to_string(other).ok()?);
+    let padded = plain.len().div_ceil(RECORD_BLOCK).max(1) * RECORD_BLOCK;
+    while plain.len() < padded {
+        plain.push(' ');
+    }
+    let encrypted = encrypt_data(login_key, &plain).ok()?;
+    Some(general_purpose::STANDARD.encode(encrypted))
+}
+
+fn open_record(login_key: &VaultKey, record: &str) -> Option<Other> {
+    open_plain(login_key, record).and_then(|plain| serde_json::from_str(&plain).ok())
+}
+
+fn open_plain(login_key: &VaultKey, record: &str) -> Option<Zeroizing<String>> {
+    let encrypted = general_purpose::STANDARD.decode(record).ok()?;
+    Some(Zeroizing::new(decrypt_data(login_key, &encrypted).ok()?))
+}
+
+// Records written before the decoy re-keyed itself kept the duress password
+fn holds_password(login_key: &VaultKey, record: &str) -> bool {
+    open_plain(login_key, record)
+        .and_then(|plain| serde_json::from_str::<serde_json::Value>(&plain).ok())
+        .is_some_and(|value| value.get("password").is_some())
+}
+
+/// A record nobody opens.
+fn filler_record() -> Option<String> {
+    seal_record(&Zeroizing::new(random_bytes::<32>()), &Other::Filler)
+}
+
+/// Writes the records of a new account: what the real login knows of the other slot, and
+/// for the duress login that the other slot is real.
+pub fn create(username: &str, login_key: &VaultKey, other: &Other, duress_key: Option<&VaultKey>) -> io::Result<()> {
+    let path = slots_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let sealing_failed = || io::Error::other("sealing failed");
+    let mut records = vec![seal_record(login_key, other).ok_or_else(sealing_failed)?];
+    let duress_record = match duress_key {
+        Some(key) => seal_record(key, &Other::Real),
+        None => filler_record(),
+    };
+    records.push(duress_record.ok_or_else(sealing_failed)?);
+    write_records(&path, records)
+}
+
+/// What the login knows of the other slot, `None` for accounts created before the records.
+/// A record still holding the duress password is sealed again without it.
+pub fn other(username: &str, login_key: &VaultKey) -> Option<Other> {
+    let path = slots_path(username);
+    let records = lock::shared(&path).and_then(|_lock| read_records(&path)).ok()?;
+    let record = records.iter().find(|record| open_record(login_key, record).is_some())?;
+    let other = open_record(login_key, record)?;
+    if holds_password(login_key, record) {
+        let _ = reseal(username, login_key, login_key, &other);
+    }
+    Some(other)
+}
+
+/// Seals the record of a login again after its key changed, with what it now knows of the
+/// other slot. The records of the other logins stay as they are.
+pub fn reseal(username: &str, old_key: &VaultKey, new_key: &VaultKey, other: &Other) -> io::Result<()> {
+    let path = slots_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut records = read_records(&path)?;
+    let Some(record) = records.iter_mut().find(|record| open_record(old_key, record).is_some()) else {
+        return Ok(());
+    };
+    *record = seal_record(new_key, other).ok_or_else(|| io::Error::other("sealing failed"))?;
+    write_records(&path, records)
+}
+
+/// Keeps the record of the login, now that the other slot is filler, and replaces the
+/// records of the other logins with records nobody opens. Used once the other logins are wiped.
+pub fn remove_foreign(username: &str, login_key: &VaultKey) -> io::Result<()> {
+    let path = slots_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let sealing_failed = || io::Error::other("sealing failed");
+    let mut records = vec![seal_record(login_key, &Other::Filler).ok_or_else(sealing_failed)?];
+    while records.len() < SLOT_COUNT {
+        records.push(filler_record().ok_or_else(sealing_failed)?);
+    }
+    write_records(&path, records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_hide_their_content() {
+        let key = Zeroizing::new([3u8; 32]);
+        let records = [
+            seal_record(&key, &Other::Filler).unwrap(),
+            seal_record(&key, &Other::Real).unwrap(),
+            seal_record(&key, &Other::Decoy).unwrap(),
+            filler_record().unwrap(),
+        ];
+        assert!(records.iter().all(|record| record.len() == records[0].len()));
+        assert_eq!(open_record(&key, &records[2]), Some(Other::Decoy));
+        assert_eq!(open_record(&key, &records[3]), None);
+        assert!(!holds_password(&key, &records[2]));
+    }
+
+    #[test]
+    fn legacy_decoy_records_are_read_without_the_password() {
+        let key = Zeroizing::new([5u8; 32]);
+        let plain = r#"{"kind":"decoy","password":"correct horse","wipe":true}"#;
+        let record = general_purpose::STANDARD.encode(encrypt_data(&key, plain).unwrap());
+        assert_eq!(open_record(&key, &record), Some(Other::Decoy));
+        assert!(holds_password(&key, &record));
+    }
+}
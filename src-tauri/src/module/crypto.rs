@@ -2,29 +2,177 @@
 
 /// Encryption/decryption and hashing utilities
 pub mod mycrypt {
-    pub fn encrypt_data(data: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let buffer = data.as_bytes().to_vec();
+    use aes_gcm::{
+        aead::{Aead, AeadCore, KeyInit, OsRng},
+        Aes256Gcm,
+        Key,
+        Nonce,
+    };
 
-        // This is a placeholder for actual encryption logic.
+    // AES-GCM uses a 96-bit nonce, stored in front of the ciphertext.
+    const NONCE_LEN: usize = 12;
 
-        Ok(buffer.to_vec())
+    /// Encrypts the data with AES-256-GCM.
+    /// The output is `nonce || ciphertext || tag`.
+    pub fn encrypt_data(key: &[u8; 32], data: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        encrypt_bytes(key, data.as_bytes())
     }
 
-    pub fn decrypt_data(encrypted_data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
-        let buffer = encrypted_data.to_vec();
-        
-        // This is a placeholder for actual decryption logic.
-
+    /// Decrypts the data produced by [`encrypt_data`].
+    pub fn decrypt_data(key: &[u8; 32], encrypted_data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        let buffer = decrypt_bytes(key, encrypted_data)?;
         Ok(String::from_utf8(buffer)?)
     }
+
+    /// Encrypts raw bytes with AES-256-GCM.
+    pub fn encrypt_bytes(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = cipher.encrypt(&nonce, data)
+            .map_err(|_| "encryption failed")?;
+
+        let mut buffer = Vec::with_capacity(NONCE_LEN + encrypted.len());
+        buffer.extend_from_slice(&nonce);
+        buffer.extend_from_slice(&encrypted);
+        Ok(buffer)
+    }
+
+    /// Decrypts raw bytes produced by [`encrypt_bytes`].
+    pub fn decrypt_bytes(key: &[u8; 32], encrypted_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if encrypted_data.len() < NONCE_LEN {
+            return Err("encrypted data is too short".into());
+        }
+        let (nonce, encrypted) = encrypted_data.split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+        let buffer = cipher.decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| "decryption failed")?;
+        Ok(buffer)
+    }
+
+    /// Fills a buffer with random bytes from the operating system.
+    pub fn random_bytes<const N: usize>() -> [u8; N] {
+        use aes_gcm::aead::rand_core::RngCore;
+        let mut buffer = [0u8; N];
+        OsRng.fill_bytes(&mut buffer);
+        buffer
+    }
 }
 
 /// Hashing utilities
 pub mod myhash {
+    use sha2::{Digest, Sha512};
+
+    /// SHA-512 hash of a string, as lowercase hex.
     pub fn hash_data(data: &str) -> String {
-        
-        // This is a placeholder for actual hashing logic.
+        hash_bytes(data.as_bytes())
+    }
+
+    /// SHA-512 hash of raw bytes, as lowercase hex.
+    pub fn hash_bytes(data: &[u8]) -> String {
+        hex::encode(Sha512::digest(data))
+    }
+}
+
+/// Key derivation utilities
+pub mod mykdf {
+    use std::time::Instant;
+    use argon2::{Algorithm, Argon2, Params, Version};
+    use common::define;
+    use serde::{Deserialize, Serialize};
+    use zeroize::Zeroizing;
+
+    /// Argon2id cost parameters.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct KdfParams {
+        pub memory_kib: u32,
+        pub iterations: u32,
+        pub parallelism: u32,
+    }
+
+    impl Default for KdfParams {
+        /// The fixed parameters used before calibration existed.
+        fn default() -> Self {
+            KdfParams {
+                memory_kib: define::KDF_MEMORY_KIB,
+                iterations: define::KDF_ITERATIONS,
+                parallelism: define::KDF_PARALLELISM,
+            }
+        }
+    }
+
+    impl KdfParams {
+        /// Returns `true` if any cost is lower than the floor.
+        pub fn is_below(&self, floor: &KdfParams) -> bool {
+            self.memory_kib < floor.memory_kib
+                || self.iterations < floor.iterations
+                || self.parallelism < floor.parallelism
+        }
+
+        /// Returns `true` if Argon2 accepts the parameters and no cost is above the limits,
+        /// so a derivation cannot exhaust the memory or run for hours.
+        pub fn is_valid(&self) -> bool {
+            self.memory_kib <= define::KDF_MAX_MEMORY_KIB
+                && self.iterations <= define::KDF_MAX_ITERATIONS
+                && self.parallelism <= define::KDF_MAX_PARALLELISM
+                && Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32)).is_ok()
+        }
+
+        /// Raises every cost to at least the floor.
+        pub fn at_least(&self, floor: &KdfParams) -> KdfParams {
+            KdfParams {
+                memory_kib: self.memory_kib.max(floor.memory_kib),
+                iterations: self.iterations.max(floor.iterations),
+                parallelism: self.parallelism.max(floor.parallelism),
+            }
+        }
+    }
+
+    /// Derives a 256-bit key from a password with Argon2id and the default parameters.
+    pub fn derive_key(password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, Box<dyn std::error::Error>> {
+        derive_key_with(password, salt, &KdfParams::default())
+    }
+
+    /// Derives a 256-bit key from a password with Argon2id.
+    pub fn derive_key_with(password: &str, salt: &[u8], kdf_params: &KdfParams) -> Result<Zeroizing<[u8; 32]>, Box<dyn std::error::Error>> {
+        let params = Params::new(
+            kdf_params.memory_kib,
+            kdf_params.iterations,
+            kdf_params.parallelism,
+            Some(32),
+        ).map_err(|e| e.to_string())?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        argon2.hash_password_into(password.as_bytes(), salt, key.as_mut())
+            .map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+
+    /// Picks memory and iteration costs so that one derivation takes about `target_ms` on this machine.
+    /// The result is never below `floor`.
+    pub fn calibrate(target_ms: u64, floor: &KdfParams) -> KdfParams {
+        let mut params = *floor;
+        let target_ms = target_ms.max(1) as f64;
+        let time_once = |params: &KdfParams| -> f64 {
+            let started = Instant::now();
+            let _ = derive_key_with("PassKeeper calibration", b"PassKeeper:calibrate", params);
+            (started.elapsed().as_millis() as f64).max(1.0)
+        };
 
-        data.to_string()
+        // Grow the memory first, it is the cost that hurts attackers most
+        let mut elapsed = time_once(&params);
+        while elapsed < target_ms && params.memory_kib < define::KDF_MAX_MEMORY_KIB {
+            let factor = (target_ms / elapsed).clamp(1.25, 4.0);
+            params.memory_kib = ((params.memory_kib as f64 * factor) as u32).min(define::KDF_MAX_MEMORY_KIB);
+            elapsed = time_once(&params);
+        }
+        // Then add iterations if the memory limit was reached
+        if elapsed < target_ms {
+            let factor = target_ms / elapsed;
+            params.iterations = ((params.iterations as f64 * factor).round() as u32)
+                .max(params.iterations)
+                .min(define::KDF_MAX_ITERATIONS);
+        }
+        params.at_least(floor)
     }
 }
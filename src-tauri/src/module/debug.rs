@@ -25,10 +25,11 @@ unsafe extern "system" {
 
 /// Using the `OutputDebugString` API.
 /// This function is not available on non-Windows platforms.
+/// Prints only in debug builds.
 /// Reference [`OutputDebugStringW`](https://docs.microsoft.com/en-us/windows/win32/api/debugapi/nf-debugapi-outputdebugstringw).
 #[allow(dead_code)]
 pub fn make_print_out(s: &str) {
-    #[cfg(windows)]
+    #[cfg(all(windows, debug_assertions))]
     {
         let len = s.encode_utf16().count() + 1;
         let mut s_utf16: Vec<u16> = Vec::with_capacity(len);
@@ -41,19 +42,24 @@ pub fn make_print_out(s: &str) {
             //OutputDebugStringW(&s_utf16[0]);
         }
     }
-    #[cfg(not(windows))]
+    #[cfg(all(not(windows), debug_assertions))]
+    {
+        println!("{}", s);
+    }
+    // Release builds print nothing, the messages may name entries and vaults
+    #[cfg(not(debug_assertions))]
     {
-        //let _ = s;
-        println!(s);
+        let _ = s;
     }
 }
 
 /// Using the `OutputDebugString` API.
 /// This function is not available on non-Windows platforms.
+/// Prints only in debug builds.
 /// Reference [`OutputDebugStringW`](https://docs.microsoft.com/en-us/windows/win32/api/debugapi/nf-debugapi-outputdebugstringw).
 #[allow(dead_code)]
 pub fn make_eprint_out(file: &str, line: u32, /*column: u32,*/ s: &str) {
-    #[cfg(windows)]
+    #[cfg(all(windows, debug_assertions))]
     {
         let strip_s = s.strip_prefix(">>").unwrap_or(s).trim();
         //let es = format!("[{}({}:{})] {}", file, line, column, strip_s);
@@ -66,10 +72,13 @@ pub fn make_eprint_out(file: &str, line: u32, /*column: u32,*/ s: &str) {
             OutputDebugStringW(&s_utf16[0]);
         }
     }
-    #[cfg(not(windows))]
+    #[cfg(all(not(windows), debug_assertions))]
+    {
+        eprintln!("[{}({})] {}", file, line, s);
+    }
+    #[cfg(not(debug_assertions))]
     {
-        //let _ = s;
-        eprintln!(s);
+        let _ = (file, line, s);
     }
 }
 
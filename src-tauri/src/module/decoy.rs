@@ -0,0 +1,107 @@
+//! # Decoy Module
+//!
+//! `decoy` fills the vault a duress password opens with a few everyday logins, so it does not
+//! stand out as a fresh empty vault. Filler slots are sealed from the same kind of payload with
+//! a key nobody keeps, so a data file looks the same with or without a duress password.
+
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
+use super::{
+    crypto::{mycrypt::random_bytes, mykdf::KdfParams},
+    timestamps,
+    vault::{self, new_entry_id, Payload, Slot},
+};
+
+// Services a decoy vault picks its logins from: name, login URL and whether the account is an email
+const SERVICES: &[(&str, &str, bool)] = &[
+    ("네이버", "https://nid.naver.com", false),
+    ("카카오", "https://accounts.kakao.com", true),
+    ("Google", "https://accounts.google.com", true),
+    ("쿠팡", "https://login.coupang.com", true),
+    ("Netflix", "https://www.netflix.com/login", true),
+    ("YouTube Premium", "https://www.youtube.com", true),
+    ("배달의민족", "https://www.baemin.com", false),
+    ("11번가", "https://login.11st.co.kr", false),
+    ("인스타그램", "https://www.instagram.com", false),
+    ("Microsoft", "https://login.live.com", true),
+];
+const MIN_ENTRIES: usize = 3;
+const MAX_ENTRIES: usize = 6;
+const PASSWORD_CHARS: &[u8] = b"abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789!@#$%";
+// Oldest entry, in days before the vault is made
+const MAX_AGE_DAYS: u64 = 730;
+
+/// A random number below `bound`.
+fn below(bound: usize) -> usize {
+    u32::from_le_bytes(random_bytes::<4>()) as usize % bound
+}
+
+fn password() -> String {
+    let length = 10 + below(7);
+    (0..length).map(|_| PASSWORD_CHARS[below(PASSWORD_CHARS.len())] as char).collect()
+}
+
+/// A few logins of `username` at common services, created at random times in the past.
+pub fn entries(username: &str) -> Vec<Value> {
+    let count = MIN_ENTRIES + below(MAX_ENTRIES - MIN_ENTRIES + 1);
+    let mut services: Vec<&(&str, &str, bool)> = SERVICES.iter().collect();
+    let now = timestamps::now();
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (service, url, email) = services.remove(below(services.len()));
+        let account = if *email { format!("{}@gmail.com", username) } else { username.to_string() };
+        let created = now - below(MAX_AGE_DAYS as usize * 86_400) as u64;
+        entries.push(json!({
+            "id": new_entry_id(),
+            "service": service,
+            "account": account,
+            "password": password(),
+            "urls": [url],
+            (timestamps::CREATED): created,
+            (timestamps::MODIFIED): created,
+            (timestamps::PASSWORD_CHANGED): created,
+        }));
+    }
+    entries.sort_by_key(|entry| timestamps::get(entry, timestamps::CREATED));
+    entries
+}
+
+/// A new vault holding the logins of [`entries`].
+pub fn payload(username: &str) -> Payload {
+    Payload { entries: entries(username), ..Payload::new() }
+}
+
+/// A filler slot: a decoy vault sealed with a key nobody keeps, under a random key hash.
+pub fn filler_slot(username: &str, params: Option<KdfParams>) -> Slot {
+    let throwaway = Zeroizing::new(random_bytes::<32>());
+    let mut slot = vault::filler_slot(params);
+    if let Ok(sealed) = payload(username).seal(&throwaway) {
+        slot.payload = sealed;
+    }
+    slot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_distinct_logins() {
+        for _ in 0..20 {
+            let entries = entries("tester");
+            assert!((MIN_ENTRIES..=MAX_ENTRIES).contains(&entries.len()));
+            for (index, entry) in entries.iter().enumerate() {
+                assert!(entry["account"].as_str().unwrap().starts_with("tester"));
+                assert!(entry["password"].as_str().unwrap().len() >= 10);
+                assert!(entries[..index].iter().all(|other| other["service"] != entry["service"]));
+            }
+        }
+    }
+
+    #[test]
+    fn filler_has_a_chunk_per_entry() {
+        let slot = filler_slot("tester", None);
+        let chunks = slot.payload.split('.').count();
+        assert!((MIN_ENTRIES + 1..=MAX_ENTRIES + 1).contains(&chunks));
+    }
+}
@@ -0,0 +1,193 @@
+//! # Entry Module
+//!
+//! `entry` describes the fields of a vault entry beyond its service, account and password:
+//! login URLs, multiline notes, tags and custom fields such as security-question answers.
+//!
+//! Entries stay free-form json objects in the vault. A field is only written when it has a
+//! value, so entries saved with the three original fields load and save unchanged.
+//! Typed entries such as payment cards also carry their `type` and `data`, see [`super::types`].
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use super::types::{EntryType, TypeError};
+
+/// Most URLs an entry holds
+pub const MAX_URLS: usize = 16;
+/// Most tags an entry holds
+pub const MAX_TAGS: usize = 32;
+/// Most custom fields an entry holds
+pub const MAX_CUSTOM_FIELDS: usize = 64;
+/// Longest note, in characters
+pub const MAX_NOTES_LEN: usize = 10_000;
+/// Longest URL, tag, field name or field value, in characters
+pub const MAX_FIELD_LEN: usize = 2_048;
+
+/// Why the fields of an entry were refused.
+#[derive(Debug, PartialEq)]
+pub enum FieldError {
+    /// A URL has white space in it
+    InvalidUrl,
+    /// A custom field has no name
+    EmptyName,
+    /// Two custom fields have the same name
+    DuplicateName,
+    /// Too many values, or a value too long
+    TooLong,
+    /// A value of a typed entry is missing or invalid
+    Type(TypeError),
+}
+
+/// How a custom field is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    #[default]
+    Text,
+    /// Masked like the password, e.g. the answer to a security question
+    Hidden,
+}
+
+/// A named value of an entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomField {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub kind: FieldKind,
+}
+
+/// The fields of an entry besides service, account and password.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntryFields {
+    pub urls: Vec<String>,
+    pub notes: String,
+    pub tags: Vec<String>,
+    /// Stored as `fields`
+    #[serde(rename = "fields")]
+    pub custom: Vec<CustomField>,
+    /// Stored as `type`, left out for logins
+    #[serde(rename = "type")]
+    pub entry_type: EntryType,
+    /// The values of a typed entry, checked against its template
+    pub data: Map<String, Value>,
+}
+
+impl EntryFields {
+    /// The fields of a stored entry. Missing or unreadable fields are empty.
+    pub fn of(entry: &Value) -> Self {
+        serde_json::from_value(entry.clone()).unwrap_or_default()
+    }
+
+    /// Trims the values, drops empty ones and repeated tags, and checks the limits.
+    pub fn normalize(self) -> Result<Self, FieldError> {
+        let too_long = |value: &str| value.chars().count() > MAX_FIELD_LEN;
+        let urls: Vec<String> = self.urls.iter()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        if urls.iter().any(|url| url.chars().any(char::is_whitespace)) {
+            return Err(FieldError::InvalidUrl);
+        }
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+            if !tags.iter().any(|known| known.to_lowercase() == tag.to_lowercase()) {
+                tags.push(tag.to_string());
+            }
+        }
+        let notes = self.notes.replace("\r\n", "\n");
+        let notes = notes.trim_end().to_string();
+        let mut custom: Vec<CustomField> = Vec::new();
+        for field in self.custom {
+            let name = field.name.trim().to_string();
+            if name.is_empty() {
+                return Err(FieldError::EmptyName);
+            }
+            if custom.iter().any(|known| known.name == name) {
+                return Err(FieldError::DuplicateName);
+            }
+            custom.push(CustomField { name, ..field });
+        }
+        if urls.len() > MAX_URLS || tags.len() > MAX_TAGS || custom.len() > MAX_CUSTOM_FIELDS
+            || notes.chars().count() > MAX_NOTES_LEN
+            || urls.iter().chain(tags.iter()).any(|value| too_long(value))
+            || custom.iter().any(|field| too_long(&field.name) || too_long(&field.value))
+        {
+            return Err(FieldError::TooLong);
+        }
+        let data = self.entry_type.normalize(&self.data).map_err(FieldError::Type)?;
+        if data.values().any(|value| value.as_str().is_some_and(too_long)) {
+            return Err(FieldError::TooLong);
+        }
+        Ok(EntryFields { urls, notes, tags, custom, entry_type: self.entry_type, data })
+    }
+
+    /// Writes the fields into an entry. Empty fields are removed from it.
+    pub fn apply(&self, entry: &mut Value) {
+        let Some(object) = entry.as_object_mut() else {
+            return;
+        };
+        let mut set = |key: &str, value: Value, empty: bool| {
+            if empty {
+                object.remove(key);
+            } else {
+                object.insert(key.to_string(), value);
+            }
+        };
+        set("urls", serde_json::json!(self.urls), self.urls.is_empty());
+        set("notes", Value::String(self.notes.clone()), self.notes.is_empty());
+        set("tags", serde_json::json!(self.tags), self.tags.is_empty());
+        set("fields", serde_json::json!(self.custom), self.custom.is_empty());
+        set("type", serde_json::json!(self.entry_type), self.entry_type == EntryType::Login);
+        set("data", Value::Object(self.data.clone()), self.data.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn a_legacy_entry_loads_and_saves_unchanged() {
+        let legacy = json!({ "service": "mail", "account": "me", "password": "secret" });
+        let fields = EntryFields::of(&legacy).normalize().unwrap();
+        assert_eq!(fields, EntryFields::default());
+        let mut saved = legacy.clone();
+        fields.apply(&mut saved);
+        assert_eq!(saved, legacy);
+    }
+
+    #[test]
+    fn fields_round_trip_and_empty_ones_are_removed() {
+        let mut entry = json!({ "service": "mail", "account": "me", "password": "secret", "notes": "old" });
+        let fields = EntryFields {
+            urls: vec![" https://mail.example.com ".to_string(), String::new()],
+            tags: vec!["Work".to_string(), "work".to_string()],
+            custom: vec![CustomField { name: " pet ".to_string(), value: "rex".to_string(), kind: FieldKind::Hidden }],
+            ..EntryFields::default()
+        }.normalize().unwrap();
+        fields.apply(&mut entry);
+        assert_eq!(entry, json!({
+            "service": "mail", "account": "me", "password": "secret",
+            "urls": ["https://mail.example.com"],
+            "tags": ["Work"],
+            "fields": [{ "name": "pet", "value": "rex", "kind": "hidden" }],
+        }));
+        assert_eq!(EntryFields::of(&entry), fields);
+    }
+
+    #[test]
+    fn invalid_fields_are_refused() {
+        let url = EntryFields { urls: vec!["https://a b".to_string()], ..EntryFields::default() };
+        assert_eq!(url.normalize(), Err(FieldError::InvalidUrl));
+        let field = |name: &str| CustomField { name: name.to_string(), value: String::new(), kind: FieldKind::Text };
+        let unnamed = EntryFields { custom: vec![field(" ")], ..EntryFields::default() };
+        assert_eq!(unnamed.normalize(), Err(FieldError::EmptyName));
+        let twice = EntryFields { custom: vec![field("pin"), field("pin ")], ..EntryFields::default() };
+        assert_eq!(twice.normalize(), Err(FieldError::DuplicateName));
+        let notes = EntryFields { notes: "x".repeat(MAX_NOTES_LEN + 1), ..EntryFields::default() };
+        assert_eq!(notes.normalize(), Err(FieldError::TooLong));
+    }
+}
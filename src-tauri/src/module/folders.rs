@@ -0,0 +1,192 @@
+//! # Folders Module
+//!
+//! `folders` organizes the entries of a vault in nested folders.
+//!
+//! A folder is a record of the vault like an entry, `{id, kind: "folder", name, parent}`,
+//! so it is encrypted, synchronized and merged the same way. An entry names its folder in
+//! `folder`. Entries and folders without one are at the top of the vault.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use super::{
+    trash,
+    vault::{entry_id, new_entry_id},
+};
+
+/// Id of the top of the vault, holding what is in no folder
+pub const ROOT_FOLDER: &str = "root";
+/// `kind` of a folder record
+pub const FOLDER_KIND: &str = "folder";
+/// Longest folder name, in characters
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Why a folder operation failed.
+#[derive(Debug, PartialEq)]
+pub enum FolderError {
+    NotFound,
+    /// The name is empty or too long
+    InvalidName,
+    /// A folder with the same name is in the same parent
+    DuplicateName,
+    /// A folder cannot move into itself or into one of its subfolders
+    Cycle,
+}
+
+/// A folder with what it holds, as returned for the tree of a vault
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderNode {
+    pub id: String,
+    pub name: String,
+    pub folders: Vec<FolderNode>,
+    pub entries: Vec<Value>,
+}
+
+/// Returns `true` if a vault record is a folder rather than an entry.
+pub fn is_folder(record: &Value) -> bool {
+    record["kind"] == FOLDER_KIND
+}
+
+/// The folder an entry or folder is in, `ROOT_FOLDER` at the top.
+pub fn parent_of(record: &Value) -> &str {
+    let key = if is_folder(record) { "parent" } else { "folder" };
+    record[key].as_str().filter(|id| !id.is_empty()).unwrap_or(ROOT_FOLDER)
+}
+
+/// Puts an entry or folder into a folder. `ROOT_FOLDER` takes it out of any.
+pub fn set_parent(record: &mut Value, folder_id: &str) {
+    let key = if is_folder(record) { "parent" } else { "folder" };
+    if let Some(object) = record.as_object_mut() {
+        if folder_id == ROOT_FOLDER {
+            object.remove(key);
+        } else {
+            object.insert(key.to_string(), Value::String(folder_id.to_string()));
+        }
+    }
+}
+
+/// The folder records of a vault.
+pub fn folders(records: &[Value]) -> impl Iterator<Item = &Value> {
+    records.iter().filter(|record| is_folder(record))
+}
+
+/// The entries of a vault, without its folders and the entries in the trash.
+pub fn entries(records: &[Value]) -> impl Iterator<Item = &Value> {
+    records.iter().filter(|record| !is_folder(record) && !trash::is_trashed(record))
+}
+
+/// Returns `true` if the folder exists. The top of the vault always does.
+pub fn exists(records: &[Value], folder_id: &str) -> bool {
+    folder_id == ROOT_FOLDER || folders(records).any(|folder| entry_id(folder) == folder_id)
+}
+
+/// A trimmed folder name, if it is valid and no other folder in the parent has it.
+pub fn check_name(records: &[Value], name: &str, parent: &str, except: Option<&str>) -> Result<String, FolderError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(FolderError::InvalidName);
+    }
+    let taken = folders(records).any(|folder| {
+        parent_of(folder) == parent
+            && Some(entry_id(folder)) != except
+            && folder["name"].as_str().is_some_and(|other| other.to_lowercase() == name.to_lowercase())
+    });
+    if taken {
+        return Err(FolderError::DuplicateName);
+    }
+    Ok(name.to_string())
+}
+
+/// A name like `name` that no folder in the parent has, e.g. `name (2)`.
+pub fn unique_name(records: &[Value], name: &str, parent: &str, except: Option<&str>) -> String {
+    let mut candidate = name.to_string();
+    let mut number = 2;
+    while check_name(records, &candidate, parent, except) == Err(FolderError::DuplicateName) {
+        candidate = format!("{} ({})", name, number);
+        number += 1;
+    }
+    candidate
+}
+
+/// A new folder record.
+pub fn new_folder(name: &str, parent: &str) -> Value {
+    let mut folder = json!({
+        "id": new_entry_id(),
+        "kind": FOLDER_KIND,
+        "name": name,
+    });
+    set_parent(&mut folder, parent);
+    folder
+}
+
+/// The ids of a folder and of every folder below it.
+pub fn subtree(records: &[Value], folder_id: &str) -> Vec<String> {
+    let mut ids = vec![folder_id.to_string()];
+    let mut index = 0;
+    while index < ids.len() {
+        let parent = ids[index].clone();
+        // Concurrent moves on two devices can leave a cycle, which is walked once
+        for folder in folders(records).filter(|folder| parent_of(folder) == parent) {
+            let id = entry_id(folder).to_string();
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        index += 1;
+    }
+    ids
+}
+
+/// Checks that a folder can move into another one.
+pub fn check_move(records: &[Value], folder_id: &str, parent: &str) -> Result<(), FolderError> {
+    if !exists(records, folder_id) || folder_id == ROOT_FOLDER || !exists(records, parent) {
+        return Err(FolderError::NotFound);
+    }
+    if subtree(records, folder_id).iter().any(|id| id == parent) {
+        return Err(FolderError::Cycle);
+    }
+    Ok(())
+}
+
+/// The entries directly in a folder. Entries whose folder is gone are at the top.
+pub fn contents(records: &[Value], folder_id: &str) -> Vec<Value> {
+    entries(records)
+        .filter(|entry| parent_of(entry) == folder_id
+            || (folder_id == ROOT_FOLDER && !exists(records, parent_of(entry))))
+        .cloned()
+        .collect()
+}
+
+/// A folder with its subfolders and entries, sorted by name. Folders whose parent is gone
+/// are shown at the top.
+pub fn tree(records: &[Value], folder_id: &str) -> Option<FolderNode> {
+    build_tree(records, folder_id, &mut Vec::new())
+}
+
+fn build_tree(records: &[Value], folder_id: &str, visited: &mut Vec<String>) -> Option<FolderNode> {
+    let name = match folders(records).find(|folder| entry_id(folder) == folder_id) {
+        Some(folder) => folder["name"].as_str().unwrap_or_default().to_string(),
+        None if folder_id == ROOT_FOLDER => String::new(),
+        None => return None,
+    };
+    if visited.iter().any(|id| id == folder_id) {
+        return None;
+    }
+    visited.push(folder_id.to_string());
+    let orphan = |record: &Value| folder_id == ROOT_FOLDER && !exists(records, parent_of(record));
+    let children: Vec<&Value> = folders(records)
+        .filter(|folder| parent_of(folder) == folder_id || orphan(folder))
+        .collect();
+    let mut node = FolderNode {
+        id: folder_id.to_string(),
+        name,
+        folders: children.into_iter()
+            .filter_map(|folder| build_tree(records, entry_id(folder), visited))
+            .collect(),
+        entries: entries(records)
+            .filter(|entry| parent_of(entry) == folder_id || orphan(entry))
+            .cloned()
+            .collect(),
+    };
+    node.folders.sort_by_key(|folder| folder.name.to_lowercase());
+    Some(node)
+}
@@ -0,0 +1,162 @@
+//! # Generation Store Module
+//!
+//! `generation` remembers the last seen write generation of every vault, keyed by the
+//! random vault id kept inside the encrypted payload.
+//!
+//! It also remembers, for every vault key that opened a versioned payload, the id of that
+//! vault, so a payload without an id or with another id is known to be an older copy.
+//! Those records go by a hash of the vault key and tell nothing about the data file or slot.
+//!
+//! The store lives outside the `data` directory, so copying an older data file back
+//! in place does not roll back the record with it. The rollback policy is kept there too.
+//! Windows keeps it in the registry, other platforms in `~/.passkeeper`.
+
+use std::io;
+use zeroize::Zeroizing;
+use super::{
+    crypto::myhash::hash_bytes,
+    session::VaultKey,
+};
+
+#[cfg(windows)]
+mod imp {
+    use std::io;
+    use common::define;
+    use crate::module::registry::{self, HKEY_CURRENT_USER};
+
+    pub fn read(vault_id: &str) -> Option<u64> {
+        registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_GENERATIONS).ok()?;
+        registry::read_string(vault_id).ok()?.parse().ok()
+    }
+
+    pub fn write(vault_id: &str, generation: u64) -> io::Result<()> {
+        registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_GENERATIONS)?;
+        registry::write_string(vault_id, &generation.to_string())
+    }
+
+    pub fn read_vault(marker: &str) -> Option<String> {
+        registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_VAULT_IDS).ok()?;
+        registry::read_string(marker).ok().filter(|id| !id.is_empty())
+    }
+
+    pub fn write_vault(marker: &str, vault_id: &str) -> io::Result<()> {
+        registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_VAULT_IDS)?;
+        registry::write_string(marker, vault_id)
+    }
+
+    pub fn read_refuse_rollback() -> Option<bool> {
+        registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_SETTINGS).ok()?;
+        registry::read_dword("RefuseRollback").ok().map(|value| value != 0)
+    }
+
+    pub fn write_refuse_rollback(refuse: bool) -> io::Result<()> {
+        registry::check_or_create_key(HKEY_CURRENT_USER, define::REG_SUBKEY_SETTINGS)?;
+        registry::write_dword("RefuseRollback", refuse as u32)
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use std::{collections::HashMap, env, fs, io, path::PathBuf};
+    use serde::{de::DeserializeOwned, Serialize};
+
+    fn store_path(file: &str) -> PathBuf {
+        let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+        home.join(".passkeeper").join(file)
+    }
+
+    fn load<T: DeserializeOwned>(file: &str) -> HashMap<String, T> {
+        fs::read_to_string(store_path(file))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn insert<T: Serialize + DeserializeOwned>(file: &str, key: &str, value: T) -> io::Result<()> {
+        let path = store_path(file);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut values = load(file);
+        values.insert(key.to_string(), value);
+        fs::write(path, serde_json::to_string_pretty(&values)?)
+    }
+
+    pub fn read(vault_id: &str) -> Option<u64> {
+        load("generations.json").get(vault_id).copied()
+    }
+
+    pub fn write(vault_id: &str, generation: u64) -> io::Result<()> {
+        insert("generations.json", vault_id, generation)
+    }
+
+    pub fn read_vault(marker: &str) -> Option<String> {
+        load::<String>("vaults.json").remove(marker).filter(|id| !id.is_empty())
+    }
+
+    pub fn write_vault(marker: &str, vault_id: &str) -> io::Result<()> {
+        insert("vaults.json", marker, vault_id.to_string())
+    }
+
+    pub fn read_refuse_rollback() -> Option<bool> {
+        load("policy.json").get("refuse_rollback").copied()
+    }
+
+    pub fn write_refuse_rollback(refuse: bool) -> io::Result<()> {
+        insert("policy.json", "refuse_rollback", refuse)
+    }
+}
+
+// The name of the record of a vault key. It differs from the key hash in the data file.
+fn marker(vault_key: &VaultKey) -> String {
+    let mut input = Zeroizing::new(b"generation:".to_vec());
+    input.extend_from_slice(vault_key.as_ref());
+    hash_bytes(&input)
+}
+
+/// The last seen generation of a vault, `None` if the vault was never seen on this machine.
+pub fn last_seen(vault_id: &str) -> Option<u64> {
+    imp::read(vault_id)
+}
+
+/// Records a generation. The record never goes backwards.
+pub fn record(vault_id: &str, generation: u64) -> io::Result<()> {
+    if last_seen(vault_id).is_some_and(|seen| seen >= generation) {
+        return Ok(());
+    }
+    imp::write(vault_id, generation)
+}
+
+/// Overwrites the record, e.g. after the user accepted a restored backup.
+pub fn reset(vault_id: &str, generation: u64) -> io::Result<()> {
+    imp::write(vault_id, generation)
+}
+
+/// The id of the vault the key last opened, `None` until it opened a versioned payload.
+pub fn known_vault(vault_key: &VaultKey) -> Option<String> {
+    imp::read_vault(&marker(vault_key))
+}
+
+/// Records the id of the versioned payload the key opened.
+pub fn record_vault(vault_key: &VaultKey, vault_id: &str) -> io::Result<()> {
+    let marker = marker(vault_key);
+    if imp::read_vault(&marker).as_deref() == Some(vault_id) {
+        return Ok(());
+    }
+    imp::write_vault(&marker, vault_id)
+}
+
+/// Forgets the vault of the key, e.g. after the user accepted a restored backup without an id.
+pub fn forget_vault(vault_key: &VaultKey) -> io::Result<()> {
+    imp::write_vault(&marker(vault_key), "")
+}
+
+/// Whether a vault older than its last seen generation is refused, instead of only warned about.
+pub fn refuses_rollback() -> bool {
+    imp::read_refuse_rollback().unwrap_or(true)
+}
+
+/// Sets the rollback policy.
+pub fn set_refuse_rollback(refuse: bool) -> io::Result<()> {
+    imp::write_refuse_rollback(refuse)
+}
@@ -0,0 +1,226 @@
+//! # Hangul Module
+//!
+//! `hangul` takes Korean text apart the way it is typed, so search can match it while it is
+//! still being composed, by its initial consonants, or typed with the wrong keyboard layout.
+//!
+//! A syllable such as `네` is decomposed (NFD) into its jamo `ㄴㅔ`, and compound vowels and
+//! final consonants into the keys they are typed with, `ㅘ` into `ㅗㅏ` and `ㄳ` into `ㄱㅅ`.
+//! Jamo are written as compatibility jamo (U+3131..U+318E) whether the text holds precomposed
+//! syllables, conjoining jamo or jamo typed alone, so `넹` is the start of `네이버`.
+//! Keys map between the 2-set (dubeolsik) Korean layout and QWERTY.
+
+/// Initial consonants, in syllable order
+const INITIALS: [char; 19] = [
+    'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
+];
+/// Vowels, in syllable order
+const MEDIALS: [char; 21] = [
+    'ㅏ', 'ㅐ', 'ㅑ', 'ㅒ', 'ㅓ', 'ㅔ', 'ㅕ', 'ㅖ', 'ㅗ', 'ㅘ', 'ㅙ', 'ㅚ', 'ㅛ', 'ㅜ', 'ㅝ', 'ㅞ', 'ㅟ', 'ㅠ', 'ㅡ', 'ㅢ', 'ㅣ',
+];
+/// Final consonants, in syllable order after the syllable without one
+const FINALS: [char; 27] = [
+    'ㄱ', 'ㄲ', 'ㄳ', 'ㄴ', 'ㄵ', 'ㄶ', 'ㄷ', 'ㄹ', 'ㄺ', 'ㄻ', 'ㄼ', 'ㄽ', 'ㄾ', 'ㄿ', 'ㅀ', 'ㅁ',
+    'ㅂ', 'ㅄ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
+];
+/// QWERTY keys of the jamo on the 2-set layout. Shifted keys type the double consonants.
+const LAYOUT: [(char, char); 33] = [
+    ('q', 'ㅂ'), ('w', 'ㅈ'), ('e', 'ㄷ'), ('r', 'ㄱ'), ('t', 'ㅅ'), ('y', 'ㅛ'), ('u', 'ㅕ'), ('i', 'ㅑ'),
+    ('o', 'ㅐ'), ('p', 'ㅔ'), ('a', 'ㅁ'), ('s', 'ㄴ'), ('d', 'ㅇ'), ('f', 'ㄹ'), ('g', 'ㅎ'), ('h', 'ㅗ'),
+    ('j', 'ㅓ'), ('k', 'ㅏ'), ('l', 'ㅣ'), ('z', 'ㅋ'), ('x', 'ㅌ'), ('c', 'ㅊ'), ('v', 'ㅍ'), ('b', 'ㅠ'),
+    ('n', 'ㅜ'), ('m', 'ㅡ'), ('Q', 'ㅃ'), ('W', 'ㅉ'), ('E', 'ㄸ'), ('R', 'ㄲ'), ('T', 'ㅆ'), ('O', 'ㅒ'),
+    ('P', 'ㅖ'),
+];
+
+const SYLLABLE_FIRST: u32 = 0xAC00;
+const SYLLABLE_COUNT: u32 = 11172;
+const CONJOINING_INITIAL: u32 = 0x1100;
+const CONJOINING_MEDIAL: u32 = 0x1161;
+const CONJOINING_FINAL: u32 = 0x11A8;
+
+/// A character as it reads: a syllable, precomposed or written in conjoining jamo, or any other
+/// character, with the characters of the text it spans.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub start: usize,
+    pub end: usize,
+    /// The precomposed syllable, the compatibility jamo or the character itself
+    pub text: char,
+    /// The initial consonant of a syllable, or the consonant itself
+    pub initial: Option<char>,
+}
+
+/// Returns `true` for a consonant typed alone, such as `ㄴ`.
+pub fn is_consonant(c: char) -> bool {
+    ('ㄱ'..='ㅎ').contains(&c)
+}
+
+/// Returns `true` for Hangul a search takes apart: syllables and jamo.
+pub fn is_hangul(c: char) -> bool {
+    decompose(c).is_some() || conjoining(c).is_some() || ('ㄱ'..='ㅣ').contains(&c)
+}
+
+/// The indexes of the initial, vowel and final (`0` for none) of a precomposed syllable.
+fn decompose(c: char) -> Option<(usize, usize, usize)> {
+    let index = (c as u32).checked_sub(SYLLABLE_FIRST).filter(|index| *index < SYLLABLE_COUNT)?;
+    Some(((index / 588) as usize, (index % 588 / 28) as usize, (index % 28) as usize))
+}
+
+fn compose(initial: usize, medial: usize, last: usize) -> char {
+    char::from_u32(SYLLABLE_FIRST + (initial * 588 + medial * 28 + last) as u32).unwrap_or_default()
+}
+
+/// The compatibility form of a conjoining jamo.
+fn conjoining(c: char) -> Option<char> {
+    let code = c as u32;
+    let index = |first: u32, count: usize| code.checked_sub(first).map(|i| i as usize).filter(|i| *i < count);
+    index(CONJOINING_INITIAL, INITIALS.len()).map(|i| INITIALS[i])
+        .or_else(|| index(CONJOINING_MEDIAL, MEDIALS.len()).map(|i| MEDIALS[i]))
+        .or_else(|| index(CONJOINING_FINAL, FINALS.len()).map(|i| FINALS[i]))
+}
+
+/// The keys a compound vowel or final consonant is typed with, e.g. `ㅗㅏ` for `ㅘ`.
+fn keys_of(jamo: char) -> Option<[char; 2]> {
+    let keys = match jamo {
+        'ㅘ' => ['ㅗ', 'ㅏ'],
+        'ㅙ' => ['ㅗ', 'ㅐ'],
+        'ㅚ' => ['ㅗ', 'ㅣ'],
+        'ㅝ' => ['ㅜ', 'ㅓ'],
+        'ㅞ' => ['ㅜ', 'ㅔ'],
+        'ㅟ' => ['ㅜ', 'ㅣ'],
+        'ㅢ' => ['ㅡ', 'ㅣ'],
+        'ㄳ' => ['ㄱ', 'ㅅ'],
+        'ㄵ' => ['ㄴ', 'ㅈ'],
+        'ㄶ' => ['ㄴ', 'ㅎ'],
+        'ㄺ' => ['ㄹ', 'ㄱ'],
+        'ㄻ' => ['ㄹ', 'ㅁ'],
+        'ㄼ' => ['ㄹ', 'ㅂ'],
+        'ㄽ' => ['ㄹ', 'ㅅ'],
+        'ㄾ' => ['ㄹ', 'ㅌ'],
+        'ㄿ' => ['ㄹ', 'ㅍ'],
+        'ㅀ' => ['ㄹ', 'ㅎ'],
+        'ㅄ' => ['ㅂ', 'ㅅ'],
+        _ => return None,
+    };
+    Some(keys)
+}
+
+/// The letters of a text, Hangul taken apart into the jamo it is typed with, each with the
+/// index of the character it comes from. Other characters are kept as they are.
+pub fn letters(chars: &[char]) -> Vec<(char, usize)> {
+    let mut letters = Vec::with_capacity(chars.len());
+    for (index, c) in chars.iter().enumerate() {
+        let jamo = match decompose(*c) {
+            Some((initial, medial, last)) => {
+                let mut jamo = vec![INITIALS[initial], MEDIALS[medial]];
+                jamo.extend((last > 0).then(|| FINALS[last - 1]));
+                jamo
+            },
+            None => vec![conjoining(*c).unwrap_or(*c)],
+        };
+        for letter in jamo {
+            match keys_of(letter) {
+                Some(keys) => letters.extend(keys.map(|key| (key, index))),
+                None => letters.push((letter, index)),
+            }
+        }
+    }
+    letters
+}
+
+/// The characters of a text as they read, syllables in conjoining jamo composed.
+pub fn units(chars: &[char]) -> Vec<Unit> {
+    let position = |c: char, first: u32, count: usize| (c as u32).checked_sub(first).map(|i| i as usize).filter(|i| *i < count);
+    let mut units = Vec::with_capacity(chars.len());
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let initial = position(c, CONJOINING_INITIAL, INITIALS.len());
+        let medial = chars.get(index + 1).and_then(|c| position(*c, CONJOINING_MEDIAL, MEDIALS.len()));
+        if let (Some(initial), Some(medial)) = (initial, medial) {
+            let last = chars.get(index + 2).and_then(|c| position(*c, CONJOINING_FINAL, FINALS.len()));
+            let end = index + if last.is_some() { 3 } else { 2 };
+            let text = compose(initial, medial, last.map_or(0, |last| last + 1));
+            units.push(Unit { start: index, end, text, initial: Some(INITIALS[initial]) });
+            index = end;
+            continue;
+        }
+        let unit = match decompose(c) {
+            Some((initial, ..)) => Unit { start: index, end: index + 1, text: c, initial: Some(INITIALS[initial]) },
+            None => {
+                let text = conjoining(c).unwrap_or(c);
+                Unit { start: index, end: index + 1, text, initial: is_consonant(text).then_some(text) }
+            },
+        };
+        units.push(unit);
+        index += 1;
+    }
+    units
+}
+
+/// What a text typed on QWERTY keys reads as on the Korean layout, e.g. `ㄴㅔㅇㅣㅂㅓ` for
+/// `spdlqj`. Left as jamo, since search compares jamo. `None` without any Latin letter.
+pub fn to_korean_keys(text: &str) -> Option<String> {
+    if !text.chars().any(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let jamo = |key: char| LAYOUT.iter().find(|(k, _)| *k == key).map(|(_, jamo)| *jamo);
+    Some(text.chars().map(|c| jamo(c).or_else(|| jamo(c.to_ascii_lowercase())).unwrap_or(c)).collect())
+}
+
+/// The QWERTY keys a Korean text is typed with, e.g. `naver` for `ㅜㅁㅍㄷㄱ`.
+/// `None` without any Hangul.
+pub fn to_latin_keys(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if !chars.iter().any(|c| is_hangul(*c)) {
+        return None;
+    }
+    let key = |letter: char| LAYOUT.iter().find(|(_, jamo)| *jamo == letter).map(|(key, _)| key.to_ascii_lowercase());
+    Some(letters(&chars).into_iter().map(|(letter, _)| key(letter).unwrap_or(letter)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn letter_text(text: &str) -> String {
+        letters(&chars(text)).into_iter().map(|(letter, _)| letter).collect()
+    }
+
+    #[test]
+    fn letters_are_the_keys_typed() {
+        assert_eq!(letters(&chars("네이")), [('ㄴ', 0), ('ㅔ', 0), ('ㅇ', 1), ('ㅣ', 1)]);
+        // Compound vowels and final consonants are typed with two keys
+        assert_eq!(letter_text("과"), "ㄱㅗㅏ");
+        assert_eq!(letter_text("넋"), "ㄴㅓㄱㅅ");
+        // Conjoining jamo read like the syllable they spell
+        assert_eq!(letter_text("\u{1102}\u{1166}"), "ㄴㅔ");
+        assert_eq!(letter_text("a넹"), "aㄴㅔㅇ");
+    }
+
+    #[test]
+    fn units_compose_conjoining_jamo() {
+        let units = units(&chars("\u{1102}\u{1166}\u{11BC}이ㅂx"));
+        let texts: Vec<char> = units.iter().map(|unit| unit.text).collect();
+        assert_eq!(texts, ['넹', '이', 'ㅂ', 'x']);
+        assert_eq!((units[0].start, units[0].end), (0, 3));
+        assert_eq!((units[1].start, units[1].end), (3, 4));
+        assert_eq!(units[0].initial, Some('ㄴ'));
+        assert_eq!(units[2].initial, Some('ㅂ'));
+        assert_eq!(units[3].initial, None);
+    }
+
+    #[test]
+    fn keys_map_between_layouts() {
+        assert_eq!(to_korean_keys("spdlqj").as_deref(), Some("ㄴㅔㅇㅣㅂㅓ"));
+        assert_eq!(to_korean_keys("Rk").as_deref(), Some("ㄲㅏ"));
+        assert_eq!(to_korean_keys("1234"), None);
+        assert_eq!(to_latin_keys("네이버").as_deref(), Some("spdlqj"));
+        assert_eq!(to_latin_keys("ㅜㅁㅍㄷㄱ").as_deref(), Some("naver"));
+        assert_eq!(to_latin_keys("과").as_deref(), Some("rhk"));
+        assert_eq!(to_latin_keys("naver"), None);
+    }
+}
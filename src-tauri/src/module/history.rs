@@ -0,0 +1,200 @@
+//! # History Module
+//!
+//! `history` keeps the previous values of the secrets of an entry: the password of a login
+//! and the hidden fields of a typed entry, such as the passphrase of a Wi-Fi network.
+//! Previous service and account names are kept too, so a renamed entry can still be recognized.
+//!
+//! Previous values are stored in the entry under `history`, newest first, as
+//! `{field, value, changed}`, so they are encrypted and synchronized with it.
+//! `field` is `password`, `service`, `account` or the name of a field in `data`, and `changed`
+//! is the Unix time the value was replaced. How many values are kept is a setting; older ones
+//! are dropped the next time the entry changes. Only secrets can be put back.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::{
+    timestamps::now,
+    types::{EntryType, ValueKind},
+};
+
+/// Key of the history in an entry
+pub const HISTORY_KEY: &str = "history";
+/// `field` of a previous password
+pub const PASSWORD_FIELD: &str = "password";
+/// `field` of previous names
+pub const NAME_FIELDS: [&str; 2] = ["service", "account"];
+/// Most values kept per entry, whatever the setting
+pub const MAX_HISTORY: u32 = 100;
+
+/// A previous value of a secret of an entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryItem {
+    pub field: String,
+    pub value: String,
+    /// Unix time the value was replaced
+    pub changed: u64,
+}
+
+/// Why a value could not be restored.
+#[derive(Debug, PartialEq)]
+pub enum HistoryError {
+    /// No value at that index, a previous name, or its field is no longer part of the entry
+    NotFound,
+}
+
+/// Returns `true` if previous values of the field are kept for entries of this type.
+fn is_secret(entry_type: EntryType, field: &str) -> bool {
+    if field == PASSWORD_FIELD {
+        return entry_type.template().password;
+    }
+    entry_type.template().fields.iter().any(|spec| spec.name == field && spec.kind == ValueKind::Hidden)
+}
+
+fn secret_value<'a>(entry: &'a Value, field: &str) -> &'a str {
+    let value = if field == PASSWORD_FIELD { &entry[field] } else { &entry["data"][field] };
+    value.as_str().unwrap_or_default()
+}
+
+/// The secrets of an entry, as `(field, value)`.
+fn secrets(entry: &Value) -> Vec<(String, String)> {
+    let entry_type = EntryType::of(entry);
+    let mut fields = vec![PASSWORD_FIELD];
+    fields.extend(entry_type.template().fields.iter().map(|spec| spec.name));
+    fields.into_iter()
+        .filter(|field| is_secret(entry_type, field))
+        .map(|field| (field.to_string(), secret_value(entry, field).to_string()))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+/// Returns `true` if `after` changed or cleared a secret of `before`, or has a new one.
+pub fn secrets_changed(before: &Value, after: &Value) -> bool {
+    secrets(before) != secrets(after)
+}
+
+/// The previous values of an entry, newest first. An unreadable history is empty.
+pub fn of(entry: &Value) -> Vec<HistoryItem> {
+    serde_json::from_value(entry[HISTORY_KEY].clone()).unwrap_or_default()
+}
+
+fn write(entry: &mut Value, mut items: Vec<HistoryItem>, limit: usize) {
+    items.truncate(limit);
+    if let Some(object) = entry.as_object_mut() {
+        if items.is_empty() {
+            object.remove(HISTORY_KEY);
+        } else {
+            object.insert(HISTORY_KEY.to_string(), serde_json::json!(items));
+        }
+    }
+}
+
+/// Adds to the history of `after` the secrets and names of `before` it changed or cleared,
+/// keeping at most `limit` values.
+pub fn record(before: &Value, after: &mut Value, limit: usize) {
+    let mut items = of(after);
+    for (field, value) in secrets(before) {
+        if secret_value(after, &field) != value {
+            items.insert(0, HistoryItem { field, value, changed: now() });
+        }
+    }
+    for field in NAME_FIELDS {
+        let value = before[field].as_str().unwrap_or_default();
+        if !value.is_empty() && after[field].as_str().unwrap_or_default() != value {
+            items.insert(0, HistoryItem { field: field.to_string(), value: value.to_string(), changed: now() });
+        }
+    }
+    write(after, items, limit);
+}
+
+/// Puts the value at `index` of the history back into the entry.
+/// The value it replaces goes into the history in its place.
+pub fn restore(entry: &mut Value, index: usize, limit: usize) -> Result<(), HistoryError> {
+    let mut items = of(entry);
+    if index >= items.len() || !is_secret(EntryType::of(entry), &items[index].field) {
+        return Err(HistoryError::NotFound);
+    }
+    let item = items.remove(index);
+    write(entry, items, usize::MAX);
+    let before = entry.clone();
+    if item.field == PASSWORD_FIELD {
+        entry[PASSWORD_FIELD] = Value::String(item.value);
+    } else {
+        if !entry["data"].is_object() {
+            entry["data"] = serde_json::json!({});
+        }
+        entry["data"][&item.field] = Value::String(item.value);
+    }
+    record(&before, entry, limit);
+    Ok(())
+}
+
+/// Removes the history from an entry, e.g. before it is listed or shared.
+pub fn strip(entry: &mut Value) {
+    if let Some(object) = entry.as_object_mut() {
+        object.remove(HISTORY_KEY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn login(password: &str) -> Value {
+        json!({ "service": "mail", "account": "me", "password": password })
+    }
+
+    #[test]
+    fn replaced_values_are_kept_newest_first_up_to_the_limit() {
+        let mut entry = login("one");
+        for password in ["two", "three", "four"] {
+            let mut next = entry.clone();
+            next[PASSWORD_FIELD] = json!(password);
+            record(&entry, &mut next, 2);
+            entry = next;
+        }
+        let values: Vec<String> = of(&entry).into_iter().map(|item| item.value).collect();
+        assert_eq!(values, ["three", "two"]);
+
+        let mut shrunk = entry.clone();
+        shrunk[PASSWORD_FIELD] = json!("five");
+        record(&entry, &mut shrunk, 0);
+        assert!(shrunk.get(HISTORY_KEY).is_none());
+    }
+
+    #[test]
+    fn hidden_fields_and_names_are_kept() {
+        let before = json!({ "type": "wifi", "service": "home", "data": { "ssid": "home", "passphrase": "old" } });
+        let mut after = json!({ "type": "wifi", "service": "house", "data": { "ssid": "house", "passphrase": "new" } });
+        assert!(secrets_changed(&before, &after));
+        record(&before, &mut after, MAX_HISTORY as usize);
+        let fields: Vec<String> = of(&after).into_iter().map(|item| item.field).collect();
+        // The ssid is not a secret, the service is a name
+        assert_eq!(fields, ["service", "passphrase"]);
+    }
+
+    #[test]
+    fn restoring_swaps_the_current_value_into_the_history() {
+        let before = login("old");
+        let mut entry = login("new");
+        entry["service"] = json!("webmail");
+        record(&before, &mut entry, 10);
+        // Names are only kept to recognize a renamed entry
+        assert_eq!(restore(&mut entry, 0, 10), Err(HistoryError::NotFound));
+        assert_eq!(restore(&mut entry, 5, 10), Err(HistoryError::NotFound));
+
+        restore(&mut entry, 1, 10).unwrap();
+        assert_eq!(entry[PASSWORD_FIELD], "old");
+        let items = of(&entry);
+        assert_eq!((items[0].field.as_str(), items[0].value.as_str()), (PASSWORD_FIELD, "new"));
+        assert_eq!(items.len(), 2);
+    }
+
+    #[test]
+    fn stripping_removes_the_history() {
+        let mut entry = login("new");
+        record(&login("old"), &mut entry, 10);
+        strip(&mut entry);
+        assert_eq!(entry, login("new"));
+    }
+}
@@ -0,0 +1,89 @@
+//! # Keyring Module
+//!
+//! `keyring` caches the vault key in the Linux session keyring through the kernel keyutils API,
+//! so the vault can stay unlocked across app restarts for a limited time.
+//! On other platforms every function is a no-op.
+
+use super::session::VaultKey;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use linux_keyutils::{KeyPermissionsBuilder, KeyRing, KeyRingIdentifier, Permission};
+    use zeroize::Zeroizing;
+    use super::VaultKey;
+
+    fn description(username: &str) -> String {
+        format!("passkeeper:{}", username)
+    }
+
+    pub fn store(username: &str, vault_key: &VaultKey, minutes: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let ring = KeyRing::from_special_id(KeyRingIdentifier::Session, false)
+            .map_err(|e| format!("{:?}", e))?;
+        let key = ring.add_key(&description(username), vault_key.as_ref())
+            .map_err(|e| format!("{:?}", e))?;
+        // Only processes possessing the session keyring may use the key
+        let perms = KeyPermissionsBuilder::builder()
+            .posessor(Permission::ALL)
+            .user(Permission::VIEW)
+            .build();
+        let _ = key.set_perms(perms);
+        key.set_timeout(minutes as usize * 60)
+            .map_err(|e| format!("{:?}", e))?;
+        Ok(())
+    }
+
+    pub fn load(username: &str) -> Option<VaultKey> {
+        let ring = KeyRing::from_special_id(KeyRingIdentifier::Session, false).ok()?;
+        let key = ring.search(&description(username)).ok()?;
+        let buffer = Zeroizing::new(key.read_to_vec().ok()?);
+        if buffer.len() != 32 {
+            return None;
+        }
+        let mut vault_key = Zeroizing::new([0u8; 32]);
+        vault_key.copy_from_slice(&buffer);
+        Some(vault_key)
+    }
+
+    pub fn revoke(username: &str) {
+        if let Ok(ring) = KeyRing::from_special_id(KeyRingIdentifier::Session, false) {
+            if let Ok(key) = ring.search(&description(username)) {
+                let _ = key.revoke();
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::VaultKey;
+
+    pub fn store(_username: &str, _vault_key: &VaultKey, _minutes: u32) -> Result<(), Box<dyn std::error::Error>> {
+        Err("the kernel keyring is only available on Linux".into())
+    }
+
+    pub fn load(_username: &str) -> Option<VaultKey> {
+        None
+    }
+
+    pub fn revoke(_username: &str) {}
+}
+
+/// Returns `true` if the kernel keyring can be used on this platform.
+pub fn is_supported() -> bool {
+    cfg!(target_os = "linux")
+}
+
+/// Stores the vault key in the session keyring. The kernel drops it after `minutes`.
+pub fn store(username: &str, vault_key: &VaultKey, minutes: u32) -> Result<(), Box<dyn std::error::Error>> {
+    imp::store(username, vault_key, minutes)
+}
+
+/// Loads the cached vault key, if it has not expired or been revoked.
+pub fn load(username: &str) -> Option<VaultKey> {
+    imp::load(username)
+}
+
+/// Revokes the cached vault key.
+pub fn revoke(username: &str) {
+    imp::revoke(username)
+}
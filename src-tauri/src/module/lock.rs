@@ -0,0 +1,111 @@
+//! # Vault Lock Module
+//!
+//! `lock` takes advisory locks on vault files, so that a second PassKeeper process or a
+//! command line tool honouring them never reads a half-written vault or writes over one.
+//!
+//! Writers hold an exclusive lock and readers a shared one, on a `<vault>.lock` file next to
+//! the vault. The vault itself is not locked: locks are mandatory on Windows and would
+//! also block the writes of the process holding them.
+//! Locks belong to the open lock file, so a thread must not take a second lock on a vault
+//! it already holds.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for a lock held by someone else
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A held lock. Released when dropped.
+pub struct VaultLock {
+    _file: File,
+}
+
+fn lock_path(vault_path: &str) -> String {
+    format!("{}.lock", vault_path)
+}
+
+fn acquire(vault_path: &str, exclusive: bool) -> io::Result<VaultLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(vault_path))?;
+    let started = Instant::now();
+    loop {
+        let result = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+        match result {
+            Ok(()) => return Ok(VaultLock { _file: file }),
+            Err(TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => {
+                thread::sleep(RETRY_INTERVAL);
+            },
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "the vault is locked by another process"));
+            },
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+    }
+}
+
+/// Locks a vault for writing. Fails with [`io::ErrorKind::WouldBlock`] after [`LOCK_TIMEOUT`].
+pub fn exclusive(vault_path: &str) -> io::Result<VaultLock> {
+    acquire(vault_path, true)
+}
+
+/// Locks a vault for reading. Fails with [`io::ErrorKind::WouldBlock`] after [`LOCK_TIMEOUT`].
+pub fn shared(vault_path: &str) -> io::Result<VaultLock> {
+    acquire(vault_path, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, sync::mpsc};
+    use super::*;
+
+    fn temp_vault(name: &str) -> String {
+        env::temp_dir().join(format!("passkeeper-lock-{}-{}.dat", std::process::id(), name)).display().to_string()
+    }
+
+    #[test]
+    fn readers_share_the_lock() {
+        let path = temp_vault("shared");
+        let first = shared(&path).unwrap();
+        let second = shared(&path).unwrap();
+        drop((first, second));
+        let _ = fs::remove_file(lock_path(&path));
+    }
+
+    #[test]
+    fn a_writer_waits_for_the_readers() {
+        let path = temp_vault("wait");
+        let reader = shared(&path).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let lock = exclusive(&path);
+                sender.send(()).unwrap();
+                lock.map(drop)
+            })
+        };
+        // The writer is still waiting while the reader holds the lock
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(reader);
+        assert!(receiver.recv_timeout(LOCK_TIMEOUT).is_ok());
+        writer.join().unwrap().unwrap();
+        let _ = fs::remove_file(lock_path(&path));
+    }
+
+    #[test]
+    fn a_lock_held_too_long_times_out() {
+        let path = temp_vault("timeout");
+        let _writer = exclusive(&path).unwrap();
+        let error = shared(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        let _ = fs::remove_file(lock_path(&path));
+    }
+}
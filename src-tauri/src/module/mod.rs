@@ -1,3 +1,32 @@
 pub mod debug;
 pub mod crypto;
-pub mod registry;
\ No newline at end of file
+#[cfg(windows)]
+pub mod registry;
+pub mod session;
+pub mod settings;
+pub mod keyring;
+pub mod vault;
+pub mod selftest;
+pub mod generation;
+pub mod store;
+pub mod cache;
+pub mod sync;
+pub mod watcher;
+pub mod lock;
+pub mod vaults;
+pub mod share;
+pub mod teams;
+pub mod admin;
+pub mod entry;
+pub mod folders;
+pub mod types;
+pub mod history;
+pub mod timestamps;
+pub mod trash;
+pub mod undo;
+pub mod search;
+pub mod hangul;
+pub mod decoy;
+pub mod companion;
+#[cfg(test)]
+pub mod testing;
\ No newline at end of file
@@ -0,0 +1,423 @@
+//! # Search Module
+//!
+//! `search` finds the entries of a vault matching what the user types, best matches first.
+//!
+//! The query is split into terms on whitespace, and an entry matches when every term is found
+//! in its service, account, URLs, tags, notes or the searchable values of its type. A term is
+//! found as typed, at the start of a word, inside a word, with a typo in longer terms, or with
+//! its letters in order but apart, each scoring less. Passwords, secret fields and custom fields
+//! are never searched.
+//!
+//! Korean is compared jamo by jamo, see [`super::hangul`], so a syllable still being typed
+//! matches, and consonants typed alone match the syllables they start: `ㄴㅇㅂ` finds `네이버`.
+//! A term typed with the other keyboard layout is also tried, for a little less:
+//! `spdlqj` finds `네이버` and `ㅜㅁㅍㄷㄱ` finds `naver`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::{
+    folders::{self, ROOT_FOLDER},
+    hangul::{self, Unit},
+    timestamps::{self, now},
+    types::EntryType,
+};
+
+/// Seconds in a day
+const DAY: u64 = 24 * 60 * 60;
+/// What a match of a term typed with the other keyboard layout is worth, in percent
+const LAYOUT_WEIGHT: u32 = 90;
+
+/// What a search is narrowed to, besides the query.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchFilters {
+    #[serde(rename = "type")]
+    pub entry_type: Option<EntryType>,
+    /// Entries in the folder or one of its subfolders
+    pub folder: Option<String>,
+    /// Entries with the tag, in any case
+    pub tag: Option<String>,
+    /// Entries changed in the last days
+    pub newer_than_days: Option<u32>,
+    /// Entries not changed for that many days, and those saved before timestamps existed
+    pub older_than_days: Option<u32>,
+    /// Most results returned
+    pub limit: Option<usize>,
+}
+
+/// Where the terms of a query were found in a value of an entry.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Highlight {
+    /// `service`, `account`, `notes`, `urls.0`, `tags.1` or `data.ssid`
+    pub field: String,
+    /// The value searched, e.g. only the last digits of a card number
+    pub value: String,
+    /// `[start, end)` of each match in the value, in UTF-16 code units as the webview counts them
+    pub ranges: Vec<[usize; 2]>,
+}
+
+/// An entry found by a search.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub entry: Value,
+    pub score: u32,
+    pub highlights: Vec<Highlight>,
+}
+
+/// A searchable value of an entry, with what a match in it is worth in percent.
+struct Field {
+    name: String,
+    value: String,
+    weight: u32,
+}
+
+fn fields(entry: &Value) -> Vec<Field> {
+    let text = |key: &str| entry[key].as_str().unwrap_or_default().to_string();
+    let mut fields = vec![
+        Field { name: "service".to_string(), value: text("service"), weight: 100 },
+        Field { name: "account".to_string(), value: text("account"), weight: 80 },
+    ];
+    for (key, weight) in [("tags", 70), ("urls", 60)] {
+        let values = entry[key].as_array().into_iter().flatten().filter_map(Value::as_str);
+        fields.extend(values.enumerate().map(|(index, value)| {
+            Field { name: format!("{}.{}", key, index), value: value.to_string(), weight }
+        }));
+    }
+    fields.extend(EntryType::of(entry).search_terms(entry).into_iter()
+        .map(|(name, value)| Field { name: format!("data.{}", name), value, weight: 70 }));
+    fields.push(Field { name: "notes".to_string(), value: text("notes"), weight: 40 });
+    fields.retain(|field| !field.value.is_empty());
+    fields
+}
+
+/// A character as it is compared. Always one character, so positions stay those of the value.
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// A value or a term as it is compared: its letters, Hangul taken apart into jamo, with the
+/// character each comes from, and its characters as they read.
+struct Text {
+    letters: Vec<char>,
+    origin: Vec<usize>,
+    units: Vec<Unit>,
+}
+
+impl Text {
+    fn new(value: &str) -> Self {
+        let chars: Vec<char> = value.chars().map(fold).collect();
+        let (letters, origin) = hangul::letters(&chars).into_iter().unzip();
+        Text { letters, origin, units: hangul::units(&chars) }
+    }
+}
+
+/// A term of a query, and the same keys on the other keyboard layout, with what each is worth.
+fn alternatives(term: &str) -> Vec<(Text, u32)> {
+    let mut alternatives = vec![(Text::new(term), 100)];
+    // A single key typed on the other layout would match too much
+    if term.chars().count() > 1 {
+        alternatives.extend(hangul::to_korean_keys(term).into_iter()
+            .chain(hangul::to_latin_keys(term))
+            .map(|keys| (Text::new(&keys), LAYOUT_WEIGHT)));
+    }
+    alternatives
+}
+
+/// The `[start, end)` of the words of a value.
+fn words(value: &[char]) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in value.iter().enumerate() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                words.push((begin, index));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some(begin) = start {
+        words.push((begin, value.len()));
+    }
+    words
+}
+
+/// Edits turning one word into the other, a swap of neighbours counting as one.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut edits = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                edits = edits.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = edits;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The letters of a term in order in a value, over at most twice its length.
+fn subsequence(term: &[char], value: &[char]) -> Option<(u32, Vec<(usize, usize)>)> {
+    if term.len() < 2 {
+        return None;
+    }
+    let span = |positions: &[usize]| positions[positions.len() - 1] - positions[0] + 1;
+    let mut best: Option<Vec<usize>> = None;
+    for start in (0..value.len()).filter(|&index| value[index] == term[0]) {
+        let mut positions = vec![start];
+        for c in &term[1..] {
+            let from = positions[positions.len() - 1] + 1;
+            match value[from..].iter().position(|v| v == c) {
+                Some(offset) => positions.push(from + offset),
+                None => break,
+            }
+        }
+        // A later start cannot find what this one did not
+        if positions.len() < term.len() {
+            break;
+        }
+        if best.as_deref().is_none_or(|best| span(&positions) < span(best)) {
+            best = Some(positions);
+        }
+    }
+    let positions = best?;
+    let length = span(&positions);
+    if length > term.len() * 2 {
+        return None;
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for position in positions {
+        match ranges.last_mut() {
+            Some(range) if range.1 == position => range.1 = position + 1,
+            _ => ranges.push((position, position + 1)),
+        }
+    }
+    Some((20 + (10 * term.len() / length) as u32, ranges))
+}
+
+/// How well a term matches a value, out of 100, with the matched character ranges.
+fn match_term(term: &[char], value: &[char]) -> Option<(u32, Vec<(usize, usize)>)> {
+    let length = term.len();
+    if length == 0 || value.is_empty() {
+        return None;
+    }
+    if term == value {
+        return Some((100, vec![(0, length)]));
+    }
+    if let Some(start) = value.windows(length).position(|window| window == term) {
+        let score = match start {
+            0 => 80,
+            _ if !value[start - 1].is_alphanumeric() => 70,
+            _ => 60,
+        };
+        return Some((score, vec![(start, start + length)]));
+    }
+    // Typos in longer terms, against whole words or, while typing, their start
+    let allowed = match length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    if allowed > 0 {
+        let typo = words(value).into_iter()
+            .filter_map(|(start, end)| {
+                let word = &value[start..end];
+                let mut edits = distance(term, word);
+                if word.len() > length {
+                    edits = edits.min(distance(term, &word[..length]));
+                }
+                (edits <= allowed).then_some((edits, start, end))
+            })
+            .min_by_key(|(edits, ..)| *edits);
+        if let Some((edits, start, end)) = typo {
+            return Some((50 - 10 * edits as u32, vec![(start, end)]));
+        }
+    }
+    subsequence(term, value)
+}
+
+/// A term read against the syllables of a value, consonants typed alone standing for the
+/// syllables they start, e.g. `ㄴㅇㅂ` or `네ㅇㅂ` for `네이버`.
+fn match_initials(term: &[Unit], value: &[Unit]) -> Option<(u32, Vec<(usize, usize)>)> {
+    if term.is_empty() || !term.iter().any(|unit| hangul::is_consonant(unit.text)) {
+        return None;
+    }
+    let matches = |typed: &Unit, read: &Unit| {
+        typed.text == read.text || (hangul::is_consonant(typed.text) && read.initial == Some(typed.text))
+    };
+    let start = value.windows(term.len())
+        .position(|window| window.iter().zip(term).all(|(read, typed)| matches(typed, read)))?;
+    let score = match start {
+        0 => 75,
+        _ if !value[start - 1].text.is_alphanumeric() => 65,
+        _ => 55,
+    };
+    Some((score, vec![(value[start].start, value[start + term.len() - 1].end)]))
+}
+
+/// How well a term matches a value, by its letters or its initial consonants,
+/// with the matched character ranges.
+fn match_text(term: &Text, value: &Text) -> Option<(u32, Vec<(usize, usize)>)> {
+    let by_letters = match_term(&term.letters, &value.letters).map(|(score, ranges)| {
+        let ranges = ranges.into_iter().map(|(start, end)| (value.origin[start], value.origin[end - 1] + 1)).collect();
+        (score, ranges)
+    });
+    let by_initials = match_initials(&term.units, &value.units);
+    by_letters.into_iter().chain(by_initials).max_by_key(|(score, _)| *score)
+}
+
+/// Sorted ranges without overlaps, in UTF-16 code units of the value.
+fn utf16_ranges(value: &str, mut ranges: Vec<(usize, usize)>) -> Vec<[usize; 2]> {
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let mut offsets = vec![0];
+    for c in value.chars() {
+        offsets.push(offsets[offsets.len() - 1] + c.len_utf16());
+    }
+    merged.into_iter().map(|(start, end)| [offsets[start], offsets[end]]).collect()
+}
+
+/// The score of an entry for the terms of a query, with where they were found.
+/// `None` if a term is found nowhere.
+fn score(entry: &Value, terms: &[Vec<(Text, u32)>]) -> Option<(u32, Vec<Highlight>)> {
+    let fields = fields(entry);
+    let values: Vec<Text> = fields.iter().map(|field| Text::new(&field.value)).collect();
+    let mut found: Vec<Vec<(usize, usize)>> = vec![Vec::new(); fields.len()];
+    let mut total = 0;
+    for alternatives in terms {
+        let mut best = None;
+        for (term, weight) in alternatives {
+            for (index, field) in fields.iter().enumerate() {
+                if let Some((score, ranges)) = match_text(term, &values[index]) {
+                    best = best.max(Some(score * weight / 100 * field.weight / 100));
+                    found[index].extend(ranges);
+                }
+            }
+        }
+        total += best?;
+    }
+    let highlights = fields.into_iter().zip(found)
+        .filter(|(_, ranges)| !ranges.is_empty())
+        .map(|(field, ranges)| Highlight { ranges: utf16_ranges(&field.value, ranges), field: field.name, value: field.value })
+        .collect();
+    Some((total, highlights))
+}
+
+/// Returns `true` if an entry passes the filters, `in_folders` being the folders the folder filter allows.
+fn passes(entry: &Value, filters: &SearchFilters, in_folders: Option<&[String]>) -> bool {
+    if filters.entry_type.is_some_and(|entry_type| entry_type != EntryType::of(entry)) {
+        return false;
+    }
+    if in_folders.is_some_and(|ids| !ids.iter().any(|id| id == folders::parent_of(entry))) {
+        return false;
+    }
+    if let Some(tag) = &filters.tag {
+        let tag = tag.trim().to_lowercase();
+        let tags = entry["tags"].as_array().into_iter().flatten().filter_map(Value::as_str);
+        if !tags.map(str::to_lowercase).any(|t| t == tag) {
+            return false;
+        }
+    }
+    let changed = timestamps::get(entry, timestamps::MODIFIED).max(timestamps::get(entry, timestamps::CREATED));
+    let since = |days: u32| now().saturating_sub(days as u64 * DAY);
+    if filters.newer_than_days.is_some_and(|days| changed == 0 || changed < since(days)) {
+        return false;
+    }
+    if filters.older_than_days.is_some_and(|days| changed >= since(days)) {
+        return false;
+    }
+    true
+}
+
+/// The entries of a vault matching a query and the filters, best first.
+/// Entries scoring the same stay in vault order. An empty query matches every entry passing the filters.
+pub fn search(records: &[Value], query: &str, filters: &SearchFilters) -> Vec<SearchHit> {
+    let terms: Vec<Vec<(Text, u32)>> = query.split_whitespace().map(alternatives).collect();
+    let in_folders = filters.folder.as_deref()
+        .filter(|folder| *folder != ROOT_FOLDER)
+        .map(|folder| folders::subtree(records, folder));
+    let mut hits: Vec<SearchHit> = folders::entries(records)
+        .filter(|entry| passes(entry, filters, in_folders.as_deref()))
+        .filter_map(|entry| {
+            let (score, highlights) = score(entry, &terms)?;
+            Some(SearchHit { entry: entry.clone(), score, highlights })
+        })
+        .collect();
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    if let Some(limit) = filters.limit {
+        hits.truncate(limit);
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn found(records: &[Value], query: &str) -> Vec<String> {
+        search(records, query, &SearchFilters::default()).into_iter()
+            .map(|hit| hit.entry["service"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn terms_score_by_where_they_match() {
+        let score = |term: &str, value: &str| match_term(&chars(term), &chars(value)).map(|(score, _)| score);
+        assert_eq!(score("mail", "mail"), Some(100));
+        assert_eq!(score("mail", "mailbox"), Some(80));
+        assert_eq!(score("mail", "my mail"), Some(70));
+        assert_eq!(score("mail", "gmail"), Some(60));
+        assert_eq!(score("gogle", "google"), Some(40));
+        assert!(score("ggl", "google").is_some_and(|score| score < 40));
+        assert_eq!(score("xyz", "google"), None);
+        assert_eq!(score("", "google"), None);
+    }
+
+    #[test]
+    fn term_ranges_cover_the_match() {
+        assert_eq!(match_term(&chars("mail"), &chars("gmail")).unwrap().1, [(1, 5)]);
+        assert_eq!(match_term(&chars("gogle"), &chars("my google")).unwrap().1, [(3, 9)]);
+    }
+
+    #[test]
+    fn korean_matches_while_typed_by_initials_and_layout() {
+        let records = [
+            json!({ "id": "1", "service": "네이버", "account": "me" }),
+            json!({ "id": "2", "service": "naver mail", "account": "me" }),
+            json!({ "id": "3", "service": "카카오", "account": "me" }),
+        ];
+        assert_eq!(found(&records, "넹"), ["네이버"]);
+        assert_eq!(found(&records, "ㄴㅇㅂ"), ["네이버"]);
+        assert_eq!(found(&records, "spdlqj"), ["네이버"]);
+        assert_eq!(found(&records, "ㅜㅁㅍㄷㄱ"), ["naver mail"]);
+        assert_eq!(found(&records, "ㅋㅋㅇ"), ["카카오"]);
+    }
+
+    #[test]
+    fn highlights_count_utf16_units() {
+        let records = [json!({ "id": "1", "service": "😀 네이버", "account": "me" })];
+        let hits = search(&records, "네이", &SearchFilters::default());
+        assert_eq!(hits[0].highlights[0].field, "service");
+        assert_eq!(hits[0].highlights[0].ranges, [[3, 5]]);
+    }
+}
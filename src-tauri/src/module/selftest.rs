@@ -0,0 +1,170 @@
+//! # Crypto Self-Test Module
+//!
+//! `selftest` runs known-answer tests for the cipher, the KDF and the hash at startup,
+//! and checks that none of them behaves like an identity or placeholder function.
+//! Vaults must not be unlocked unless every test has passed.
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use argon2::{Algorithm, Argon2, AssociatedData, ParamsBuilder, Version};
+use super::crypto::{
+    myhash::{hash_bytes, hash_data},
+    mycrypt::{encrypt_data, decrypt_data, decrypt_bytes},
+    mykdf::derive_key,
+};
+
+// SHA-512("abc"), FIPS 180-2
+const SHA512_ABC: &str = "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                          2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f";
+// AES-256-GCM, zero key, zero IV, 16 zero bytes (GCM spec test case 14): ciphertext || tag
+const AES_GCM_TC14: &str = "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919";
+// Argon2id, RFC 9106 section 5.3
+const ARGON2ID_RFC9106: &str = "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659";
+// Argon2id with the configured parameters, password "password", salt "PassKeeper:selftest".
+// Must be recomputed whenever the KDF constants in `define` change.
+const ARGON2ID_CONFIGURED: &str = "20670aa55bc205dcbe041a9153203ddf7d75fc5b96e49dda25a6513583214922";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub results: Vec<SelfTestResult>,
+}
+
+static REPORT: OnceCell<SelfTestReport> = OnceCell::new();
+
+/// Returns the report of the self-test, running it on first use.
+pub fn report() -> &'static SelfTestReport {
+    REPORT.get_or_init(run)
+}
+
+/// Returns `true` if every self-test has passed.
+pub fn is_passed() -> bool {
+    report().passed
+}
+
+fn check(name: &'static str, outcome: Result<(), String>) -> SelfTestResult {
+    match outcome {
+        Ok(()) => SelfTestResult { name, passed: true, detail: String::from("ok") },
+        Err(detail) => SelfTestResult { name, passed: false, detail },
+    }
+}
+
+/// Runs every self-test.
+pub fn run() -> SelfTestReport {
+    let results = vec![
+        check("sha512_known_answer", sha512_known_answer()),
+        check("hash_not_identity", hash_not_identity()),
+        check("aes256gcm_known_answer", aes256gcm_known_answer()),
+        check("cipher_not_identity", cipher_not_identity()),
+        check("argon2id_known_answer", argon2id_known_answer()),
+        check("kdf_configured_known_answer", kdf_configured_known_answer()),
+        check("kdf_not_identity", kdf_not_identity()),
+    ];
+    let passed = results.iter().all(|result| result.passed);
+    SelfTestReport { passed, results }
+}
+
+fn sha512_known_answer() -> Result<(), String> {
+    let digest = hash_data("abc");
+    if digest != SHA512_ABC {
+        return Err(format!("unexpected digest {}", digest));
+    }
+    Ok(())
+}
+
+fn hash_not_identity() -> Result<(), String> {
+    let input = "PassKeeper self-test";
+    let digest = hash_data(input);
+    if digest == input || digest.contains(input) || digest.len() != 128 {
+        return Err(String::from("hash returns its input"));
+    }
+    if hash_data("a") == hash_bytes(b"b") {
+        return Err(String::from("hash ignores its input"));
+    }
+    Ok(())
+}
+
+fn aes256gcm_known_answer() -> Result<(), String> {
+    let mut encrypted = vec![0u8; 12];
+    encrypted.extend(hex::decode(AES_GCM_TC14).map_err(|e| e.to_string())?);
+    let decrypted = decrypt_bytes(&[0u8; 32], &encrypted).map_err(|e| e.to_string())?;
+    if decrypted != [0u8; 16] {
+        return Err(String::from("unexpected plaintext"));
+    }
+    // A flipped bit in the tag must be rejected
+    let last = encrypted.len() - 1;
+    encrypted[last] ^= 1;
+    if decrypt_bytes(&[0u8; 32], &encrypted).is_ok() {
+        return Err(String::from("tampered ciphertext was accepted"));
+    }
+    Ok(())
+}
+
+fn cipher_not_identity() -> Result<(), String> {
+    let key = [0x5au8; 32];
+    let plain = "PassKeeper self-test plaintext";
+    let first = encrypt_data(&key, plain).map_err(|e| e.to_string())?;
+    let second = encrypt_data(&key, plain).map_err(|e| e.to_string())?;
+    if first.windows(plain.len()).any(|window| window == plain.as_bytes()) {
+        return Err(String::from("ciphertext contains the plaintext"));
+    }
+    if first == second {
+        return Err(String::from("encryption is deterministic"));
+    }
+    if decrypt_data(&key, &first).map_err(|e| e.to_string())? != plain {
+        return Err(String::from("round trip failed"));
+    }
+    if decrypt_data(&[0xa5u8; 32], &first).is_ok() {
+        return Err(String::from("decryption ignores the key"));
+    }
+    Ok(())
+}
+
+fn argon2id_known_answer() -> Result<(), String> {
+    let params = ParamsBuilder::new()
+        .m_cost(32)
+        .t_cost(3)
+        .p_cost(4)
+        .data(AssociatedData::new(&[4u8; 12]).map_err(|e| e.to_string())?)
+        .output_len(32)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let argon2 = Argon2::new_with_secret(&[3u8; 8], Algorithm::Argon2id, Version::V0x13, params)
+        .map_err(|e| e.to_string())?;
+    let mut tag = [0u8; 32];
+    argon2.hash_password_into(&[1u8; 32], &[2u8; 16], &mut tag)
+        .map_err(|e| e.to_string())?;
+    if hex::encode(tag) != ARGON2ID_RFC9106 {
+        return Err(format!("unexpected tag {}", hex::encode(tag)));
+    }
+    Ok(())
+}
+
+fn kdf_configured_known_answer() -> Result<(), String> {
+    let key = derive_key("password", b"PassKeeper:selftest").map_err(|e| e.to_string())?;
+    if hex::encode(key.as_ref()) != ARGON2ID_CONFIGURED {
+        return Err(format!("unexpected key {}", hex::encode(key.as_ref())));
+    }
+    Ok(())
+}
+
+fn kdf_not_identity() -> Result<(), String> {
+    let salt = b"PassKeeper:selftest";
+    let first = derive_key("password", salt).map_err(|e| e.to_string())?;
+    let second = derive_key("passwore", salt).map_err(|e| e.to_string())?;
+    let other_salt = derive_key("password", b"PassKeeper:selftesu").map_err(|e| e.to_string())?;
+    if first.starts_with(b"password") || first.iter().all(|b| *b == 0) {
+        return Err(String::from("key derivation returns its input"));
+    }
+    if first == second || first == other_salt {
+        return Err(String::from("key derivation ignores its input"));
+    }
+    Ok(())
+}
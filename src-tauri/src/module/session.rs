@@ -0,0 +1,215 @@
+//! # Session Module
+//!
+//! `session` keeps the unlocked vault keys in memory for the lifetime of the process.
+//! Nothing in this module is ever written to disk.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use zeroize::Zeroizing;
+use common::define;
+use super::crypto::{
+    mycrypt::{encrypt_bytes, decrypt_bytes, random_bytes},
+    mykdf::derive_key,
+};
+
+pub type VaultKey = Zeroizing<[u8; 32]>;
+
+/// Reasons a PIN operation can fail.
+#[derive(Debug, PartialEq)]
+pub enum PinError {
+    /// The vault has not been unlocked with the master password in this session.
+    Locked,
+    /// No PIN is set, or it was discarded after too many wrong attempts.
+    NotSet,
+    /// The PIN format is not acceptable.
+    Malformed,
+    /// Wrong PIN, attempts remain.
+    Invalid,
+    /// Wrong PIN, and the wrapped key has been discarded.
+    Discarded,
+    /// Key wrapping failed.
+    Crypto,
+}
+
+/// The PIN-wrapped copy of the vault key.
+struct PinLock {
+    salt: [u8; 16],
+    wrapped: Vec<u8>,
+    attempts: u32,
+}
+
+#[derive(Default)]
+struct Session {
+    vault_key: Option<VaultKey>,
+    pin: Option<PinLock>,
+    // The named vault the data commands use, `None` for the default vault
+    vault: Option<String>,
+}
+
+static SESSIONS: Lazy<Mutex<HashMap<String, Session>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// Stores the vault key after a successful master password login.
+/// The login may be another slot, so the default vault is selected again.
+pub fn unlock(username: &str, vault_key: VaultKey) {
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = sessions.entry(username.to_string()).or_default();
+    session.vault_key = Some(vault_key);
+    session.vault = None;
+}
+
+/// Selects the vault the data commands use. `None` selects the default vault.
+pub fn select_vault(username: &str, vault_id: Option<&str>) {
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get_mut(username) {
+        session.vault = vault_id.map(str::to_string);
+    }
+}
+
+/// The selected vault, `None` for the default vault.
+pub fn current_vault(username: &str) -> Option<String> {
+    let sessions = SESSIONS.lock().unwrap();
+    sessions.get(username).and_then(|session| session.vault.clone())
+}
+
+/// Returns a copy of the vault key if the vault is unlocked.
+pub fn vault_key(username: &str) -> Option<VaultKey> {
+    let sessions = SESSIONS.lock().unwrap();
+    sessions.get(username)
+        .and_then(|session| session.vault_key.clone())
+}
+
+/// The users whose vault is unlocked.
+pub fn unlocked_users() -> Vec<String> {
+    let sessions = SESSIONS.lock().unwrap();
+    sessions.iter()
+        .filter(|(_, session)| session.vault_key.is_some())
+        .map(|(username, _)| username.clone())
+        .collect()
+}
+
+/// Drops the vault key but keeps the PIN, so the vault can be unlocked again with the PIN.
+pub fn lock(username: &str) {
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get_mut(username) {
+        session.vault_key = None;
+    }
+}
+
+/// Drops everything held for the user, including the PIN and the undo journal.
+pub fn logout(username: &str) {
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.remove(username);
+    super::undo::clear(username);
+}
+
+/// Checks whether a PIN is available for the user.
+pub fn has_pin(username: &str) -> bool {
+    let sessions = SESSIONS.lock().unwrap();
+    sessions.get(username)
+        .map(|session| session.pin.is_some())
+        .unwrap_or(false)
+}
+
+/// Wraps the current vault key with a key derived from the PIN.
+/// The key is derived without holding the sessions, which other commands need meanwhile.
+pub fn set_pin(username: &str, pin: &str) -> Result<(), PinError> {
+    if pin.len() < define::PIN_MIN_LENGTH
+        || pin.len() > define::PIN_MAX_LENGTH
+        || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(PinError::Malformed);
+    }
+
+    let vault_key = vault_key(username).ok_or(PinError::Locked)?;
+    let salt = random_bytes::<16>();
+    let pin_key = derive_key(pin, &salt).map_err(|_| PinError::Crypto)?;
+    let wrapped = encrypt_bytes(&pin_key, vault_key.as_ref()).map_err(|_| PinError::Crypto)?;
+
+    // The vault may have been locked or unlocked with another key in the meantime
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = sessions.get_mut(username).ok_or(PinError::Locked)?;
+    if session.vault_key.as_ref() != Some(&vault_key) {
+        return Err(PinError::Locked);
+    }
+    session.pin = Some(PinLock { salt, wrapped, attempts: 0 });
+    Ok(())
+}
+
+/// Removes the PIN, keeping the vault state as it is.
+pub fn clear_pin(username: &str) {
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get_mut(username) {
+        session.pin = None;
+    }
+}
+
+/// Unwraps the vault key with the PIN.
+/// After `PIN_MAX_ATTEMPTS` wrong attempts the wrapped key is discarded.
+/// The key is derived without holding the sessions. An attempt counts as wrong until it
+/// turns out right, so attempts made at the same time cannot get past the limit.
+pub fn unlock_with_pin(username: &str, pin: &str) -> Result<VaultKey, PinError> {
+    let (salt, wrapped) = {
+        let mut sessions = SESSIONS.lock().unwrap();
+        let session = sessions.get_mut(username).ok_or(PinError::NotSet)?;
+        let pin_lock = session.pin.as_mut().ok_or(PinError::NotSet)?;
+        if pin_lock.attempts >= define::PIN_MAX_ATTEMPTS {
+            session.pin = None;
+            return Err(PinError::Discarded);
+        }
+        pin_lock.attempts += 1;
+        (pin_lock.salt, pin_lock.wrapped.clone())
+    };
+
+    let unwrapped = derive_key(pin, &salt)
+        .ok()
+        .and_then(|pin_key| decrypt_bytes(&pin_key, &wrapped).ok())
+        .map(Zeroizing::new);
+
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = sessions.get_mut(username).ok_or(PinError::NotSet)?;
+    // The PIN may have been replaced, cleared or discarded in the meantime
+    let pin_lock = session.pin.as_mut()
+        .filter(|pin_lock| pin_lock.salt == salt)
+        .ok_or(PinError::NotSet)?;
+    match unwrapped {
+        Some(buffer) if buffer.len() == 32 => {
+            let mut vault_key = Zeroizing::new([0u8; 32]);
+            vault_key.copy_from_slice(&buffer);
+            pin_lock.attempts = 0;
+            session.vault_key = Some(vault_key.clone());
+            Ok(vault_key)
+        },
+        _ => {
+            if pin_lock.attempts >= define::PIN_MAX_ATTEMPTS {
+                session.pin = None;
+                return Err(PinError::Discarded);
+            }
+            Err(PinError::Invalid)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_pins_discard_the_wrapped_key() {
+        let username = "session-test-pin";
+        let key = Zeroizing::new([9u8; 32]);
+        unlock(username, key.clone());
+        set_pin(username, "1234").unwrap();
+        lock(username);
+        assert_eq!(unlock_with_pin(username, "1234").unwrap(), key);
+        lock(username);
+        for _ in 1..define::PIN_MAX_ATTEMPTS {
+            assert_eq!(unlock_with_pin(username, "0000").unwrap_err(), PinError::Invalid);
+        }
+        assert_eq!(unlock_with_pin(username, "0000").unwrap_err(), PinError::Discarded);
+        assert_eq!(unlock_with_pin(username, "1234").unwrap_err(), PinError::NotSet);
+        assert!(vault_key(username).is_none());
+        logout(username);
+    }
+}
@@ -0,0 +1,69 @@
+//! # Settings Module
+//!
+//! `settings` loads and saves the application settings kept in `data/settings.json`.
+
+use std::{
+    fs,
+    env::current_dir,
+};
+use serde::{Deserialize, Serialize};
+use common::define;
+use super::{
+    crypto::mykdf::KdfParams,
+    store::StoreKind,
+    vault,
+};
+
+/// Application settings. Every field falls back to its default when missing from the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Minutes the vault key stays in the Linux session keyring across app restarts.
+    /// `0` disables the cache.
+    pub keyring_cache_minutes: u32,
+    /// Target time of one key derivation when calibrating.
+    pub kdf_target_ms: u32,
+    /// Vaults whose KDF parameters fall below this floor are upgraded on login.
+    pub kdf_floor: KdfParams,
+    /// Parameters picked by the last calibration on this machine.
+    pub kdf_calibrated: Option<KdfParams>,
+    /// Storage backend of new accounts.
+    pub storage_backend: StoreKind,
+    /// Previous passwords kept per entry. `0` keeps none.
+    pub password_history_size: u32,
+    /// Days deleted entries stay in the trash. `0` keeps them until the trash is emptied.
+    pub trash_retention_days: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            keyring_cache_minutes: 0,
+            kdf_target_ms: define::KDF_TARGET_MS,
+            kdf_floor: KdfParams::default(),
+            kdf_calibrated: None,
+            storage_backend: StoreKind::File,
+            password_history_size: define::PASSWORD_HISTORY_SIZE,
+            trash_retention_days: define::TRASH_RETENTION_DAYS,
+        }
+    }
+}
+
+fn settings_path() -> String {
+    format!("{}/data/settings.json", current_dir().unwrap().display())
+}
+
+/// Loads the settings, or the defaults if the file does not exist or cannot be parsed.
+pub fn load() -> Settings {
+    fs::read_to_string(settings_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Saves the settings.
+pub fn save(settings: &Settings) -> std::io::Result<()> {
+    fs::create_dir_all("./data")?;
+    let content = serde_json::to_string_pretty(settings)?;
+    vault::write_atomic(settings_path(), content)
+}
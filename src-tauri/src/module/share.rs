@@ -0,0 +1,654 @@
+//! # Sharing Module
+//!
+//! `share` hands entries from one PassKeeper user to another on the same machine or on a
+//! shared `data` directory, without writing them anywhere in plain text.
+//!
+//! Every account has an identity: an X25519 key to receive bundles and an Ed25519 key to sign
+//! the bundles it sends. `data/<username>.identity` holds the public keys, for other users to
+//! read, and the secret keys sealed with the login key of the login that created it.
+//! The duress login of the account has an identity of its own next to it, see [`create_identities`].
+//! Other users seal for the first identity only, but signatures of either are accepted.
+//!
+//! A bundle is encrypted with a key agreed between a one-off X25519 key and the key of the
+//! recipient, so only the recipient opens it, and is signed by the sender over every field,
+//! the recipient key included. Bundles wait in `data/inbox/<recipient>/` until imported.
+//! The same keys wrap the keys of team vaults for their members, see `teams`.
+
+use std::{
+    fs,
+    io,
+    env::current_dir,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use base64::{engine::general_purpose, Engine as _};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+use super::{
+    crypto::{
+        myhash::hash_bytes,
+        mycrypt::{decrypt_bytes, decrypt_data, encrypt_bytes, encrypt_data, random_bytes},
+    },
+    lock,
+    session::VaultKey,
+};
+
+/// Version of the bundle format
+pub const BUNDLE_VERSION: u32 = 1;
+/// Extension of bundle files
+pub const BUNDLE_EXTENSION: &str = "pkshare";
+/// Directory of the inboxes under `data`
+pub const INBOX_DIR: &str = "inbox";
+// Contexts of the key derivation, so an agreed key is never used for anything else
+const KEY_INFO: &[u8] = b"passkeeper-share-v1";
+const WRAP_INFO: &[u8] = b"passkeeper-wrap-v1";
+
+/// Why sharing failed.
+#[derive(Debug)]
+pub enum ShareError {
+    /// The login holds no identity, e.g. the identity belongs to the other login
+    NoIdentity,
+    /// The recipient has no identity
+    UnknownRecipient,
+    /// The bundle is not signed by its sender, or not sealed for this user
+    BadSignature,
+    /// The bundle cannot be parsed
+    Malformed,
+    /// Sealing or opening failed
+    Crypto,
+    /// Reading or writing a file failed
+    Io(io::Error),
+}
+
+impl From<io::Error> for ShareError {
+    fn from(e: io::Error) -> Self {
+        ShareError::Io(e)
+    }
+}
+
+/// The public keys of a user, as read by other users.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicIdentity {
+    pub username: String,
+    /// Base64 of the X25519 key bundles are sealed for
+    pub x25519: String,
+    /// Base64 of the Ed25519 key bundles are signed with
+    pub ed25519: String,
+}
+
+impl PublicIdentity {
+    /// Short hash of both keys, for users to compare out of band.
+    pub fn fingerprint(&self) -> String {
+        let hash = hash_bytes(format!("{}:{}", self.x25519, self.ed25519).as_bytes()).to_uppercase();
+        hash.as_bytes()[..20]
+            .chunks(4)
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn x25519_key(&self) -> Option<PublicKey> {
+        Some(PublicKey::from(decode_32(&self.x25519)?))
+    }
+
+    fn ed25519_key(&self) -> Option<VerifyingKey> {
+        VerifyingKey::from_bytes(&decode_32(&self.ed25519)?).ok()
+    }
+}
+
+/// The identity file.
+#[derive(Serialize, Deserialize)]
+struct IdentityFile {
+    #[serde(flatten)]
+    public: PublicIdentity,
+    /// The secret keys, sealed with the login key
+    sealed: String,
+    /// The identity of the other login of the account, or filler that looks the same.
+    /// Missing in files written before the other login had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    other: Option<OtherIdentity>,
+}
+
+/// The public keys and sealed secret keys of the other login.
+#[derive(Serialize, Deserialize)]
+struct OtherIdentity {
+    x25519: String,
+    ed25519: String,
+    sealed: String,
+}
+
+/// The sealed part of the identity file, base64 of both secret keys.
+#[derive(Serialize, Deserialize)]
+struct SecretKeys {
+    x25519: String,
+    ed25519: String,
+}
+
+/// An opened identity. The secret keys are zeroed on drop.
+struct Identity {
+    public: PublicIdentity,
+    x25519: StaticSecret,
+    ed25519: SigningKey,
+}
+
+/// A key sealed for the X25519 key of a user, e.g. the key of a team vault for a member.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// Base64 of the one-off X25519 key of the wrapping side
+    pub ephemeral: String,
+    /// Base64 of the encrypted key
+    pub sealed: String,
+}
+
+/// Entries sealed by one user for another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    pub id: String,
+    pub sender: String,
+    pub recipient: String,
+    /// Base64 of the X25519 key of the recipient the bundle is sealed for
+    pub recipient_key: String,
+    /// Base64 of the one-off X25519 key of the sender
+    pub ephemeral: String,
+    /// Seconds since the epoch
+    pub created: u64,
+    /// Base64 of the encrypted entries
+    pub sealed: String,
+    /// Base64 of the Ed25519 signature of the sender over the fields above
+    pub signature: String,
+}
+
+impl Bundle {
+    /// The signed bytes, every field prefixed with its length so fields cannot run into each other.
+    fn signed_message(&self) -> Vec<u8> {
+        let version = self.version.to_string();
+        let created = self.created.to_string();
+        let fields = [
+            KEY_INFO,
+            version.as_bytes(),
+            self.id.as_bytes(),
+            self.sender.as_bytes(),
+            self.recipient.as_bytes(),
+            self.recipient_key.as_bytes(),
+            self.ephemeral.as_bytes(),
+            created.as_bytes(),
+            self.sealed.as_bytes(),
+        ];
+        let mut message = Vec::new();
+        for field in fields {
+            message.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            message.extend_from_slice(field);
+        }
+        message
+    }
+
+    /// Checks the signature against the published key of the sender.
+    fn verify(&self) -> Result<(), ShareError> {
+        verify(&self.sender, &self.signed_message(), &self.signature)
+    }
+}
+
+/// A bundle waiting in the inbox, as listed to the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleInfo {
+    pub id: String,
+    pub sender: String,
+    pub created: u64,
+    /// `false` if the signature does not match the published key of the sender
+    pub verified: bool,
+}
+
+fn data_dir() -> PathBuf {
+    current_dir().unwrap().join("data")
+}
+
+fn identity_path(username: &str) -> String {
+    format!("{}/{}.identity", data_dir().display(), username)
+}
+
+fn inbox_dir(username: &str) -> PathBuf {
+    data_dir().join(INBOX_DIR).join(username)
+}
+
+fn bundle_path(username: &str, bundle_id: &str) -> Result<PathBuf, ShareError> {
+    // Ids are hex, anything else could leave the inbox
+    if bundle_id.is_empty() || !bundle_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ShareError::Malformed);
+    }
+    Ok(inbox_dir(username).join(format!("{}.{}", bundle_id, BUNDLE_EXTENSION)))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    general_purpose::STANDARD.encode(bytes)
+}
+
+fn decode_32(text: &str) -> Option<[u8; 32]> {
+    let bytes = Zeroizing::new(general_purpose::STANDARD.decode(text).ok()?);
+    bytes.as_slice().try_into().ok()
+}
+
+fn read_identity_file(path: &str) -> io::Result<IdentityFile> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn seal_keys(login_key: &VaultKey, keys: &SecretKeys) -> Result<String, ShareError> {
+    let plain = Zeroizing::new(serde_json::to_string(keys).map_err(|_| ShareError::Crypto)?);
+    let encrypted = encrypt_data(login_key, &plain).map_err(|_| ShareError::Crypto)?;
+    Ok(encode(&encrypted))
+}
+
+/// New secret keys and their public keys.
+fn new_keys(username: &str) -> (PublicIdentity, SecretKeys) {
+    let x25519 = StaticSecret::from(random_bytes::<32>());
+    let ed25519 = SigningKey::from_bytes(&random_bytes::<32>());
+    let public = PublicIdentity {
+        username: username.to_string(),
+        x25519: encode(PublicKey::from(&x25519).as_bytes()),
+        ed25519: encode(ed25519.verifying_key().as_bytes()),
+    };
+    let keys = SecretKeys {
+        x25519: encode(x25519.as_bytes()),
+        ed25519: encode(ed25519.as_bytes()),
+    };
+    (public, keys)
+}
+
+/// An identity for the other login, sealed with its key, or filler sealed with a key nobody keeps.
+fn other_identity(username: &str, login_key: Option<&VaultKey>) -> Result<OtherIdentity, ShareError> {
+    let (public, keys) = new_keys(username);
+    let throwaway = Zeroizing::new(random_bytes::<32>());
+    Ok(OtherIdentity {
+        x25519: public.x25519,
+        ed25519: public.ed25519,
+        sealed: seal_keys(login_key.unwrap_or(&throwaway), &keys)?,
+    })
+}
+
+fn open_keys(login_key: &VaultKey, sealed: &str) -> Option<SecretKeys> {
+    let encrypted = general_purpose::STANDARD.decode(sealed).ok()?;
+    let plain = Zeroizing::new(decrypt_data(login_key, &encrypted).ok()?);
+    serde_json::from_str(&plain).ok()
+}
+
+/// The public keys of a user, `None` if the user has no identity.
+pub fn public_identity(username: &str) -> Option<PublicIdentity> {
+    let path = identity_path(username);
+    let _lock = lock::shared(&path).ok()?;
+    read_identity_file(&path).ok().map(|file| file.public)
+}
+
+/// Creates the identities of a new account: the published one for `login_key`, and one for
+/// the duress login, or filler when there is none.
+pub fn create_identities(username: &str, login_key: &VaultKey, duress_key: Option<&VaultKey>) -> Result<PublicIdentity, ShareError> {
+    let path = identity_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let (public, keys) = new_keys(username);
+    let file = IdentityFile {
+        public: public.clone(),
+        sealed: seal_keys(login_key, &keys)?,
+        other: Some(other_identity(username, duress_key)?),
+    };
+    fs::write(&path, serde_json::to_string(&file).unwrap_or_default())?;
+    Ok(public)
+}
+
+/// The public keys of the identity the login opens, creating it if the login has none yet.
+/// A file without an identity for the other login gets one for this login.
+pub fn ensure_identity(username: &str, login_key: &VaultKey) -> Result<PublicIdentity, ShareError> {
+    let path = identity_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut file = match read_identity_file(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            drop(_lock);
+            return create_identities(username, login_key, None);
+        },
+        Err(e) => return Err(e.into()),
+    };
+    if let Some((public, _)) = open_keys_of(&file, login_key) {
+        return Ok(public);
+    }
+    if file.other.is_some() {
+        return Err(ShareError::NoIdentity);
+    }
+    let (public, keys) = new_keys(username);
+    file.other = Some(OtherIdentity {
+        x25519: public.x25519.clone(),
+        ed25519: public.ed25519.clone(),
+        sealed: seal_keys(login_key, &keys)?,
+    });
+    fs::write(&path, serde_json::to_string(&file).unwrap_or_default())?;
+    Ok(public)
+}
+
+/// The public and secret keys of the identity of the file the login opens.
+fn open_keys_of(file: &IdentityFile, login_key: &VaultKey) -> Option<(PublicIdentity, SecretKeys)> {
+    if let Some(keys) = open_keys(login_key, &file.sealed) {
+        return Some((file.public.clone(), keys));
+    }
+    let other = file.other.as_ref()?;
+    let keys = open_keys(login_key, &other.sealed)?;
+    let public = PublicIdentity {
+        username: file.public.username.clone(),
+        x25519: other.x25519.clone(),
+        ed25519: other.ed25519.clone(),
+    };
+    Some((public, keys))
+}
+
+/// Opens the identity of a login.
+fn open_identity(username: &str, login_key: &VaultKey) -> Result<Identity, ShareError> {
+    let path = identity_path(username);
+    let file = match lock::shared(&path).and_then(|_lock| read_identity_file(&path)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(ShareError::NoIdentity),
+        Err(e) => return Err(e.into()),
+    };
+    let (public, keys) = open_keys_of(&file, login_key).ok_or(ShareError::NoIdentity)?;
+    let x25519 = Zeroizing::new(decode_32(&keys.x25519).ok_or(ShareError::Crypto)?);
+    let ed25519 = Zeroizing::new(decode_32(&keys.ed25519).ok_or(ShareError::Crypto)?);
+    Ok(Identity {
+        public,
+        x25519: StaticSecret::from(*x25519),
+        ed25519: SigningKey::from_bytes(&ed25519),
+    })
+}
+
+/// Seals the identity again after the login key changed, e.g. on a KDF upgrade.
+pub fn reseal(username: &str, old_key: &VaultKey, new_key: &VaultKey) -> Result<(), ShareError> {
+    let path = identity_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut file = match read_identity_file(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    // The identity of the other login stays as it is
+    if let Some(keys) = open_keys(old_key, &file.sealed) {
+        file.sealed = seal_keys(new_key, &keys)?;
+    } else if let Some(other) = file.other.as_mut() {
+        let Some(keys) = open_keys(old_key, &other.sealed) else {
+            return Ok(());
+        };
+        other.sealed = seal_keys(new_key, &keys)?;
+    } else {
+        return Ok(());
+    }
+    fs::write(&path, serde_json::to_string(&file).unwrap_or_default())?;
+    Ok(())
+}
+
+/// Drops the identities the login does not open. The identity of the login becomes the
+/// published one, and the inbox sealed for another identity goes with it.
+/// Used once the other logins are wiped.
+pub fn remove_foreign(username: &str, login_key: &VaultKey) -> Result<(), ShareError> {
+    let path = identity_path(username);
+    {
+        let _lock = lock::exclusive(&path)?;
+        let mut file = match read_identity_file(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if open_keys(login_key, &file.sealed).is_some() {
+            if file.other.is_some() {
+                file.other = Some(other_identity(username, None)?);
+                fs::write(&path, serde_json::to_string(&file).unwrap_or_default())?;
+            }
+            return Ok(());
+        }
+        match open_keys_of(&file, login_key) {
+            Some((public, _)) => {
+                let other = file.other.take().ok_or(ShareError::NoIdentity)?;
+                file.public = public;
+                file.sealed = other.sealed;
+                file.other = Some(other_identity(username, None)?);
+                fs::write(&path, serde_json::to_string(&file).unwrap_or_default())?;
+            },
+            None => fs::remove_file(&path)?,
+        }
+    }
+    match fs::remove_dir_all(inbox_dir(username)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The key both sides agree on, for a bundle or a wrapped key.
+fn agreed_key(secret: &StaticSecret, their_key: &PublicKey, ephemeral: &PublicKey, recipient_key: &PublicKey, info: &[u8]) -> Result<VaultKey, ShareError> {
+    let shared = secret.diffie_hellman(their_key);
+    // A low order key from a forged identity would agree on a known value
+    if !shared.was_contributory() {
+        return Err(ShareError::Crypto);
+    }
+    let mut salt = Vec::with_capacity(64);
+    salt.extend_from_slice(ephemeral.as_bytes());
+    salt.extend_from_slice(recipient_key.as_bytes());
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(info, key.as_mut())
+        .map_err(|_| ShareError::Crypto)?;
+    Ok(key)
+}
+
+/// Seals entries for a recipient, signed by the sender.
+pub fn seal_bundle(sender: &str, login_key: &VaultKey, recipient: &str, entries: &[Value]) -> Result<Bundle, ShareError> {
+    let identity = open_identity(sender, login_key)?;
+    let recipient_identity = public_identity(recipient).ok_or(ShareError::UnknownRecipient)?;
+    let recipient_key = recipient_identity.x25519_key().ok_or(ShareError::UnknownRecipient)?;
+    let ephemeral_secret = StaticSecret::from(random_bytes::<32>());
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let key = agreed_key(&ephemeral_secret, &recipient_key, &ephemeral, &recipient_key, KEY_INFO)?;
+    let plain = Zeroizing::new(serde_json::to_string(entries).map_err(|_| ShareError::Crypto)?);
+    let sealed = encrypt_data(&key, &plain).map_err(|_| ShareError::Crypto)?;
+    let mut bundle = Bundle {
+        version: BUNDLE_VERSION,
+        id: hex::encode(random_bytes::<16>()),
+        sender: identity.public.username.clone(),
+        recipient: recipient.to_string(),
+        recipient_key: recipient_identity.x25519,
+        ephemeral: encode(ephemeral.as_bytes()),
+        created: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        sealed: encode(&sealed),
+        signature: String::new(),
+    };
+    bundle.signature = encode(&identity.ed25519.sign(&bundle.signed_message()).to_bytes());
+    Ok(bundle)
+}
+
+/// Puts a bundle in the inbox of its recipient and returns its path.
+pub fn deliver(bundle: &Bundle) -> Result<PathBuf, ShareError> {
+    fs::create_dir_all(inbox_dir(&bundle.recipient))?;
+    let path = bundle_path(&bundle.recipient, &bundle.id)?;
+    // Written aside and renamed, so the recipient never lists a half-written bundle
+    let partial = path.with_extension("partial");
+    fs::write(&partial, serde_json::to_string(bundle).unwrap_or_default())?;
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+fn read_bundle(path: &PathBuf) -> Result<Bundle, ShareError> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|_| ShareError::Malformed)
+}
+
+/// The bundles waiting in the inbox of a user, oldest first.
+pub fn inbox(username: &str) -> Vec<BundleInfo> {
+    let Ok(dir) = fs::read_dir(inbox_dir(username)) else {
+        return Vec::new();
+    };
+    let mut bundles: Vec<BundleInfo> = dir
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(BUNDLE_EXTENSION))
+        .filter_map(|path| read_bundle(&path).ok())
+        .filter(|bundle| bundle.recipient == username)
+        .map(|bundle| BundleInfo {
+            verified: bundle.verify().is_ok(),
+            id: bundle.id,
+            sender: bundle.sender,
+            created: bundle.created,
+        })
+        .collect();
+    bundles.sort_by_key(|bundle| bundle.created);
+    bundles
+}
+
+/// Opens a bundle of the inbox. Returns the sender and the entries.
+pub fn open_bundle(username: &str, login_key: &VaultKey, bundle_id: &str) -> Result<(String, Vec<Value>), ShareError> {
+    let bundle = read_bundle(&bundle_path(username, bundle_id)?)?;
+    if bundle.version != BUNDLE_VERSION {
+        return Err(ShareError::Malformed);
+    }
+    let identity = open_identity(username, login_key)?;
+    if bundle.recipient != username || bundle.recipient_key != identity.public.x25519 {
+        return Err(ShareError::BadSignature);
+    }
+    bundle.verify()?;
+    let ephemeral = PublicKey::from(decode_32(&bundle.ephemeral).ok_or(ShareError::Malformed)?);
+    let recipient_key = PublicKey::from(&identity.x25519);
+    let key = agreed_key(&identity.x25519, &ephemeral, &ephemeral, &recipient_key, KEY_INFO)?;
+    let encrypted = general_purpose::STANDARD.decode(&bundle.sealed).map_err(|_| ShareError::Malformed)?;
+    let plain = Zeroizing::new(decrypt_data(&key, &encrypted).map_err(|_| ShareError::Crypto)?);
+    let entries = serde_json::from_str(&plain).map_err(|_| ShareError::Malformed)?;
+    Ok((bundle.sender, entries))
+}
+
+/// Removes a bundle from the inbox.
+pub fn discard(username: &str, bundle_id: &str) -> Result<(), ShareError> {
+    fs::remove_file(bundle_path(username, bundle_id)?)?;
+    Ok(())
+}
+
+/// Signs a message with the identity of a login. Returns the base64 signature.
+pub fn sign(username: &str, login_key: &VaultKey, message: &[u8]) -> Result<String, ShareError> {
+    let identity = open_identity(username, login_key)?;
+    Ok(encode(&identity.ed25519.sign(message).to_bytes()))
+}
+
+/// Checks a base64 signature against the published keys of a user, of either login.
+pub fn verify(username: &str, message: &[u8], signature: &str) -> Result<(), ShareError> {
+    let path = identity_path(username);
+    let file = lock::shared(&path)
+        .and_then(|_lock| read_identity_file(&path))
+        .map_err(|_| ShareError::BadSignature)?;
+    let bytes = general_purpose::STANDARD.decode(signature).map_err(|_| ShareError::BadSignature)?;
+    let signature = Signature::from_slice(&bytes).map_err(|_| ShareError::BadSignature)?;
+    let other = file.other.as_ref().map(|other| PublicIdentity {
+        username: file.public.username.clone(),
+        x25519: other.x25519.clone(),
+        ed25519: other.ed25519.clone(),
+    });
+    let verified = [Some(&file.public), other.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(PublicIdentity::ed25519_key)
+        .any(|key| key.verify_strict(message, &signature).is_ok());
+    if verified { Ok(()) } else { Err(ShareError::BadSignature) }
+}
+
+/// Seals a key for the published X25519 key of a user.
+pub fn wrap_key(recipient: &PublicIdentity, key: &VaultKey) -> Result<WrappedKey, ShareError> {
+    let recipient_key = recipient.x25519_key().ok_or(ShareError::UnknownRecipient)?;
+    let ephemeral_secret = StaticSecret::from(random_bytes::<32>());
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let wrapping_key = agreed_key(&ephemeral_secret, &recipient_key, &ephemeral, &recipient_key, WRAP_INFO)?;
+    let sealed = encrypt_bytes(&wrapping_key, key.as_ref()).map_err(|_| ShareError::Crypto)?;
+    Ok(WrappedKey {
+        ephemeral: encode(ephemeral.as_bytes()),
+        sealed: encode(&sealed),
+    })
+}
+
+/// Opens a key sealed for the identity of a login.
+pub fn unwrap_key(username: &str, login_key: &VaultKey, wrapped: &WrappedKey) -> Result<VaultKey, ShareError> {
+    let identity = open_identity(username, login_key)?;
+    let ephemeral = PublicKey::from(decode_32(&wrapped.ephemeral).ok_or(ShareError::Malformed)?);
+    let recipient_key = PublicKey::from(&identity.x25519);
+    let wrapping_key = agreed_key(&identity.x25519, &ephemeral, &ephemeral, &recipient_key, WRAP_INFO)?;
+    let encrypted = general_purpose::STANDARD.decode(&wrapped.sealed).map_err(|_| ShareError::Malformed)?;
+    let plain = Zeroizing::new(decrypt_bytes(&wrapping_key, &encrypted).map_err(|_| ShareError::Crypto)?);
+    let key: [u8; 32] = plain.as_slice().try_into().map_err(|_| ShareError::Malformed)?;
+    Ok(Zeroizing::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::module::testing::{data_dir, login_key, username};
+    use super::*;
+
+    fn account(name: &str) -> (String, VaultKey) {
+        data_dir();
+        let username = username(name);
+        let key = login_key();
+        create_identities(&username, &key, None).unwrap();
+        (username, key)
+    }
+
+    #[test]
+    fn a_bundle_opens_for_its_recipient_only() {
+        let (alice, alice_key) = account("alice");
+        let (bob, bob_key) = account("bob");
+        let entries = vec![json!({ "service": "mail", "account": "me", "password": "pw" })];
+        let bundle = seal_bundle(&alice, &alice_key, &bob, &entries).unwrap();
+        deliver(&bundle).unwrap();
+        assert!(inbox(&bob).iter().any(|info| info.id == bundle.id && info.verified));
+        assert!(open_bundle(&bob, &alice_key, &bundle.id).is_err());
+        let (sender, opened) = open_bundle(&bob, &bob_key, &bundle.id).unwrap();
+        assert_eq!(sender, alice);
+        assert_eq!(opened, entries);
+    }
+
+    #[test]
+    fn a_tampered_bundle_is_refused() {
+        let (alice, alice_key) = account("alice");
+        let (bob, bob_key) = account("bob");
+        let mut bundle = seal_bundle(&alice, &alice_key, &bob, &[json!({ "service": "mail" })]).unwrap();
+        bundle.created += 1;
+        deliver(&bundle).unwrap();
+        assert!(matches!(open_bundle(&bob, &bob_key, &bundle.id), Err(ShareError::BadSignature)));
+        assert!(inbox(&bob).iter().any(|info| info.id == bundle.id && !info.verified));
+    }
+
+    #[test]
+    fn a_bundle_signed_by_someone_else_is_refused() {
+        let (alice, _) = account("alice");
+        let (mallory, mallory_key) = account("mallory");
+        let (bob, bob_key) = account("bob");
+        let mut bundle = seal_bundle(&mallory, &mallory_key, &bob, &[json!({ "service": "mail" })]).unwrap();
+        bundle.sender = alice;
+        bundle.signature = sign(&mallory, &mallory_key, &bundle.signed_message()).unwrap();
+        deliver(&bundle).unwrap();
+        assert!(matches!(open_bundle(&bob, &bob_key, &bundle.id), Err(ShareError::BadSignature)));
+    }
+
+    #[test]
+    fn signatures_are_checked_against_the_signer() {
+        let (alice, alice_key) = account("alice");
+        let (mallory, mallory_key) = account("mallory");
+        let signature = sign(&alice, &alice_key, b"message").unwrap();
+        assert!(verify(&alice, b"message", &signature).is_ok());
+        assert!(verify(&alice, b"massage", &signature).is_err());
+        assert!(verify(&mallory, b"message", &signature).is_err());
+        let forged = sign(&mallory, &mallory_key, b"message").unwrap();
+        assert!(verify(&alice, b"message", &forged).is_err());
+    }
+
+    #[test]
+    fn a_wrapped_key_opens_for_its_recipient_only() {
+        let (alice, alice_key) = account("alice");
+        let (bob, bob_key) = account("bob");
+        let key = login_key();
+        let wrapped = wrap_key(&public_identity(&bob).unwrap(), &key).unwrap();
+        assert_eq!(unwrap_key(&bob, &bob_key, &wrapped).unwrap(), key);
+        assert!(unwrap_key(&alice, &alice_key, &wrapped).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! # Vault Store Module
+//!
+//! `store` is where a user's vault is persisted.
+//!
+//! [`VaultStore`] covers whole-vault reads and writes, used by login, repair and migration,
+//! and per-entry operations on an opened slot, used by the data commands.
+//! The file backend keeps `data/<username>.dat` and rewrites it on every change.
+//! The SQLite backend keeps `data/<username>.db` with one encrypted row per entry,
+//! so saving an entry only writes that row and the manifest.
+//! Both backends hold the vault lock of the `lock` module while they write.
+
+pub mod file;
+pub mod sqlite;
+
+use std::{
+    env::current_dir,
+    fmt,
+    fs,
+    io,
+    path::Path,
+    time::SystemTime,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::{
+    lock::{self, VaultLock},
+    session::VaultKey,
+    vault::{PayloadError, Payload, VaultFile},
+};
+pub use file::FileStore;
+pub use sqlite::SqliteStore;
+
+/// Kind of a storage backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    File,
+    Sqlite,
+}
+
+impl StoreKind {
+    /// Parses the name used by the frontend.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "file" => Some(StoreKind::File),
+            "sqlite" => Some(StoreKind::Sqlite),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// Reading or writing the backend failed
+    Backend(String),
+    /// The stored payload could not be opened
+    Payload(PayloadError),
+    /// Sealing the payload failed
+    Encryption,
+    /// Another process kept the vault locked
+    Busy,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Backend(e) => write!(f, "storage error: {}", e),
+            StoreError::Payload(e) => write!(f, "{}", e),
+            StoreError::Encryption => write!(f, "encryption failed"),
+            StoreError::Busy => write!(f, "the vault is locked by another process"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::WouldBlock => StoreError::Busy,
+            _ => StoreError::Backend(e.to_string()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<PayloadError> for StoreError {
+    fn from(e: PayloadError) -> Self {
+        StoreError::Payload(e)
+    }
+}
+
+/// Modification time and size of the storage, to notice changes made by someone else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    /// Stamp of a file, or of a missing file.
+    pub fn of(path: &str) -> Self {
+        match fs::metadata(path) {
+            Ok(metadata) => Stamp {
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+            },
+            Err(_) => Stamp { modified: None, len: 0 },
+        }
+    }
+}
+
+/// Locks a vault for writing.
+fn lock_exclusive(path: &str) -> Result<VaultLock, StoreError> {
+    Ok(lock::exclusive(path)?)
+}
+
+/// Locks a vault for reading.
+fn lock_shared(path: &str) -> Result<VaultLock, StoreError> {
+    Ok(lock::shared(path)?)
+}
+
+/// An entry as listed without decrypting it.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryInfo {
+    pub id: String,
+    pub label: String,
+}
+
+/// Facts about an opened vault.
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultMeta {
+    pub backend: StoreKind,
+    pub id: String,
+    pub generation: u64,
+    pub entries: usize,
+}
+
+/// Persistence of one user's vault.
+///
+/// Slot indices and vault keys come from [`VaultStore::read`] and login.
+/// Callers pick the generation of every write, see the `generation` module.
+pub trait VaultStore {
+    /// Kind of the backend.
+    fn kind(&self) -> StoreKind;
+
+    /// Reads every slot: headers and payloads.
+    fn read(&self) -> Result<VaultFile, StoreError>;
+
+    /// Replaces every slot.
+    fn write(&self, vault_file: &VaultFile) -> Result<(), StoreError>;
+
+    /// Deletes the whole vault.
+    fn remove(&self) -> Result<(), StoreError>;
+
+    /// Current stamp of the storage. Changes with every write.
+    fn stamp(&self) -> Stamp;
+
+    /// Loads and authenticates every entry of a slot.
+    fn load(&self, slot: usize, vault_key: &VaultKey) -> Result<Payload, StoreError>;
+
+    /// Replaces the payload of a slot.
+    fn replace(&self, slot: usize, vault_key: &VaultKey, payload: &Payload) -> Result<(), StoreError>;
+
+    /// Deletes the entries with the ids in `deletes`, then inserts the entries in `upserts`
+    /// or replaces those with the same id, all in one write as the given generation.
+    fn apply(&self, slot: usize, vault_key: &VaultKey, upserts: &[Value], deletes: &[String], generation: u64) -> Result<(), StoreError>;
+
+    /// Inserts an entry, or replaces the entry with the same id, as the given generation.
+    fn save_entry(&self, slot: usize, vault_key: &VaultKey, entry: &Value, generation: u64) -> Result<(), StoreError> {
+        self.apply(slot, vault_key, std::slice::from_ref(entry), &[], generation)
+    }
+
+    /// Deletes an entry by id, as the given generation.
+    fn delete_entry(&self, slot: usize, vault_key: &VaultKey, entry_id: &str, generation: u64) -> Result<(), StoreError> {
+        self.apply(slot, vault_key, &[], &[entry_id.to_string()], generation)
+    }
+
+    /// Ids and labels of the entries of a slot, read from its manifest.
+    fn list(&self, slot: usize, vault_key: &VaultKey) -> Result<Vec<EntryInfo>, StoreError>;
+
+    /// Vault id, generation and entry count of a slot.
+    fn metadata(&self, slot: usize, vault_key: &VaultKey) -> Result<VaultMeta, StoreError>;
+}
+
+fn data_path(username: &str, kind: StoreKind) -> String {
+    let extension = match kind {
+        StoreKind::File => "dat",
+        StoreKind::Sqlite => "db",
+    };
+    format!("{}/data/{}.{}", current_dir().unwrap().display(), username, extension)
+}
+
+/// The backend a user's vault is kept in, if the user exists.
+pub fn kind_of(username: &str) -> Option<StoreKind> {
+    [StoreKind::Sqlite, StoreKind::File]
+        .into_iter()
+        .find(|kind| Path::new(&data_path(username, *kind)).exists())
+}
+
+/// The users with a vault in any backend, sorted by name.
+pub fn users() -> Vec<String> {
+    let Ok(dir) = fs::read_dir(current_dir().unwrap().join("data")) else {
+        return Vec::new();
+    };
+    let mut users: Vec<String> = dir
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("dat") | Some("db")))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .collect();
+    users.sort();
+    users.dedup();
+    users
+}
+
+/// Returns `true` if the user has a vault in any backend.
+pub fn exists(username: &str) -> bool {
+    kind_of(username).is_some()
+}
+
+/// Opens the backend of an existing or new vault.
+pub fn open_kind(kind: StoreKind, username: &str) -> Result<Box<dyn VaultStore>, StoreError> {
+    let path = data_path(username, kind);
+    Ok(match kind {
+        StoreKind::File => Box::new(FileStore::new(path)),
+        StoreKind::Sqlite => Box::new(SqliteStore::open(path)?),
+    })
+}
+
+/// Opens the vault of an existing user.
+pub fn open(username: &str) -> Result<Box<dyn VaultStore>, StoreError> {
+    match kind_of(username) {
+        Some(kind) => open_kind(kind, username),
+        None => Err(StoreError::Backend(format!("no vault for {}", username))),
+    }
+}
@@ -0,0 +1,145 @@
+//! File backend: both slots in `data/<username>.dat`, rewritten on every change.
+//!
+//! Reads hold the shared vault lock, so they never see a file another process is rewriting.
+
+use std::fs;
+use serde_json::Value;
+use crate::module::{
+    session::VaultKey,
+    vault::{self, entry_id, split_line, Manifest, Payload, VaultFile},
+};
+use super::{lock_exclusive, lock_shared, EntryInfo, Stamp, StoreError, StoreKind, VaultMeta, VaultStore};
+
+pub struct FileStore {
+    path: String,
+}
+
+impl FileStore {
+    pub fn new(path: String) -> Self {
+        FileStore { path }
+    }
+
+    fn payload_line(&self, slot: usize) -> Result<String, StoreError> {
+        let vault_file = self.read()?;
+        Ok(vault_file.slots.get(slot).map(|s| s.payload.clone()).unwrap_or_default())
+    }
+
+    // Read-modify-write of a payload, under the lock the caller holds
+    fn update_payload<F: FnOnce(&mut Payload)>(&self, slot: usize, vault_key: &VaultKey, change: F) -> Result<(), StoreError> {
+        let mut vault_file = vault::read(&self.path)?;
+        let line = vault_file.slots.get(slot).map(|s| s.payload.as_str()).unwrap_or_default();
+        let mut payload = Payload::open(vault_key, line)?;
+        change(&mut payload);
+        vault_file.slots[slot].payload = payload.seal(vault_key).map_err(|_| StoreError::Encryption)?;
+        Ok(vault::write(&self.path, &vault_file)?)
+    }
+
+    fn manifest(&self, slot: usize, vault_key: &VaultKey) -> Result<Manifest, StoreError> {
+        let line = self.payload_line(slot)?;
+        Ok(Manifest::open(vault_key, split_line(&line).0)?)
+    }
+}
+
+impl VaultStore for FileStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::File
+    }
+
+    fn read(&self) -> Result<VaultFile, StoreError> {
+        let _lock = lock_shared(&self.path)?;
+        Ok(vault::read(&self.path)?)
+    }
+
+    fn write(&self, vault_file: &VaultFile) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        Ok(vault::write(&self.path, vault_file)?)
+    }
+
+    fn remove(&self) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        Ok(fs::remove_file(&self.path)?)
+    }
+
+    fn stamp(&self) -> Stamp {
+        Stamp::of(&self.path)
+    }
+
+    fn load(&self, slot: usize, vault_key: &VaultKey) -> Result<Payload, StoreError> {
+        Ok(Payload::open(vault_key, &self.payload_line(slot)?)?)
+    }
+
+    fn replace(&self, slot: usize, vault_key: &VaultKey, payload: &Payload) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        let mut vault_file = vault::read(&self.path)?;
+        vault_file.slots[slot].payload = payload.seal(vault_key).map_err(|_| StoreError::Encryption)?;
+        Ok(vault::write(&self.path, &vault_file)?)
+    }
+
+    fn apply(&self, slot: usize, vault_key: &VaultKey, upserts: &[Value], deletes: &[String], generation: u64) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        self.update_payload(slot, vault_key, |payload| {
+            payload.entries.retain(|e| !deletes.iter().any(|id| id == entry_id(e)));
+            for entry in upserts {
+                match payload.entries.iter_mut().find(|e| entry_id(e) == entry_id(entry)) {
+                    Some(existing) => *existing = entry.clone(),
+                    None => payload.entries.push(entry.clone()),
+                }
+            }
+            payload.generation = generation;
+        })
+    }
+
+    fn list(&self, slot: usize, vault_key: &VaultKey) -> Result<Vec<EntryInfo>, StoreError> {
+        let manifest = self.manifest(slot, vault_key)?;
+        Ok(manifest.chunks.into_iter().map(|c| EntryInfo { id: c.id, label: c.label }).collect())
+    }
+
+    fn metadata(&self, slot: usize, vault_key: &VaultKey) -> Result<VaultMeta, StoreError> {
+        let manifest = self.manifest(slot, vault_key)?;
+        Ok(VaultMeta {
+            backend: self.kind(),
+            id: manifest.id,
+            generation: manifest.generation,
+            entries: manifest.chunks.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use serde_json::json;
+    use zeroize::Zeroizing;
+    use crate::module::vault::{filler_slot, make_skey, new_entry_id, Slot};
+    use super::*;
+
+    fn temp_store() -> FileStore {
+        let path = env::temp_dir().join(format!("passkeeper-file-{}.dat", new_entry_id()));
+        FileStore::new(path.display().to_string())
+    }
+
+    fn entry(service: &str) -> Value {
+        json!({ "id": new_entry_id(), "service": service, "account": "me", "password": "pw" })
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let key: VaultKey = Zeroizing::new([11u8; 32]);
+        let store = temp_store();
+        let real = Slot { kdf: None, skey: make_skey(&key), payload: Payload::new().seal(&key).unwrap() };
+        store.write(&VaultFile { slots: vec![filler_slot(None), real] }).unwrap();
+        let (mail, bank, shop) = (entry("mail"), entry("bank"), entry("shop"));
+        store.save_entry(1, &key, &mail, 1).unwrap();
+        store.apply(1, &key, &[bank.clone(), shop.clone()], &[], 2).unwrap();
+        store.apply(1, &key, &[], &[entry_id(&mail).to_string(), entry_id(&shop).to_string()], 3).unwrap();
+
+        let payload = store.load(1, &key).unwrap();
+        assert_eq!(payload.entries, vec![bank]);
+        assert_eq!(payload.generation, 3);
+        let meta = store.metadata(1, &key).unwrap();
+        assert_eq!((meta.generation, meta.entries), (3, 1));
+        // The other slot is left as it was
+        assert_eq!(store.read().unwrap().find_slot(&make_skey(&key)), Some(1));
+        store.remove().unwrap();
+    }
+}
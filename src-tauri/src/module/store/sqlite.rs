@@ -0,0 +1,262 @@
+//! SQLite backend: `data/<username>.db` with one row per slot and one row per entry chunk.
+//!
+//! The slot row holds the header line and the sealed manifest. Entry chunks are keyed by
+//! their digest, which the manifest records, so saving an entry touches only its own row
+//! and the manifest.
+//!
+//! SQLite keeps readers consistent by itself. Writes also hold the vault lock, so a second
+//! process waits for its turn instead of failing on a busy database.
+
+use std::fs;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use crate::module::{
+    crypto::myhash::hash_data,
+    session::VaultKey,
+    vault::{join_line, seal_entry, split_line, Manifest, Payload, Slot, VaultFile},
+};
+use super::{lock_exclusive, EntryInfo, Stamp, StoreError, StoreKind, VaultMeta, VaultStore};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS slots (
+        slot INTEGER PRIMARY KEY,
+        header TEXT NOT NULL,
+        manifest TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS chunks (
+        slot INTEGER NOT NULL,
+        digest TEXT NOT NULL,
+        chunk TEXT NOT NULL,
+        PRIMARY KEY (slot, digest)
+    );
+";
+
+pub struct SqliteStore {
+    path: String,
+}
+
+impl SqliteStore {
+    /// Opens the database, creating it and its tables if needed.
+    pub fn open(path: String) -> Result<Self, StoreError> {
+        let store = SqliteStore { path };
+        store.connect()?.execute_batch(SCHEMA)?;
+        Ok(store)
+    }
+
+    // A connection per operation, so the file is never held open between commands
+    fn connect(&self) -> Result<Connection, StoreError> {
+        Ok(Connection::open(&self.path)?)
+    }
+
+    fn manifest_chunk(conn: &Connection, slot: usize) -> Result<String, StoreError> {
+        let chunk = conn
+            .query_row("SELECT manifest FROM slots WHERE slot = ?1", params![slot as i64], |row| row.get(0))
+            .optional()?;
+        Ok(chunk.unwrap_or_default())
+    }
+
+    fn entry_chunks(conn: &Connection, slot: usize) -> Result<Vec<String>, StoreError> {
+        let mut statement = conn.prepare("SELECT chunk FROM chunks WHERE slot = ?1 ORDER BY rowid")?;
+        let chunks = statement
+            .query_map(params![slot as i64], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(chunks)
+    }
+
+    fn manifest(&self, slot: usize, vault_key: &VaultKey) -> Result<Manifest, StoreError> {
+        let conn = self.connect()?;
+        Ok(Manifest::open(vault_key, &Self::manifest_chunk(&conn, slot)?)?)
+    }
+}
+
+impl VaultStore for SqliteStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::Sqlite
+    }
+
+    fn read(&self) -> Result<VaultFile, StoreError> {
+        let conn = self.connect()?;
+        let mut statement = conn.prepare("SELECT slot, header, manifest FROM slots ORDER BY slot")?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut slots = Vec::with_capacity(rows.len());
+        for (slot, header, manifest) in rows {
+            let chunks = Self::entry_chunks(&conn, slot as usize)?;
+            slots.push(Slot::from_lines(&header, &join_line(&manifest, &chunks)));
+        }
+        if slots.is_empty() {
+            return Err(StoreError::Backend(String::from("the database holds no vault")));
+        }
+        Ok(VaultFile { slots })
+    }
+
+    fn write(&self, vault_file: &VaultFile) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM chunks", [])?;
+        tx.execute("DELETE FROM slots", [])?;
+        for (index, slot) in vault_file.slots.iter().enumerate() {
+            let (first, chunks) = split_line(&slot.payload);
+            tx.execute(
+                "INSERT INTO slots (slot, header, manifest) VALUES (?1, ?2, ?3)",
+                params![index as i64, slot.header(), first],
+            )?;
+            for chunk in chunks {
+                tx.execute(
+                    "INSERT OR REPLACE INTO chunks (slot, digest, chunk) VALUES (?1, ?2, ?3)",
+                    params![index as i64, hash_data(chunk), chunk],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove(&self) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        Ok(fs::remove_file(&self.path)?)
+    }
+
+    fn stamp(&self) -> Stamp {
+        Stamp::of(&self.path)
+    }
+
+    fn load(&self, slot: usize, vault_key: &VaultKey) -> Result<Payload, StoreError> {
+        let conn = self.connect()?;
+        let first = Self::manifest_chunk(&conn, slot)?;
+        let chunks = Self::entry_chunks(&conn, slot)?;
+        let chunks: Vec<&str> = chunks.iter().map(String::as_str).collect();
+        Ok(Payload::open_parts(vault_key, &first, &chunks)?)
+    }
+
+    fn replace(&self, slot: usize, vault_key: &VaultKey, payload: &Payload) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        let (first, chunks) = payload.seal_parts(vault_key).map_err(|_| StoreError::Encryption)?;
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM chunks WHERE slot = ?1", params![slot as i64])?;
+        for chunk in &chunks {
+            tx.execute(
+                "INSERT OR REPLACE INTO chunks (slot, digest, chunk) VALUES (?1, ?2, ?3)",
+                params![slot as i64, hash_data(chunk), chunk],
+            )?;
+        }
+        tx.execute("UPDATE slots SET manifest = ?1 WHERE slot = ?2", params![first, slot as i64])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn apply(&self, slot: usize, vault_key: &VaultKey, upserts: &[Value], deletes: &[String], generation: u64) -> Result<(), StoreError> {
+        let _lock = lock_exclusive(&self.path)?;
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+        let mut manifest = Manifest::open(vault_key, &Self::manifest_chunk(&tx, slot)?)?;
+        for entry_id in deletes {
+            if let Some(old) = manifest.remove(entry_id) {
+                tx.execute("DELETE FROM chunks WHERE slot = ?1 AND digest = ?2", params![slot as i64, old])?;
+            }
+        }
+        for entry in upserts {
+            let (info, chunk) = seal_entry(vault_key, entry).map_err(|_| StoreError::Encryption)?;
+            let digest = info.digest.clone();
+            if let Some(old) = manifest.put(info) {
+                tx.execute("DELETE FROM chunks WHERE slot = ?1 AND digest = ?2", params![slot as i64, old])?;
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO chunks (slot, digest, chunk) VALUES (?1, ?2, ?3)",
+                params![slot as i64, digest, chunk],
+            )?;
+        }
+        manifest.generation = generation;
+        let sealed = manifest.seal(vault_key).map_err(|_| StoreError::Encryption)?;
+        tx.execute("UPDATE slots SET manifest = ?1 WHERE slot = ?2", params![sealed, slot as i64])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn list(&self, slot: usize, vault_key: &VaultKey) -> Result<Vec<EntryInfo>, StoreError> {
+        let manifest = self.manifest(slot, vault_key)?;
+        Ok(manifest.chunks.into_iter().map(|c| EntryInfo { id: c.id, label: c.label }).collect())
+    }
+
+    fn metadata(&self, slot: usize, vault_key: &VaultKey) -> Result<VaultMeta, StoreError> {
+        let manifest = self.manifest(slot, vault_key)?;
+        Ok(VaultMeta {
+            backend: self.kind(),
+            id: manifest.id,
+            generation: manifest.generation,
+            entries: manifest.chunks.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use serde_json::json;
+    use zeroize::Zeroizing;
+    use crate::module::{
+        store::FileStore,
+        vault::{entry_id, filler_slot, make_skey, new_entry_id},
+    };
+    use super::*;
+
+    fn temp_path(extension: &str) -> String {
+        env::temp_dir().join(format!("passkeeper-sqlite-{}.{}", new_entry_id(), extension)).display().to_string()
+    }
+
+    fn entry(service: &str) -> Value {
+        json!({ "id": new_entry_id(), "service": service, "account": "me", "password": "pw" })
+    }
+
+    fn vault_file(key: &VaultKey, payload: &Payload) -> VaultFile {
+        let real = Slot { kdf: None, skey: make_skey(key), payload: payload.seal(key).unwrap() };
+        VaultFile { slots: vec![real, filler_slot(None)] }
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let key: VaultKey = Zeroizing::new([12u8; 32]);
+        let store = SqliteStore::open(temp_path("db")).unwrap();
+        store.write(&vault_file(&key, &Payload::new())).unwrap();
+        let (mail, bank) = (entry("mail"), entry("bank"));
+        store.apply(0, &key, &[mail.clone(), bank.clone()], &[], 1).unwrap();
+        let mut changed = mail.clone();
+        changed["password"] = json!("new");
+        store.apply(0, &key, &[changed.clone()], &[entry_id(&bank).to_string()], 2).unwrap();
+
+        let payload = store.load(0, &key).unwrap();
+        assert_eq!(payload.entries, vec![changed]);
+        assert_eq!(payload.generation, 2);
+        // The replaced and deleted chunks are gone, the read slot opens like the loaded one
+        let line = store.read().unwrap().slots[0].payload.clone();
+        assert_eq!(split_line(&line).1.len(), 1);
+        assert_eq!(Payload::open(&key, &line).unwrap().entries, payload.entries);
+        store.remove().unwrap();
+    }
+
+    #[test]
+    fn a_file_vault_migrates_unchanged() {
+        let key: VaultKey = Zeroizing::new([13u8; 32]);
+        let mut payload = Payload::new();
+        payload.entries = vec![entry("mail"), entry("bank")];
+        let file = FileStore::new(temp_path("dat"));
+        file.write(&vault_file(&key, &payload)).unwrap();
+
+        let sqlite = SqliteStore::open(temp_path("db")).unwrap();
+        sqlite.write(&file.read().unwrap()).unwrap();
+        let copied = sqlite.read().unwrap();
+        let original = file.read().unwrap();
+        assert_eq!(copied.slots.len(), original.slots.len());
+        for (copy, slot) in copied.slots.iter().zip(&original.slots) {
+            assert_eq!(copy.header(), slot.header());
+        }
+        let migrated = sqlite.load(0, &key).unwrap();
+        assert_eq!(migrated.id, payload.id);
+        assert_eq!(migrated.entries, payload.entries);
+        file.remove().unwrap();
+        sqlite.remove().unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! # Vault Sync Module
+//!
+//! `sync` merges a vault changed on disk by someone else, another PassKeeper instance
+//! or a sync tool, into the vault open in this session.
+//!
+//! The session keeps a [`Journal`] of the entries it changed: each entry as it was before
+//! the first change and as this session left it.
+//! Entries the session did not touch are taken from disk as they are.
+//! A touched entry that still has its old version on disk was only overwritten by a writer
+//! that had not seen the change, so the change is written again.
+//! A touched entry with a third version on disk was changed on both sides: a conflict.
+
+use std::collections::HashMap;
+use serde::Serialize;
+use serde_json::Value;
+use super::vault::entry_id;
+
+/// An entry changed by this session. `None` for an entry that did not exist or was deleted.
+#[derive(Debug, Clone)]
+struct Change {
+    base: Option<Value>,
+    mine: Option<Value>,
+}
+
+/// Entries changed by this session, by entry id.
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    changes: HashMap<String, Change>,
+}
+
+impl Journal {
+    /// Records a change written by this session. The first version before the change is kept.
+    pub fn record(&mut self, id: &str, before: Option<Value>, after: Option<Value>) {
+        self.changes
+            .entry(id.to_string())
+            .or_insert(Change { base: before, mine: None })
+            .mine = after;
+    }
+
+    /// Forgets the change of an entry, e.g. when the user took the other version.
+    pub fn settle(&mut self, id: &str) {
+        self.changes.remove(id);
+    }
+
+    /// The version of an entry this session wrote, `None` for a deleted entry.
+    /// Returns `None` for an entry the session did not change.
+    pub fn mine(&self, id: &str) -> Option<Option<&Value>> {
+        self.changes.get(id).map(|change| change.mine.as_ref())
+    }
+}
+
+/// An entry changed both here and on disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub id: String,
+    /// The version of this session, `None` if deleted here
+    pub mine: Option<Value>,
+    /// The version on disk, `None` if deleted there
+    pub theirs: Option<Value>,
+}
+
+/// Result of a merge.
+#[derive(Debug, Default)]
+pub struct Merge {
+    /// Entries of the merged vault
+    pub entries: Vec<Value>,
+    /// Changes of this session the other writer overwrote, to be written again.
+    /// `None` deletes the entry.
+    pub reapply: Vec<(String, Option<Value>)>,
+    /// Entries changed on both sides. The merged vault has the version on disk.
+    pub conflicts: Vec<Conflict>,
+    /// Entries added, updated and removed compared to the vault of this session
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl Merge {
+    /// Returns `true` if the merged vault is the vault of this session.
+    pub fn is_unchanged(&self) -> bool {
+        self.added == 0 && self.updated == 0 && self.removed == 0 && self.conflicts.is_empty()
+    }
+}
+
+/// Merges the entries on disk with the changes of this session.
+/// Changes found on disk as written here are settled and leave the journal.
+pub fn merge(journal: &mut Journal, mine: &[Value], theirs: Vec<Value>) -> Merge {
+    let mut merge = Merge { entries: theirs, ..Merge::default() };
+    let mut settled = Vec::new();
+    for (id, change) in &journal.changes {
+        let position = merge.entries.iter().position(|e| entry_id(e) == id);
+        let on_disk = position.map(|i| &merge.entries[i]);
+        if on_disk == change.mine.as_ref() {
+            settled.push(id.clone());
+        } else if on_disk == change.base.as_ref() {
+            match (position, &change.mine) {
+                (Some(i), Some(entry)) => merge.entries[i] = entry.clone(),
+                (Some(i), None) => {
+                    merge.entries.remove(i);
+                },
+                (None, Some(entry)) => merge.entries.push(entry.clone()),
+                (None, None) => {},
+            }
+            merge.reapply.push((id.clone(), change.mine.clone()));
+        } else {
+            merge.conflicts.push(Conflict {
+                id: id.clone(),
+                mine: change.mine.clone(),
+                theirs: on_disk.cloned(),
+            });
+        }
+    }
+    for id in settled {
+        journal.settle(&id);
+    }
+    for entry in &merge.entries {
+        match mine.iter().find(|e| entry_id(e) == entry_id(entry)) {
+            Some(existing) if existing != entry => merge.updated += 1,
+            Some(_) => {},
+            None => merge.added += 1,
+        }
+    }
+    merge.removed = mine
+        .iter()
+        .filter(|e| !merge.entries.iter().any(|m| entry_id(m) == entry_id(e)))
+        .count();
+    merge
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn entry(id: &str, password: &str) -> Value {
+        json!({ "id": id, "service": id, "account": "me", "password": password })
+    }
+
+    #[test]
+    fn changes_found_on_disk_are_settled() {
+        let mut journal = Journal::default();
+        journal.record("a", Some(entry("a", "old")), Some(entry("a", "new")));
+        let mine = vec![entry("a", "new")];
+        let merge = merge(&mut journal, &mine, vec![entry("a", "new"), entry("b", "theirs")]);
+        assert!(merge.reapply.is_empty() && merge.conflicts.is_empty());
+        assert_eq!((merge.added, merge.updated, merge.removed), (1, 0, 0));
+        assert_eq!(journal.mine("a"), None);
+    }
+
+    #[test]
+    fn overwritten_changes_are_reapplied() {
+        let mut journal = Journal::default();
+        journal.record("a", Some(entry("a", "old")), Some(entry("a", "new")));
+        journal.record("b", Some(entry("b", "old")), None);
+        journal.record("c", None, Some(entry("c", "added")));
+        let mine = vec![entry("a", "new"), entry("c", "added")];
+        // The other writer had seen none of the changes
+        let merge = merge(&mut journal, &mine, vec![entry("a", "old"), entry("b", "old")]);
+        assert_eq!(merge.entries, vec![entry("a", "new"), entry("c", "added")]);
+        assert_eq!(merge.reapply.len(), 3);
+        assert!(merge.reapply.contains(&("b".to_string(), None)));
+        assert!(merge.conflicts.is_empty());
+        assert!(merge.is_unchanged());
+        assert_eq!(journal.mine("a"), Some(Some(&entry("a", "new"))));
+    }
+
+    #[test]
+    fn changes_on_both_sides_conflict() {
+        let mut journal = Journal::default();
+        journal.record("a", Some(entry("a", "old")), Some(entry("a", "mine")));
+        let mine = vec![entry("a", "mine")];
+        let merge = merge(&mut journal, &mine, vec![entry("a", "theirs")]);
+        assert!(merge.reapply.is_empty());
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].mine, Some(entry("a", "mine")));
+        assert_eq!(merge.conflicts[0].theirs, Some(entry("a", "theirs")));
+        // The merged vault has the version on disk until the user picks one
+        assert_eq!(merge.entries, vec![entry("a", "theirs")]);
+        assert_eq!(merge.updated, 1);
+    }
+}
@@ -0,0 +1,452 @@
+//! # Team Vaults Module
+//!
+//! `teams` keeps the vaults several accounts open with their own master passwords.
+//!
+//! A team vault is stored like a named vault, under `data/teams/<team id>`, and is encrypted
+//! with a random key. `data/teams/<team id>.members` lists the members with their role and
+//! the vault key wrapped for the X25519 key of their identity (see `share`), so every member
+//! opens the vault with their own login. The list is signed by the owner, the only member
+//! who changes it, and a list whose signature fails is not opened.
+//!
+//! Roles are kept by PassKeeper: a read-only member holds the vault key like everyone else.
+//!
+//! Removing a member rotates the vault key: the vault is encrypted again with a new key,
+//! wrapped for the remaining members only.
+
+use std::{
+    fs,
+    io,
+    env::current_dir,
+    path::PathBuf,
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use super::{
+    crypto::mycrypt::{decrypt_data, encrypt_data},
+    lock,
+    session::VaultKey,
+    share::{self, ShareError, WrappedKey},
+    vaults::{self, MAX_NAME_LENGTH},
+};
+
+/// Directory of the team vaults under `data`, also the first part of their storage names
+pub const TEAMS_DIR: &str = "teams";
+/// Prefix of the vault ids of team vaults, e.g. `team-<team id>`
+pub const TEAM_PREFIX: &str = "team-";
+
+/// What a member may do with a team vault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Reads, writes and manages the members
+    Owner,
+    ReadWrite,
+    ReadOnly,
+}
+
+impl Role {
+    /// Parses a role given by the frontend. The owner role is never given.
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "read_write" => Some(Role::ReadWrite),
+            "read_only" => Some(Role::ReadOnly),
+            _ => None,
+        }
+    }
+
+    pub fn can_write(self) -> bool {
+        self != Role::ReadOnly
+    }
+}
+
+/// Why a team vault operation failed.
+#[derive(Debug)]
+pub enum TeamsError {
+    /// No team vault with the id, or the user is not a member
+    NotFound,
+    /// Only the owner manages the members
+    NotOwner,
+    /// The user is a member already
+    AlreadyMember,
+    /// The owner cannot be removed, the team vault is deleted instead
+    OwnerRemoval,
+    /// The name is empty or too long
+    InvalidName,
+    /// The member list is not signed by the owner
+    BadSignature,
+    /// Encrypting the vault with the new key failed
+    Rotation,
+    /// Wrapping or opening a key failed, or the invited user has no identity
+    Share(ShareError),
+    /// Reading or writing the member list failed
+    Io(io::Error),
+}
+
+impl From<io::Error> for TeamsError {
+    fn from(e: io::Error) -> Self {
+        TeamsError::Io(e)
+    }
+}
+
+impl From<ShareError> for TeamsError {
+    fn from(e: ShareError) -> Self {
+        match e {
+            ShareError::BadSignature => TeamsError::BadSignature,
+            e => TeamsError::Share(e),
+        }
+    }
+}
+
+/// A team vault as listed to a member.
+#[derive(Debug, Clone, Serialize)]
+pub struct TeamInfo {
+    /// Vault id, with [`TEAM_PREFIX`]
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+}
+
+/// A member as listed to the other members.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberInfo {
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Member {
+    username: String,
+    role: Role,
+    /// The vault key wrapped for the member
+    key: WrappedKey,
+}
+
+/// The member list file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MemberList {
+    id: String,
+    /// Base64 of the name sealed with the vault key
+    name: String,
+    owner: String,
+    /// Incremented with every key rotation
+    key_version: u64,
+    members: Vec<Member>,
+    /// Base64 of the signature of the owner over the list without it
+    #[serde(default)]
+    signature: String,
+}
+
+impl MemberList {
+    fn signed_message(&self) -> Vec<u8> {
+        let unsigned = MemberList { signature: String::new(), ..self.clone() };
+        serde_json::to_vec(&unsigned).unwrap_or_default()
+    }
+
+    fn member(&self, username: &str) -> Option<&Member> {
+        self.members.iter().find(|member| member.username == username)
+    }
+
+    /// The vault key and role of a member.
+    fn open(&self, username: &str, login_key: &VaultKey) -> Result<(VaultKey, Role), TeamsError> {
+        let member = self.member(username).ok_or(TeamsError::NotFound)?;
+        let key = share::unwrap_key(username, login_key, &member.key)?;
+        Ok((key, member.role))
+    }
+
+    /// The vault key, for the owner only.
+    fn open_as_owner(&self, username: &str, login_key: &VaultKey) -> Result<VaultKey, TeamsError> {
+        let (key, role) = self.open(username, login_key)?;
+        if role != Role::Owner || self.owner != username {
+            return Err(TeamsError::NotOwner);
+        }
+        Ok(key)
+    }
+
+    fn open_name(&self, vault_key: &VaultKey) -> Option<String> {
+        let encrypted = general_purpose::STANDARD.decode(&self.name).ok()?;
+        decrypt_data(vault_key, &encrypted).ok()
+    }
+}
+
+/// The team id of a vault id, `None` if the vault is not a team vault.
+pub fn team_id(vault_id: &str) -> Option<&str> {
+    vault_id
+        .strip_prefix(TEAM_PREFIX)
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// The vault id of a team.
+pub fn vault_id(team_id: &str) -> String {
+    format!("{}{}", TEAM_PREFIX, team_id)
+}
+
+/// Name of the storage of a team vault, as used by the `store` module.
+pub fn store_name(team_id: &str) -> String {
+    format!("{}/{}", TEAMS_DIR, team_id)
+}
+
+/// Creates the directory of the team vaults.
+pub fn create_dir() -> io::Result<()> {
+    fs::create_dir_all(teams_dir())
+}
+
+fn teams_dir() -> PathBuf {
+    current_dir().unwrap().join("data").join(TEAMS_DIR)
+}
+
+fn list_path(team_id: &str) -> String {
+    format!("{}/{}.members", teams_dir().display(), team_id)
+}
+
+fn check_name(name: &str) -> Result<String, TeamsError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(TeamsError::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+fn seal_name(vault_key: &VaultKey, name: &str) -> Result<String, TeamsError> {
+    let encrypted = encrypt_data(vault_key, name).map_err(|_| TeamsError::Share(ShareError::Crypto))?;
+    Ok(general_purpose::STANDARD.encode(encrypted))
+}
+
+// Reads and verifies a list, under the lock the caller holds
+fn read_list(path: &str) -> Result<MemberList, TeamsError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(TeamsError::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+    let list: MemberList = serde_json::from_str(&content).map_err(|_| TeamsError::BadSignature)?;
+    if list.member(&list.owner).map(|owner| owner.role) != Some(Role::Owner) {
+        return Err(TeamsError::BadSignature);
+    }
+    share::verify(&list.owner, &list.signed_message(), &list.signature)?;
+    Ok(list)
+}
+
+// Signs a list as its owner and writes it, under the lock the caller holds
+fn write_list(path: &str, login_key: &VaultKey, list: &mut MemberList) -> Result<(), TeamsError> {
+    list.signature = share::sign(&list.owner, login_key, &list.signed_message())?;
+    fs::write(path, serde_json::to_string(list).unwrap_or_default())?;
+    Ok(())
+}
+
+fn read_locked(team_id: &str) -> Result<MemberList, TeamsError> {
+    let path = list_path(team_id);
+    let _lock = lock::shared(&path)?;
+    read_list(&path)
+}
+
+/// Adds the member list of a team vault whose storage was just created with `vault_key`.
+/// The creator is its owner.
+pub fn create(owner: &str, login_key: &VaultKey, team_id: &str, name: &str, vault_key: &VaultKey) -> Result<TeamInfo, TeamsError> {
+    let name = check_name(name)?;
+    let identity = share::ensure_identity(owner, login_key)?;
+    let mut list = MemberList {
+        id: team_id.to_string(),
+        name: seal_name(vault_key, &name)?,
+        owner: owner.to_string(),
+        key_version: 1,
+        members: vec![Member {
+            username: owner.to_string(),
+            role: Role::Owner,
+            key: share::wrap_key(&identity, vault_key)?,
+        }],
+        signature: String::new(),
+    };
+    let path = list_path(team_id);
+    let _lock = lock::exclusive(&path)?;
+    write_list(&path, login_key, &mut list)?;
+    Ok(TeamInfo { id: vault_id(team_id), name, role: Role::Owner })
+}
+
+/// The vault key of a team vault and the role of the member.
+pub fn open(team_id: &str, username: &str, login_key: &VaultKey) -> Result<(VaultKey, Role), TeamsError> {
+    read_locked(team_id)?.open(username, login_key)
+}
+
+/// The team vaults the login opens.
+pub fn list(username: &str, login_key: &VaultKey) -> Vec<TeamInfo> {
+    let Ok(dir) = fs::read_dir(teams_dir()) else {
+        return Vec::new();
+    };
+    let mut teams: Vec<TeamInfo> = dir
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("members"))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .filter_map(|team_id| {
+            let list = read_locked(&team_id).ok()?;
+            let (key, role) = list.open(username, login_key).ok()?;
+            Some(TeamInfo { id: vault_id(&team_id), name: list.open_name(&key)?, role })
+        })
+        .collect();
+    teams.sort_by(|a, b| a.name.cmp(&b.name));
+    teams
+}
+
+/// The members of a team vault, for its members.
+pub fn members(team_id: &str, username: &str, login_key: &VaultKey) -> Result<Vec<MemberInfo>, TeamsError> {
+    let list = read_locked(team_id)?;
+    list.open(username, login_key)?;
+    Ok(list.members
+        .iter()
+        .map(|member| MemberInfo { username: member.username.clone(), role: member.role })
+        .collect())
+}
+
+/// Gives a user access to a team vault, wrapping its key for their identity.
+pub fn invite(team_id: &str, owner: &str, login_key: &VaultKey, username: &str, role: Role) -> Result<(), TeamsError> {
+    let path = list_path(team_id);
+    let _lock = lock::exclusive(&path)?;
+    let mut list = read_list(&path)?;
+    let vault_key = list.open_as_owner(owner, login_key)?;
+    if list.member(username).is_some() {
+        return Err(TeamsError::AlreadyMember);
+    }
+    let identity = share::public_identity(username).ok_or(ShareError::UnknownRecipient)?;
+    list.members.push(Member {
+        username: username.to_string(),
+        role,
+        key: share::wrap_key(&identity, &vault_key)?,
+    });
+    write_list(&path, login_key, &mut list)
+}
+
+/// Takes access to a team vault away from a member and rotates the vault key.
+/// `reencrypt` is given the old and the new key and adds the vault encrypted with the new key
+/// to the storage, keeping the old one until the list holds the new key.
+/// Returns the new key.
+pub fn remove_member<F>(team_id: &str, owner: &str, login_key: &VaultKey, username: &str, reencrypt: F) -> Result<VaultKey, TeamsError>
+where
+    F: FnOnce(&VaultKey, &VaultKey) -> bool,
+{
+    let path = list_path(team_id);
+    let _lock = lock::exclusive(&path)?;
+    let mut list = read_list(&path)?;
+    let old_key = list.open_as_owner(owner, login_key)?;
+    if username == list.owner {
+        return Err(TeamsError::OwnerRemoval);
+    }
+    if list.member(username).is_none() {
+        return Err(TeamsError::NotFound);
+    }
+    list.members.retain(|member| member.username != username);
+    // Wrap for the remaining members first, so a member without an identity stops the rotation
+    let new_key = vaults::new_key();
+    for member in list.members.iter_mut() {
+        let identity = share::public_identity(&member.username).ok_or(ShareError::UnknownRecipient)?;
+        member.key = share::wrap_key(&identity, &new_key)?;
+    }
+    let name = list.open_name(&old_key).ok_or(TeamsError::Share(ShareError::Crypto))?;
+    list.name = seal_name(&new_key, &name)?;
+    list.key_version += 1;
+    if !reencrypt(&old_key, &new_key) {
+        return Err(TeamsError::Rotation);
+    }
+    write_list(&path, login_key, &mut list)?;
+    Ok(new_key)
+}
+
+/// Renames a team vault.
+pub fn rename(team_id: &str, owner: &str, login_key: &VaultKey, name: &str) -> Result<(), TeamsError> {
+    let name = check_name(name)?;
+    let path = list_path(team_id);
+    let _lock = lock::exclusive(&path)?;
+    let mut list = read_list(&path)?;
+    let vault_key = list.open_as_owner(owner, login_key)?;
+    list.name = seal_name(&vault_key, &name)?;
+    write_list(&path, login_key, &mut list)
+}
+
+/// Removes the member list of a team vault. Its storage is left to the caller.
+pub fn delete(team_id: &str, owner: &str, login_key: &VaultKey) -> Result<(), TeamsError> {
+    let path = list_path(team_id);
+    let _lock = lock::exclusive(&path)?;
+    read_list(&path)?.open_as_owner(owner, login_key)?;
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::module::{
+        testing::{data_dir, login_key, username},
+        vault::new_entry_id,
+    };
+    use super::*;
+
+    fn account(name: &str) -> (String, VaultKey) {
+        data_dir();
+        let username = username(name);
+        let key = login_key();
+        share::create_identities(&username, &key, None).unwrap();
+        (username, key)
+    }
+
+    fn team(owner: &str, owner_key: &VaultKey) -> (String, VaultKey) {
+        create_dir().unwrap();
+        let team_id = new_entry_id();
+        let vault_key = vaults::new_key();
+        create(owner, owner_key, &team_id, "Team", &vault_key).unwrap();
+        (team_id, vault_key)
+    }
+
+    #[test]
+    fn removing_a_member_rotates_the_key() {
+        let (owner, owner_key) = account("owner");
+        let (stays, stays_key) = account("stays");
+        let (leaves, leaves_key) = account("leaves");
+        let (team_id, old_key) = team(&owner, &owner_key);
+        invite(&team_id, &owner, &owner_key, &stays, Role::ReadWrite).unwrap();
+        invite(&team_id, &owner, &owner_key, &leaves, Role::ReadOnly).unwrap();
+        assert_eq!(open(&team_id, &leaves, &leaves_key).unwrap(), (old_key.clone(), Role::ReadOnly));
+
+        let new_key = remove_member(&team_id, &owner, &owner_key, &leaves, |old, new| {
+            old == &old_key && new != &old_key
+        }).unwrap();
+        assert_ne!(new_key, old_key);
+        assert_eq!(open(&team_id, &owner, &owner_key).unwrap().0, new_key);
+        assert_eq!(open(&team_id, &stays, &stays_key).unwrap(), (new_key, Role::ReadWrite));
+        assert!(matches!(open(&team_id, &leaves, &leaves_key), Err(TeamsError::NotFound)));
+        assert!(list(&stays, &stays_key).iter().any(|team| team.id == vault_id(&team_id) && team.name == "Team"));
+    }
+
+    #[test]
+    fn a_failed_reencryption_keeps_the_members() {
+        let (owner, owner_key) = account("owner");
+        let (member, member_key) = account("member");
+        let (team_id, old_key) = team(&owner, &owner_key);
+        invite(&team_id, &owner, &owner_key, &member, Role::ReadWrite).unwrap();
+        let removed = remove_member(&team_id, &owner, &owner_key, &member, |_, _| false);
+        assert!(matches!(removed, Err(TeamsError::Rotation)));
+        assert_eq!(open(&team_id, &member, &member_key).unwrap().0, old_key);
+    }
+
+    #[test]
+    fn only_the_owner_manages_the_members() {
+        let (owner, owner_key) = account("owner");
+        let (member, member_key) = account("member");
+        let (team_id, _) = team(&owner, &owner_key);
+        invite(&team_id, &owner, &owner_key, &member, Role::ReadWrite).unwrap();
+        assert!(matches!(remove_member(&team_id, &member, &member_key, &owner, |_, _| true), Err(TeamsError::NotOwner)));
+        assert!(matches!(remove_member(&team_id, &owner, &owner_key, &owner, |_, _| true), Err(TeamsError::OwnerRemoval)));
+    }
+
+    #[test]
+    fn a_list_not_signed_by_the_owner_is_refused() {
+        let (owner, owner_key) = account("owner");
+        let (member, member_key) = account("member");
+        let (team_id, _) = team(&owner, &owner_key);
+        invite(&team_id, &owner, &owner_key, &member, Role::ReadOnly).unwrap();
+        // The member gives itself write access and signs the list with its own key
+        let path = list_path(&team_id);
+        let mut list: MemberList = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        list.members.iter_mut().filter(|m| m.username == member).for_each(|m| m.role = Role::ReadWrite);
+        list.signature = share::sign(&member, &member_key, &list.signed_message()).unwrap();
+        fs::write(&path, serde_json::to_string(&list).unwrap()).unwrap();
+        assert!(matches!(open(&team_id, &member, &member_key), Err(TeamsError::BadSignature)));
+    }
+}
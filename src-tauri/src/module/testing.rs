@@ -0,0 +1,32 @@
+//! # Testing Module
+//!
+//! `testing` sets up the unit tests of the modules that keep their files under `data`.
+
+use std::{env, fs, sync::Once};
+use zeroize::Zeroizing;
+use super::{
+    crypto::mycrypt::random_bytes,
+    session::VaultKey,
+    vault::new_entry_id,
+};
+
+/// Moves the test process into a directory of its own, with an empty `data` directory.
+/// Every test of the process shares it, so tests keep apart by their usernames.
+pub fn data_dir() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let dir = env::temp_dir().join(format!("passkeeper-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        env::set_current_dir(&dir).unwrap();
+    });
+}
+
+/// A username no other test uses.
+pub fn username(name: &str) -> String {
+    format!("{}-{}", name, &new_entry_id()[..8])
+}
+
+/// A random login key.
+pub fn login_key() -> VaultKey {
+    Zeroizing::new(random_bytes::<32>())
+}
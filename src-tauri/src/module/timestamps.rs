@@ -0,0 +1,84 @@
+//! # Timestamps Module
+//!
+//! `timestamps` keeps when an entry was created, last modified, last had its password changed
+//! and was last used, as Unix times in seconds under `created`, `modified`, `password_changed`
+//! and `accessed`. Entries saved before timestamps existed have none until they change.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use super::history;
+
+pub const CREATED: &str = "created";
+pub const MODIFIED: &str = "modified";
+/// Also set when a secret of a typed entry changes
+pub const PASSWORD_CHANGED: &str = "password_changed";
+/// Set when the password is revealed
+pub const ACCESSED: &str = "accessed";
+
+/// What `get_data` sorts the entries by.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// Service name, then account
+    Service,
+    Created,
+    Modified,
+    PasswordChanged,
+    Accessed,
+}
+
+/// The current Unix time in seconds.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// A timestamp of an entry, `0` if it has none.
+pub fn get(entry: &Value, key: &str) -> u64 {
+    entry[key].as_u64().unwrap_or_default()
+}
+
+fn set(entry: &mut Value, key: &str, time: u64) {
+    if entry.is_object() {
+        entry[key] = json!(time);
+    }
+}
+
+/// Stamps a new entry as created, modified and with its password set now.
+pub fn stamp_new(entry: &mut Value) {
+    let time = now();
+    for key in [CREATED, MODIFIED, PASSWORD_CHANGED] {
+        set(entry, key, time);
+    }
+}
+
+/// Stamps `after` as modified now, and as having its password changed if a secret of
+/// `before` changed.
+pub fn stamp_modified(before: &Value, after: &mut Value) {
+    let time = now();
+    set(after, MODIFIED, time);
+    if history::secrets_changed(before, after) {
+        set(after, PASSWORD_CHANGED, time);
+    }
+}
+
+/// Stamps an entry as used now.
+pub fn stamp_accessed(entry: &mut Value) {
+    set(entry, ACCESSED, now());
+}
+
+/// Sorts entries, oldest first for timestamps. Entries without the timestamp come first.
+pub fn sort(entries: &mut [Value], key: SortKey, descending: bool) {
+    let text = |entry: &Value, field: &str| entry[field].as_str().unwrap_or_default().to_lowercase();
+    entries.sort_by(|a, b| {
+        let order = match key {
+            SortKey::Service => text(a, "service").cmp(&text(b, "service"))
+                .then_with(|| text(a, "account").cmp(&text(b, "account"))),
+            SortKey::Created => get(a, CREATED).cmp(&get(b, CREATED)),
+            SortKey::Modified => get(a, MODIFIED).cmp(&get(b, MODIFIED)),
+            SortKey::PasswordChanged => get(a, PASSWORD_CHANGED).cmp(&get(b, PASSWORD_CHANGED)),
+            SortKey::Accessed => get(a, ACCESSED).cmp(&get(b, ACCESSED)),
+        };
+        if descending { order.reverse() } else { order }
+    });
+}
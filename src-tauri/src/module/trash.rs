@@ -0,0 +1,109 @@
+//! # Trash Module
+//!
+//! `trash` keeps deleted entries in the vault until they are restored or purged.
+//!
+//! A deleted entry stays a record of the vault with the Unix time it was deleted in `deleted`,
+//! so it is encrypted and synchronized like the others. It is left out of
+//! [`super::folders::entries`] and so of everything listing, searching or changing entries.
+//! Entries in the trash for longer than the configured number of days are purged.
+
+use serde_json::{json, Value};
+use super::{
+    folders,
+    timestamps::now,
+    vault::entry_id,
+};
+
+/// Key holding the time an entry was deleted
+pub const DELETED_KEY: &str = "deleted";
+/// Seconds in a day
+const DAY: u64 = 24 * 60 * 60;
+
+/// Returns `true` if a record is an entry in the trash.
+pub fn is_trashed(record: &Value) -> bool {
+    !record[DELETED_KEY].is_null()
+}
+
+/// The entries in the trash, most recently deleted first.
+pub fn trashed(records: &[Value]) -> Vec<Value> {
+    let mut entries: Vec<Value> = records.iter()
+        .filter(|record| is_trashed(record) && !folders::is_folder(record))
+        .cloned()
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(deleted_at(entry)));
+    entries
+}
+
+/// The time an entry was deleted, `0` if it is not in the trash.
+pub fn deleted_at(entry: &Value) -> u64 {
+    entry[DELETED_KEY].as_u64().unwrap_or_default()
+}
+
+/// Puts an entry in the trash.
+pub fn move_to_trash(entry: &mut Value) {
+    if entry.is_object() {
+        entry[DELETED_KEY] = json!(now());
+    }
+}
+
+/// Takes an entry out of the trash. Entries whose folder is gone go to the top of the vault.
+pub fn restore(records: &[Value], entry: &mut Value) {
+    if let Some(object) = entry.as_object_mut() {
+        object.remove(DELETED_KEY);
+    }
+    if !folders::exists(records, folders::parent_of(entry)) {
+        folders::set_parent(entry, folders::ROOT_FOLDER);
+    }
+}
+
+/// Ids of the entries in the trash for more than `days` days. `0` keeps them until purged.
+pub fn expired(records: &[Value], days: u32) -> Vec<String> {
+    if days == 0 {
+        return Vec::new();
+    }
+    let limit = now().saturating_sub(days as u64 * DAY);
+    trashed(records).iter()
+        .filter(|entry| deleted_at(entry) <= limit)
+        .map(|entry| entry_id(entry).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deleted(id: &str, days_ago: u64) -> Value {
+        json!({ "id": id, "service": id, DELETED_KEY: now() - days_ago * DAY })
+    }
+
+    #[test]
+    fn trashed_entries_leave_the_listing_until_restored() {
+        let folder = folders::new_folder("work", folders::ROOT_FOLDER);
+        let mut entry = json!({ "id": "a", "service": "mail", "folder": entry_id(&folder) });
+        move_to_trash(&mut entry);
+        let mut records = vec![folder, entry.clone()];
+        assert!(is_trashed(&entry));
+        assert_eq!(folders::entries(&records).count(), 0);
+        assert_eq!(trashed(&records), vec![entry.clone()]);
+
+        restore(&records, &mut entry);
+        assert!(!is_trashed(&entry));
+        assert_eq!(folders::parent_of(&entry), entry_id(&records[0]));
+
+        // Without its folder, the entry comes back at the top
+        records.remove(0);
+        move_to_trash(&mut entry);
+        restore(&records, &mut entry);
+        assert_eq!(folders::parent_of(&entry), folders::ROOT_FOLDER);
+    }
+
+    #[test]
+    fn entries_expire_after_the_retention() {
+        let records = vec![deleted("old", 31), deleted("recent", 2), json!({ "id": "kept", "service": "kept" })];
+        assert_eq!(trashed(&records).iter().map(entry_id).collect::<Vec<_>>(), ["recent", "old"]);
+        assert_eq!(expired(&records, 30), ["old"]);
+        assert_eq!(expired(&records, 1), ["recent", "old"]);
+        // No retention keeps everything
+        assert!(expired(&records, 0).is_empty());
+    }
+}
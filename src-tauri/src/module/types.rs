@@ -0,0 +1,345 @@
+//! # Entry Types Module
+//!
+//! `types` describes the kinds of entries a vault holds besides logins: secure notes,
+//! payment cards, identities, Wi-Fi networks and API keys.
+//!
+//! A typed entry is stored like any other, with its kind in `type` and its values in `data`,
+//! e.g. `{"type": "card", "data": {"number": "4111111111111111", "expiry": "12/2027"}}`.
+//! Logins have neither key, so entries saved before types existed are logins.
+//! Each type has a [`Template`] listing its fields; values are checked and normalized
+//! against it before they are saved.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The kind of an entry, stored as `type`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryType {
+    #[default]
+    Login,
+    /// Free text kept in the notes of the entry
+    Note,
+    Card,
+    /// Personal or passport data
+    Identity,
+    Wifi,
+    ApiKey,
+}
+
+/// How a field is entered, checked and shown.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueKind {
+    Text,
+    /// Masked like a password
+    Hidden,
+    /// `YYYY-MM-DD`
+    Date,
+    /// `MM/YYYY`, also entered as `MM/YY`
+    Month,
+    /// Card number, masked but for its last four digits
+    CardNumber,
+    /// One of the `choices` of the field
+    Choice,
+    Url,
+}
+
+/// A field of a typed entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldSpec {
+    pub name: &'static str,
+    pub kind: ValueKind,
+    pub required: bool,
+    /// Searched and shown in the list. Never set on secret fields.
+    pub searchable: bool,
+    pub choices: &'static [&'static str],
+}
+
+/// The fields of an entry type, as the editor shows them.
+#[derive(Debug, Clone, Serialize)]
+pub struct Template {
+    #[serde(rename = "type")]
+    pub entry_type: EntryType,
+    /// Whether the entry uses the account and password of a login
+    pub account: bool,
+    pub password: bool,
+    pub fields: Vec<FieldSpec>,
+    /// Field shown in the account column of the list
+    pub summary: Option<&'static str>,
+    /// Field shown, masked, in the password column of the list
+    pub secret: Option<&'static str>,
+}
+
+/// Why a value of a typed entry was refused, with the name of the field.
+#[derive(Debug, PartialEq)]
+pub enum TypeError {
+    /// The field is not part of the type
+    UnknownField(String),
+    /// A required field is empty
+    Missing(String),
+    /// The value does not have the format of the field
+    Invalid(String),
+}
+
+/// Security of a Wi-Fi network
+pub const WIFI_SECURITY: &[&str] = &["none", "wep", "wpa", "wpa2", "wpa3"];
+/// Documents an identity describes
+pub const DOCUMENT_KINDS: &[&str] = &["passport", "id_card", "driver_license"];
+
+const fn field(name: &'static str, kind: ValueKind, required: bool, searchable: bool) -> FieldSpec {
+    FieldSpec { name, kind, required, searchable, choices: &[] }
+}
+
+const fn choice(name: &'static str, choices: &'static [&'static str], required: bool) -> FieldSpec {
+    FieldSpec { name, kind: ValueKind::Choice, required, searchable: true, choices }
+}
+
+impl EntryType {
+    pub const ALL: [EntryType; 6] = [
+        EntryType::Login,
+        EntryType::Note,
+        EntryType::Card,
+        EntryType::Identity,
+        EntryType::Wifi,
+        EntryType::ApiKey,
+    ];
+
+    /// The type of a stored entry. Entries of an unknown type are shown as logins.
+    pub fn of(entry: &Value) -> Self {
+        serde_json::from_value(entry["type"].clone()).unwrap_or_default()
+    }
+
+    /// The template of the type.
+    pub fn template(self) -> Template {
+        use ValueKind::*;
+        let (account, password, fields, summary, secret) = match self {
+            EntryType::Login => (true, true, vec![], None, None),
+            EntryType::Note => (false, false, vec![], None, None),
+            EntryType::Card => (false, false, vec![
+                field("holder", Text, false, true),
+                field("number", CardNumber, true, false),
+                field("expiry", Month, false, false),
+                field("cvv", Hidden, false, false),
+                field("pin", Hidden, false, false),
+            ], Some("number"), Some("number")),
+            EntryType::Identity => (false, false, vec![
+                field("full_name", Text, true, true),
+                field("birth_date", Date, false, false),
+                choice("document", DOCUMENT_KINDS, false),
+                field("document_number", Hidden, false, false),
+                field("nationality", Text, false, true),
+                field("issued", Date, false, false),
+                field("expires", Date, false, false),
+                field("address", Text, false, false),
+                field("phone", Text, false, false),
+                field("email", Text, false, true),
+            ], Some("full_name"), Some("document_number")),
+            EntryType::Wifi => (false, false, vec![
+                field("ssid", Text, true, true),
+                choice("security", WIFI_SECURITY, true),
+                field("passphrase", Hidden, false, false),
+            ], Some("ssid"), Some("passphrase")),
+            EntryType::ApiKey => (false, false, vec![
+                field("key", Hidden, true, false),
+                field("secret", Hidden, false, false),
+                field("endpoint", Url, false, true),
+                field("expires", Date, false, false),
+            ], Some("endpoint"), Some("key")),
+        };
+        Template { entry_type: self, account, password, fields, summary, secret }
+    }
+
+    /// Trims and checks the values of an entry of this type.
+    /// Empty values are dropped, and values are stored in one format, e.g. card numbers as digits.
+    pub fn normalize(self, data: &Map<String, Value>) -> Result<Map<String, Value>, TypeError> {
+        let template = self.template();
+        if let Some(name) = data.keys().find(|name| !template.fields.iter().any(|spec| spec.name == *name)) {
+            return Err(TypeError::UnknownField(name.clone()));
+        }
+        let mut normalized = Map::new();
+        for spec in &template.fields {
+            // Secrets are kept as typed, spaces included
+            let value = data.get(spec.name).and_then(Value::as_str).unwrap_or_default();
+            let value = if spec.kind == ValueKind::Hidden { value } else { value.trim() };
+            if value.is_empty() {
+                if spec.required {
+                    return Err(TypeError::Missing(spec.name.to_string()));
+                }
+                continue;
+            }
+            let value = normalize_value(spec, value)
+                .ok_or_else(|| TypeError::Invalid(spec.name.to_string()))?;
+            normalized.insert(spec.name.to_string(), Value::String(value));
+        }
+        if self == EntryType::Wifi {
+            check_passphrase(&normalized)?;
+        }
+        Ok(normalized)
+    }
+
+    /// The values of an entry of this type that may be searched, by field name. Secrets are
+    /// left out; card numbers are only found by their last four digits.
+    pub fn search_terms(self, entry: &Value) -> Vec<(&'static str, String)> {
+        self.template().fields.iter()
+            .filter_map(|spec| {
+                let value = entry["data"][spec.name].as_str()?;
+                match spec.kind {
+                    ValueKind::CardNumber => Some((spec.name, last_digits(value))),
+                    _ if spec.searchable => Some((spec.name, value.to_string())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The templates of all types, logins first.
+pub fn templates() -> Vec<Template> {
+    EntryType::ALL.iter().map(|entry_type| entry_type.template()).collect()
+}
+
+/// The stored form of a value, or `None` if it does not have the format of the field.
+fn normalize_value(spec: &FieldSpec, value: &str) -> Option<String> {
+    match spec.kind {
+        ValueKind::Text | ValueKind::Hidden => Some(value.to_string()),
+        ValueKind::Url => (!value.chars().any(char::is_whitespace)).then(|| value.to_string()),
+        ValueKind::Choice => spec.choices.contains(&value).then(|| value.to_string()),
+        ValueKind::Date => is_date(value).then(|| value.to_string()),
+        ValueKind::Month => normalize_month(value),
+        ValueKind::CardNumber => {
+            let digits: String = value.chars().filter(|c| *c != ' ' && *c != '-').collect();
+            is_card_number(&digits).then_some(digits)
+        }
+    }
+}
+
+/// Returns `true` for 12 to 19 digits passing the Luhn check.
+pub fn is_card_number(number: &str) -> bool {
+    if !(12..=19).contains(&number.len()) || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let sum: u32 = number.bytes().rev().enumerate()
+        .map(|(index, b)| {
+            let digit = (b - b'0') as u32;
+            if !index.is_multiple_of(2) {
+                let double = digit * 2;
+                if double > 9 { double - 9 } else { double }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// The last four digits of a card number. Anything else in the value is skipped.
+pub fn last_digits(number: &str) -> String {
+    let digits: Vec<char> = number.chars().filter(char::is_ascii_digit).collect();
+    digits[digits.len().saturating_sub(4)..].iter().collect()
+}
+
+fn is_leap_year(year: u32) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+/// Returns `true` for an existing day written `YYYY-MM-DD`.
+pub fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return false;
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return false;
+    }
+    let (Ok(year), Ok(month), Ok(day)) = (year.parse::<u32>(), month.parse::<u32>(), day.parse::<u32>()) else {
+        return false;
+    };
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// `MM/YY` or `MM/YYYY` as `MM/YYYY`.
+fn normalize_month(value: &str) -> Option<String> {
+    let (month, year) = value.split_once('/')?;
+    let month: u32 = month.trim().parse().ok()?;
+    let year = year.trim();
+    let year: u32 = match year.len() {
+        2 => 2000 + year.parse::<u32>().ok()?,
+        4 => year.parse().ok()?,
+        _ => return None,
+    };
+    (1..=12).contains(&month).then(|| format!("{:02}/{}", month, year))
+}
+
+/// Checks the passphrase of a Wi-Fi network against its security.
+fn check_passphrase(data: &Map<String, Value>) -> Result<(), TypeError> {
+    let passphrase = data.get("passphrase").and_then(Value::as_str).unwrap_or_default();
+    let hex = |len: usize| passphrase.len() == len && passphrase.bytes().all(|b| b.is_ascii_hexdigit());
+    let valid = match data.get("security").and_then(Value::as_str).unwrap_or_default() {
+        "none" => passphrase.is_empty(),
+        // 40 or 104 bit keys, as text or hex
+        "wep" => matches!(passphrase.len(), 5 | 13) || hex(10) || hex(26),
+        _ => (8..=63).contains(&passphrase.chars().count()) || hex(64),
+    };
+    if !valid {
+        return Err(TypeError::Invalid("passphrase".to_string()));
+    }
+    let ssid = data.get("ssid").and_then(Value::as_str).unwrap_or_default();
+    if ssid.len() > 32 {
+        return Err(TypeError::Invalid("ssid".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_numbers_pass_the_luhn_check() {
+        assert!(is_card_number("4111111111111111"));
+        assert!(is_card_number("378282246310005"));
+        assert!(!is_card_number("4111111111111112"));
+        assert!(!is_card_number("4111 1111 1111 1111"));
+        assert!(!is_card_number("41111111111"));
+        assert!(!is_card_number("４１１１１１１１１１１１１１１１"));
+    }
+
+    #[test]
+    fn last_digits_skip_anything_but_digits() {
+        assert_eq!(last_digits("4111111111111111"), "1111");
+        assert_eq!(last_digits("4111 1111 1111 1234"), "1234");
+        assert_eq!(last_digits("카드번호 12345"), "2345");
+        assert_eq!(last_digits("12"), "12");
+        assert_eq!(last_digits("없음"), "");
+    }
+
+    #[test]
+    fn dates_must_exist() {
+        assert!(is_date("2024-02-29"));
+        assert!(is_date("2000-02-29"));
+        assert!(!is_date("1900-02-29"));
+        assert!(!is_date("2023-02-29"));
+        assert!(!is_date("2024-04-31"));
+        assert!(!is_date("2024-13-01"));
+        assert!(!is_date("2024-1-01"));
+        assert!(!is_date("24-01-01"));
+    }
+
+    #[test]
+    fn months_are_written_in_full() {
+        assert_eq!(normalize_month("3/27").as_deref(), Some("03/2027"));
+        assert_eq!(normalize_month(" 12 / 2030 ").as_deref(), Some("12/2030"));
+        assert_eq!(normalize_month("13/27"), None);
+        assert_eq!(normalize_month("0/27"), None);
+        assert_eq!(normalize_month("03/202"), None);
+        assert_eq!(normalize_month("0327"), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! # Undo Module
+//!
+//! `undo` keeps the last operations of each user so they can be undone and redone.
+//!
+//! A command changing a vault starts an [`Recording`] for the user. Every record it writes
+//! while the recording is open is kept with its version before and after, and when the
+//! recording ends the changes are sealed with the login key as one [`Operation`] on the
+//! undo stack of the user. Undoing writes the versions before back, redoing the versions after.
+//! The journal only lives in memory for the session and is dropped on logout.
+//!
+//! Creating and deleting vaults, team membership and purging the trash are not journaled:
+//! they cannot be taken back without keeping what was meant to be gone.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::Mutex,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use common::define;
+use super::{
+    crypto::mycrypt::{encrypt_bytes, decrypt_bytes},
+    session::{self, VaultKey},
+};
+
+/// A change made by an operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// An entry or folder of a vault. `None` when it did not exist or was deleted.
+    Record {
+        vault_id: String,
+        id: String,
+        before: Option<Value>,
+        after: Option<Value>,
+    },
+    /// The name of a vault
+    VaultName {
+        vault_id: String,
+        before: String,
+        after: String,
+    },
+}
+
+/// The changes of one command, undone and redone together.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Operation {
+    /// The command that made the changes, e.g. `modify_data`
+    pub label: String,
+    pub changes: Vec<Change>,
+}
+
+impl Operation {
+    /// Adds a change. A record changed twice keeps its first version before and last after.
+    fn push(&mut self, change: Change) {
+        if let Change::Record { vault_id, id, after, .. } = &change {
+            let earlier = self.changes.iter_mut().find(|known| matches!(known,
+                Change::Record { vault_id: v, id: i, .. } if v == vault_id && i == id));
+            if let Some(Change::Record { after: known_after, .. }) = earlier {
+                *known_after = after.clone();
+                return;
+            }
+        }
+        self.changes.push(change);
+    }
+
+    /// The operation taking the changes back, in reverse order.
+    pub fn inverse(&self) -> Operation {
+        let changes = self.changes.iter().rev().map(|change| match change.clone() {
+            Change::Record { vault_id, id, before, after } => Change::Record { vault_id, id, before: after, after: before },
+            Change::VaultName { vault_id, before, after } => Change::VaultName { vault_id, before: after, after: before },
+        }).collect();
+        Operation { label: self.label.clone(), changes }
+    }
+}
+
+/// Sealed operations of a user, newest last.
+#[derive(Default)]
+struct Journal {
+    undo: Vec<Vec<u8>>,
+    redo: Vec<Vec<u8>>,
+}
+
+static JOURNALS: Lazy<Mutex<HashMap<String, Journal>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+thread_local! {
+    // The operation the command running on this thread is recording, with its user and login key
+    static RECORDING: RefCell<Option<(String, VaultKey, Operation)>> = const { RefCell::new(None) };
+}
+
+/// Records the changes written on this thread until it is dropped.
+pub struct Recording;
+
+/// Starts recording an operation of a user. Its changes go on the undo stack when the
+/// returned guard is dropped, and a new operation clears the redo stack.
+/// Nothing is recorded for a locked user.
+pub fn begin(username: &str, label: &str) -> Recording {
+    let started = session::vault_key(username).map(|login_key| {
+        (username.to_string(), login_key, Operation { label: label.to_string(), changes: Vec::new() })
+    });
+    RECORDING.with(|recording| *recording.borrow_mut() = started);
+    Recording
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let Some((username, login_key, operation)) = RECORDING.with(|recording| recording.borrow_mut().take()) else {
+            return;
+        };
+        if operation.changes.is_empty() {
+            return;
+        }
+        let Some(sealed) = seal(&login_key, &operation) else {
+            return;
+        };
+        let mut journals = JOURNALS.lock().unwrap();
+        let journal = journals.entry(username).or_default();
+        push_sealed(&mut journal.undo, sealed);
+        journal.redo.clear();
+    }
+}
+
+/// Puts a sealed operation on a stack, dropping the oldest past the depth of the journal.
+fn push_sealed(stack: &mut Vec<Vec<u8>>, sealed: Vec<u8>) {
+    stack.push(sealed);
+    if stack.len() > define::UNDO_DEPTH {
+        stack.remove(0);
+    }
+}
+
+/// Adds a change to the operation recording on this thread, if any.
+pub fn record(change: Change) {
+    RECORDING.with(|recording| {
+        if let Some((_, _, operation)) = recording.borrow_mut().as_mut() {
+            operation.push(change);
+        }
+    });
+}
+
+fn seal(login_key: &VaultKey, operation: &Operation) -> Option<Vec<u8>> {
+    let json = serde_json::to_vec(operation).ok()?;
+    encrypt_bytes(login_key, &json).ok()
+}
+
+fn open(login_key: &VaultKey, sealed: &[u8]) -> Option<Operation> {
+    let json = decrypt_bytes(login_key, sealed).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// Takes the last operation to undo, or with `redo` the last one undone.
+/// Operations sealed with another login key are dropped.
+pub fn pop(username: &str, login_key: &VaultKey, redo: bool) -> Option<Operation> {
+    let mut journals = JOURNALS.lock().unwrap();
+    let journal = journals.get_mut(username)?;
+    let stack = if redo { &mut journal.redo } else { &mut journal.undo };
+    let sealed = stack.pop()?;
+    let operation = open(login_key, &sealed);
+    if operation.is_none() {
+        stack.clear();
+    }
+    operation
+}
+
+/// Puts an operation on the undo stack, or with `redo` on the redo stack.
+pub fn push(username: &str, login_key: &VaultKey, operation: &Operation, redo: bool) {
+    let Some(sealed) = seal(login_key, operation) else {
+        return;
+    };
+    let mut journals = JOURNALS.lock().unwrap();
+    let journal = journals.entry(username.to_string()).or_default();
+    let stack = if redo { &mut journal.redo } else { &mut journal.undo };
+    push_sealed(stack, sealed);
+}
+
+/// How many operations can be undone and redone.
+pub fn depth(username: &str) -> (usize, usize) {
+    let journals = JOURNALS.lock().unwrap();
+    journals.get(username)
+        .map(|journal| (journal.undo.len(), journal.redo.len()))
+        .unwrap_or_default()
+}
+
+/// Forgets the operations of a user, e.g. on logout.
+pub fn clear(username: &str) {
+    JOURNALS.lock().unwrap().remove(username);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::module::testing::{login_key, username};
+    use super::*;
+
+    fn change(id: &str, before: Option<Value>, after: Option<Value>) -> Change {
+        Change::Record { vault_id: "vault".to_string(), id: id.to_string(), before, after }
+    }
+
+    #[test]
+    fn a_record_changed_twice_keeps_its_first_and_last_version() {
+        let mut operation = Operation::default();
+        operation.push(change("a", None, Some(json!(1))));
+        operation.push(change("a", Some(json!(1)), Some(json!(2))));
+        assert_eq!(operation.changes, vec![change("a", None, Some(json!(2)))]);
+    }
+
+    #[test]
+    fn the_inverse_takes_the_changes_back_in_reverse_order() {
+        let operation = Operation {
+            label: "move_entries".to_string(),
+            changes: vec![
+                change("a", Some(json!(1)), None),
+                Change::VaultName { vault_id: "vault".to_string(), before: "old".to_string(), after: "new".to_string() },
+            ],
+        };
+        let inverse = operation.inverse();
+        assert_eq!(inverse.changes, vec![
+            Change::VaultName { vault_id: "vault".to_string(), before: "new".to_string(), after: "old".to_string() },
+            change("a", None, Some(json!(1))),
+        ]);
+        assert_eq!(inverse.inverse().changes, operation.changes);
+    }
+
+    #[test]
+    fn operations_are_undone_and_redone_with_the_login_key() {
+        let user = username("undo");
+        let key = login_key();
+        {
+            let _recording = begin(&user, "modify_data");
+            record(change("a", None, Some(json!(1))));
+        }
+        // Nothing is recorded while the user is locked
+        assert_eq!(depth(&user), (0, 0));
+
+        session::unlock(&user, key.clone());
+        {
+            let _recording = begin(&user, "modify_data");
+            record(change("a", None, Some(json!(1))));
+        }
+        assert_eq!(depth(&user), (1, 0));
+        let operation = pop(&user, &key, false).unwrap();
+        push(&user, &key, &operation, true);
+        assert_eq!(depth(&user), (0, 1));
+        assert_eq!(pop(&user, &key, true).unwrap().changes, operation.changes);
+
+        // A new operation clears the redo stack
+        push(&user, &key, &operation, true);
+        {
+            let _recording = begin(&user, "modify_data");
+            record(change("b", None, Some(json!(2))));
+        }
+        assert_eq!(depth(&user), (1, 0));
+
+        // Operations sealed with another key cannot be opened and are dropped
+        assert!(pop(&user, &login_key(), false).is_none());
+        assert_eq!(depth(&user), (0, 0));
+        session::logout(&user);
+    }
+
+    #[test]
+    fn the_journal_keeps_the_last_operations() {
+        let user = username("depth");
+        let key = login_key();
+        for i in 0..define::UNDO_DEPTH + 5 {
+            let operation = Operation { label: i.to_string(), changes: vec![change("a", None, None)] };
+            push(&user, &key, &operation, false);
+        }
+        assert_eq!(depth(&user), (define::UNDO_DEPTH, 0));
+        assert_eq!(pop(&user, &key, false).unwrap().label, (define::UNDO_DEPTH + 4).to_string());
+        clear(&user);
+        assert_eq!(depth(&user), (0, 0));
+    }
+}
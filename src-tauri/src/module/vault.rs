@@ -0,0 +1,727 @@
+//! # Vault File Module
+//!
+//! `vault` reads and writes the layout of `data/<username>.dat`.
+//!
+//! A data file always holds two slots, each made of a header line and a payload line.
+//! One slot is the real vault. The other is either a duress decoy vault or random filler,
+//! so every data file looks the same whether or not a duress password is registered.
+//!
+//! The header line carries the KDF parameters, the salt and the key hash of the slot:
+//! `$argon2id$v=19$m=<memory>,t=<iterations>,p=<parallelism>$<salt>$<key hash>==`.
+//! Slots written before the header existed only hold `<key hash>==`; they use the default
+//! parameters and a salt made from the username.
+//!
+//! The payload line holds the [`Payload`] as AES-GCM sealed chunks in base64, joined by `.`:
+//! a manifest first, then one chunk per entry. The manifest carries the vault id, a generation
+//! counter increased on every write and the digest of every entry chunk, so the whole line is
+//! authenticated and an older copy of the file can be told apart.
+//! Damage to one chunk only loses that entry, see [`Payload::salvage`].
+//! Payloads written before chunks existed are a single sealed json document.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::{
+    crypto::{
+        myhash::{hash_bytes, hash_data},
+        mycrypt::{encrypt_data, decrypt_data, random_bytes},
+        mykdf::KdfParams,
+    },
+    session::VaultKey,
+};
+
+pub const SLOT_COUNT: usize = 2;
+
+// Length of an encrypted new vault: nonce + `{"id":"<32 hex>","generation":1,"chunks":[]}` + tag
+const EMPTY_PAYLOAD_LEN: usize = 12 + 68 + 16;
+
+// Separates the sealed chunks of a payload line. Not part of the base64 alphabet.
+const CHUNK_SEPARATOR: char = '.';
+
+const HEADER_PREFIX: &str = "$argon2id$v=19$";
+
+/// KDF parameters and salt of a slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotKdf {
+    pub params: KdfParams,
+    pub salt: Vec<u8>,
+}
+
+impl SlotKdf {
+    /// New parameters with a random salt.
+    pub fn generate(params: KdfParams) -> Self {
+        SlotKdf {
+            params,
+            salt: random_bytes::<16>().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Slot {
+    // `None` for slots written before the header existed
+    pub kdf: Option<SlotKdf>,
+    pub skey: String,
+    pub payload: String,
+}
+
+impl Slot {
+    /// Parses a header line. A header whose costs are out of bounds is kept whole as the key hash.
+    fn parse_header(line: &str) -> (Option<SlotKdf>, String) {
+        let Some(rest) = line.strip_prefix(HEADER_PREFIX) else {
+            return (None, line.to_string());
+        };
+        let mut parts = rest.splitn(3, '$');
+        let (Some(costs), Some(salt), Some(skey)) = (parts.next(), parts.next(), parts.next()) else {
+            return (None, line.to_string());
+        };
+        let mut params = KdfParams::default();
+        for cost in costs.split(',') {
+            match cost.split_once('=') {
+                Some(("m", value)) => params.memory_kib = value.parse().unwrap_or(params.memory_kib),
+                Some(("t", value)) => params.iterations = value.parse().unwrap_or(params.iterations),
+                Some(("p", value)) => params.parallelism = value.parse().unwrap_or(params.parallelism),
+                _ => {}
+            }
+        }
+        // Costs an edited header could use to exhaust the memory or hang the login.
+        // Such a slot is kept as it is but never matches, like filler.
+        if !params.is_valid() {
+            return (None, line.to_string());
+        }
+        let salt = general_purpose::STANDARD_NO_PAD.decode(salt).unwrap_or_default();
+        (Some(SlotKdf { params, salt }), skey.to_string())
+    }
+
+    /// Makes a slot from its header line and payload line.
+    pub fn from_lines(header: &str, payload: &str) -> Self {
+        let (kdf, skey) = Slot::parse_header(header);
+        Slot {
+            kdf,
+            skey,
+            payload: payload.to_string(),
+        }
+    }
+
+    /// Makes the header line.
+    pub fn header(&self) -> String {
+        match &self.kdf {
+            Some(kdf) => format!(
+                "{}m={},t={},p={}${}${}",
+                HEADER_PREFIX,
+                kdf.params.memory_kib,
+                kdf.params.iterations,
+                kdf.params.parallelism,
+                general_purpose::STANDARD_NO_PAD.encode(&kdf.salt),
+                self.skey,
+            ),
+            None => self.skey.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VaultFile {
+    pub slots: Vec<Slot>,
+}
+
+impl VaultFile {
+    /// Parses the content of a data file.
+    /// Missing slots, e.g. in files written before slots existed, are filled with filler.
+    pub fn parse(content: &str) -> Self {
+        let mut lines = content.lines();
+        let mut slots = Vec::with_capacity(SLOT_COUNT);
+        while let Some(header) = lines.next() {
+            if slots.len() == SLOT_COUNT {
+                break;
+            }
+            let payload = lines.next().unwrap_or_default();
+            slots.push(Slot::from_lines(header, payload));
+        }
+        while slots.len() < SLOT_COUNT {
+            slots.push(filler_slot(None));
+        }
+        VaultFile { slots }
+    }
+
+    /// Converts the slots back to the content of a data file.
+    pub fn to_content(&self) -> String {
+        let mut content = String::new();
+        for slot in &self.slots {
+            content.push_str(&slot.header());
+            content.push('\n');
+            content.push_str(&slot.payload);
+            content.push('\n');
+        }
+        content
+    }
+
+    /// Finds the slot whose key hash is `skey`.
+    pub fn find_slot(&self, skey: &str) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.skey == skey)
+    }
+}
+
+/// Why a payload line could not be opened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadError {
+    /// A chunk is not base64
+    Malformed,
+    /// A chunk failed authentication, or chunks were removed, added or swapped
+    Unauthenticated,
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::Malformed => write!(f, "payload is not base64"),
+            PayloadError::Unauthenticated => write!(f, "payload failed authentication"),
+        }
+    }
+}
+
+impl Error for PayloadError {}
+
+/// First chunk of a payload: the vault id, the generation and a record of every entry chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub id: String,
+    pub generation: u64,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkInfo {
+    /// Id of the entry, empty in manifests written before entries had ids
+    #[serde(default)]
+    pub id: String,
+    /// SHA-512 of the sealed chunk
+    pub digest: String,
+    /// Names the entry when its chunk is lost
+    pub label: String,
+}
+
+impl Manifest {
+    /// Decrypts a manifest chunk.
+    pub fn open(vault_key: &VaultKey, chunk: &str) -> Result<Self, PayloadError> {
+        let plain = open_chunk(vault_key, chunk)?;
+        serde_json::from_str(&plain).map_err(|_| PayloadError::Unauthenticated)
+    }
+
+    /// Encrypts the manifest into a chunk.
+    pub fn seal(&self, vault_key: &VaultKey) -> Result<String, Box<dyn Error>> {
+        seal_chunk(vault_key, &serde_json::to_string(self)?)
+    }
+
+    /// Replaces the record of the entry with the same id, or appends it.
+    /// Returns the digest of the replaced chunk.
+    pub fn put(&mut self, info: ChunkInfo) -> Option<String> {
+        match self.chunks.iter_mut().find(|chunk| chunk.id == info.id) {
+            Some(chunk) => Some(std::mem::replace(chunk, info).digest),
+            None => {
+                self.chunks.push(info);
+                None
+            }
+        }
+    }
+
+    /// Removes the record of an entry. Returns the digest of its chunk.
+    pub fn remove(&mut self, entry_id: &str) -> Option<String> {
+        let position = self.chunks.iter().position(|chunk| chunk.id == entry_id)?;
+        Some(self.chunks.remove(position).digest)
+    }
+}
+
+/// Entries recovered from a damaged payload line, and what could not be recovered.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Salvage {
+    #[serde(skip)]
+    pub payload: Payload,
+    pub recovered: usize,
+    /// Labels of the lost entries, or their positions when the manifest is lost too
+    pub lost: Vec<String>,
+    pub manifest_lost: bool,
+    /// Readable chunks left out because the manifest does not record them, or records them once only
+    pub dropped: usize,
+}
+
+fn seal_chunk(vault_key: &VaultKey, plain: &str) -> Result<String, Box<dyn Error>> {
+    let encrypted = encrypt_data(vault_key, plain)?;
+    Ok(general_purpose::STANDARD.encode(encrypted))
+}
+
+fn open_chunk(vault_key: &VaultKey, chunk: &str) -> Result<String, PayloadError> {
+    let encrypted = general_purpose::STANDARD.decode(chunk).map_err(|_| PayloadError::Malformed)?;
+    decrypt_data(vault_key, &encrypted).map_err(|_| PayloadError::Unauthenticated)
+}
+
+/// Decrypts an entry chunk, `None` if it is damaged.
+fn open_entry(vault_key: &VaultKey, chunk: &str) -> Option<Value> {
+    let plain = open_chunk(vault_key, chunk).ok()?;
+    serde_json::from_str(&plain).ok()
+}
+
+/// Names an entry in a repair report.
+fn entry_label(entry: &Value) -> String {
+    if entry["kind"] == "folder" {
+        return format!("[{}]", entry["name"].as_str().unwrap_or_default());
+    }
+    format!(
+        "{} / {}",
+        entry["service"].as_str().unwrap_or_default(),
+        entry["account"].as_str().unwrap_or_default(),
+    )
+}
+
+/// Id of an entry, empty for entries saved before ids existed.
+pub fn entry_id(entry: &Value) -> &str {
+    entry["id"].as_str().unwrap_or_default()
+}
+
+/// A new random entry id.
+pub fn new_entry_id() -> String {
+    hex::encode(random_bytes::<16>())
+}
+
+/// Seals one entry into a chunk, with its manifest record.
+pub fn seal_entry(vault_key: &VaultKey, entry: &Value) -> Result<(ChunkInfo, String), Box<dyn Error>> {
+    let chunk = seal_chunk(vault_key, &serde_json::to_string(entry)?)?;
+    let info = ChunkInfo {
+        id: entry_id(entry).to_string(),
+        digest: hash_data(&chunk),
+        label: entry_label(entry),
+    };
+    Ok((info, chunk))
+}
+
+/// Splits a payload line into its first chunk and the entry chunks.
+pub fn split_line(line: &str) -> (&str, Vec<&str>) {
+    let mut chunks = line.split(CHUNK_SEPARATOR);
+    let first = chunks.next().unwrap_or_default();
+    (first, chunks.collect())
+}
+
+/// Joins a first chunk and entry chunks into a payload line.
+pub fn join_line(first: &str, chunks: &[String]) -> String {
+    let mut line = first.to_string();
+    for chunk in chunks {
+        line.push(CHUNK_SEPARATOR);
+        line.push_str(chunk);
+    }
+    line
+}
+
+/// Decrypted content of a slot payload.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Payload {
+    /// Random id of the vault. Empty for payloads written before generations existed.
+    #[serde(default)]
+    pub id: String,
+    /// Increased on every write of the vault.
+    #[serde(default)]
+    pub generation: u64,
+    #[serde(default)]
+    pub entries: Vec<Value>,
+    /// Opened from a format older than the manifest, or holds entries without ids.
+    /// Such a payload is written again in the current format.
+    #[serde(skip)]
+    pub outdated: bool,
+}
+
+// Entries hold the secrets, only their number is shown
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Payload")
+            .field("id", &self.id)
+            .field("generation", &self.generation)
+            .field("entries", &self.entries.len())
+            .field("outdated", &self.outdated)
+            .finish()
+    }
+}
+
+impl Payload {
+    /// An empty vault with a new id.
+    pub fn new() -> Self {
+        Payload {
+            id: hex::encode(random_bytes::<16>()),
+            generation: 1,
+            entries: Vec::new(),
+            outdated: false,
+        }
+    }
+
+    /// Parses decrypted payload text of the formats before the manifest.
+    /// They are a single json document, a bare json array of entries, or one json value per line.
+    pub fn from_plain(plain: &str) -> Self {
+        let mut payload = match serde_json::from_str::<Payload>(plain) {
+            Ok(payload) => payload,
+            Err(_) => {
+                let entries = plain.lines()
+                    .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+                    .flat_map(|value| match value {
+                        Value::Array(values) => values,
+                        value => vec![value],
+                    })
+                    .collect();
+                Payload { entries, ..Payload::default() }
+            }
+        };
+        payload.outdated = true;
+        payload.assign_ids();
+        payload
+    }
+
+    /// Gives an id to every entry that has none. Returns `true` if any entry got one.
+    pub fn assign_ids(&mut self) -> bool {
+        let mut assigned = false;
+        for entry in &mut self.entries {
+            if let Some(object) = entry.as_object_mut() {
+                if !object.get("id").is_some_and(Value::is_string) {
+                    object.insert(String::from("id"), Value::String(new_entry_id()));
+                    assigned = true;
+                }
+            }
+        }
+        self.outdated |= assigned;
+        assigned
+    }
+
+    /// The payload of the next write: same id, next generation.
+    /// Payloads without an id get one.
+    pub fn next(&self, entries: Vec<Value>) -> Self {
+        let id = if self.id.is_empty() { Payload::new().id } else { self.id.clone() };
+        Payload { id, generation: self.generation + 1, entries, outdated: false }
+    }
+
+    /// Encrypts the payload into a manifest chunk and one chunk per entry.
+    pub fn seal_parts(&self, vault_key: &VaultKey) -> Result<(String, Vec<String>), Box<dyn Error>> {
+        let mut chunks = Vec::with_capacity(self.entries.len());
+        let mut infos = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let (info, chunk) = seal_entry(vault_key, entry)?;
+            infos.push(info);
+            chunks.push(chunk);
+        }
+        let manifest = Manifest {
+            id: self.id.clone(),
+            generation: self.generation,
+            chunks: infos,
+        };
+        Ok((manifest.seal(vault_key)?, chunks))
+    }
+
+    /// Encrypts the payload into the text of a payload line.
+    pub fn seal(&self, vault_key: &VaultKey) -> Result<String, Box<dyn Error>> {
+        let (manifest, chunks) = self.seal_parts(vault_key)?;
+        Ok(join_line(&manifest, &chunks))
+    }
+
+    /// Decrypts a payload line. Fails if any part of the line was changed or belongs to another key.
+    pub fn open(vault_key: &VaultKey, line: &str) -> Result<Self, PayloadError> {
+        let (first, chunks) = split_line(line);
+        Payload::open_parts(vault_key, first, &chunks)
+    }
+
+    /// Decrypts a manifest chunk and the entry chunks it records, given in any order.
+    pub fn open_parts(vault_key: &VaultKey, first: &str, chunks: &[&str]) -> Result<Self, PayloadError> {
+        let plain = open_chunk(vault_key, first)?;
+        let Ok(manifest) = serde_json::from_str::<Manifest>(&plain) else {
+            // Written before chunks existed
+            if !chunks.is_empty() {
+                return Err(PayloadError::Unauthenticated);
+            }
+            return Ok(Payload::from_plain(&plain));
+        };
+        if chunks.iter().any(|chunk| general_purpose::STANDARD.decode(chunk).is_err()) {
+            return Err(PayloadError::Malformed);
+        }
+        if chunks.len() != manifest.chunks.len() {
+            return Err(PayloadError::Unauthenticated);
+        }
+        let by_digest: HashMap<String, &str> = chunks.iter()
+            .map(|chunk| (hash_data(chunk), *chunk))
+            .collect();
+        let mut entries = Vec::with_capacity(chunks.len());
+        for info in &manifest.chunks {
+            let chunk = by_digest.get(&info.digest).ok_or(PayloadError::Unauthenticated)?;
+            let plain = open_chunk(vault_key, chunk)?;
+            let entry = serde_json::from_str(&plain).map_err(|_| PayloadError::Unauthenticated)?;
+            entries.push(entry);
+        }
+        let mut payload = Payload { id: manifest.id, generation: manifest.generation, entries, outdated: false };
+        payload.assign_ids();
+        Ok(payload)
+    }
+
+    /// Recovers every entry of a damaged payload line that still decrypts.
+    /// Chunks are matched to the manifest by digest, so removed or reordered chunks are found too.
+    /// While the manifest opens, only the chunks it records are taken, each once; any other chunk
+    /// may be an older one spliced in and is dropped. Without the manifest every chunk that
+    /// decrypts is taken.
+    pub fn salvage(vault_key: &VaultKey, line: &str) -> Salvage {
+        let (first, chunks) = split_line(line);
+        let manifest = match open_chunk(vault_key, first) {
+            Ok(plain) => match serde_json::from_str::<Manifest>(&plain) {
+                Ok(manifest) => Some(manifest),
+                // Written before chunks existed, nothing else to recover
+                Err(_) => {
+                    let payload = Payload::from_plain(&plain);
+                    return Salvage { recovered: payload.entries.len(), payload, ..Salvage::default() };
+                }
+            },
+            Err(_) => None,
+        };
+        let mut salvage = Salvage::default();
+        let Some(manifest) = manifest else {
+            for (index, chunk) in chunks.iter().enumerate() {
+                match open_entry(vault_key, chunk) {
+                    Some(entry) => salvage.payload.entries.push(entry),
+                    None => salvage.lost.push(format!("#{}", index + 1)),
+                }
+            }
+            salvage.manifest_lost = true;
+            salvage.payload.assign_ids();
+            salvage.recovered = salvage.payload.entries.len();
+            return salvage;
+        };
+        let mut by_digest: HashMap<String, &str> = HashMap::with_capacity(chunks.len());
+        for chunk in &chunks {
+            let digest = hash_data(chunk);
+            if !manifest.chunks.iter().any(|info| info.digest == digest) {
+                // Damaged chunks are reported through the labels of the manifest
+                if open_entry(vault_key, chunk).is_some() {
+                    salvage.dropped += 1;
+                }
+            } else if by_digest.insert(digest, chunk).is_some() {
+                salvage.dropped += 1;
+            }
+        }
+        let mut taken = vec![false; manifest.chunks.len()];
+        for (position, info) in manifest.chunks.iter().enumerate() {
+            // Each chunk is taken once, even if its digest is recorded twice
+            let entry = by_digest.remove(&info.digest).and_then(|chunk| open_entry(vault_key, chunk));
+            if let Some(entry) = entry {
+                salvage.payload.entries.push(entry);
+                taken[position] = true;
+            }
+        }
+        salvage.payload.id = manifest.id;
+        salvage.payload.generation = manifest.generation;
+        for (info, taken) in manifest.chunks.into_iter().zip(taken) {
+            if !taken {
+                salvage.lost.push(info.label);
+            }
+        }
+        salvage.payload.assign_ids();
+        salvage.recovered = salvage.payload.entries.len();
+        salvage
+    }
+}
+
+/// Makes the key hash line of a slot from the vault key.
+pub fn make_skey(vault_key: &VaultKey) -> String {
+    let mut skey = hash_bytes(vault_key.as_ref());
+    skey.push_str("=="); // Append '==' to the hashed key
+    skey
+}
+
+/// Makes the key hash line of a slot that wipes the other slot when it is opened.
+pub fn make_wipe_skey(vault_key: &VaultKey) -> String {
+    let mut buffer = vault_key.to_vec();
+    buffer.extend_from_slice(b"wipe");
+    let mut skey = hash_bytes(&buffer);
+    skey.push_str("==");
+    skey
+}
+
+/// Makes a slot of random data that looks like an empty vault.
+pub fn filler_slot(params: Option<KdfParams>) -> Slot {
+    let mut skey = hex::encode(random_bytes::<64>());
+    skey.push_str("==");
+    let payload = general_purpose::STANDARD.encode(random_bytes::<EMPTY_PAYLOAD_LEN>());
+    Slot {
+        kdf: params.map(SlotKdf::generate),
+        skey,
+        payload,
+    }
+}
+
+/// Picks the slot index of the real vault for a new data file.
+pub fn random_slot() -> usize {
+    (random_bytes::<1>()[0] as usize) % SLOT_COUNT
+}
+
+/// Reads a data file.
+pub fn read(file_path: &str) -> io::Result<VaultFile> {
+    let content = fs::read_to_string(file_path)?;
+    Ok(VaultFile::parse(&content))
+}
+
+/// Writes a data file.
+pub fn write(file_path: &str, vault_file: &VaultFile) -> io::Result<()> {
+    write_atomic(file_path, vault_file.to_content())
+}
+
+/// Writes a file aside, syncs it and renames it over the target,
+/// so a crash leaves either the old or the new content and never a torn file.
+pub fn write_atomic(file_path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = file_path.as_ref();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    let partial = PathBuf::from(partial);
+    let mut file = File::create(&partial)?;
+    file.write_all(content.as_ref())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&partial, path)?;
+    // The rename itself only lasts once the directory is synced
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use zeroize::Zeroizing;
+    use super::*;
+
+    fn test_key() -> VaultKey {
+        Zeroizing::new([7u8; 32])
+    }
+
+    fn test_payload(services: &[&str]) -> Payload {
+        let entries = services.iter()
+            .map(|service| json!({ "id": new_entry_id(), "service": service, "account": "me", "password": "pw" }))
+            .collect();
+        Payload { entries, ..Payload::new() }
+    }
+
+    fn services(payload: &Payload) -> Vec<&str> {
+        payload.entries.iter().map(|entry| entry["service"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn payload_round_trips() {
+        let key = test_key();
+        let payload = test_payload(&["mail", "bank", "네이버"]);
+        let opened = Payload::open(&key, &payload.seal(&key).unwrap()).unwrap();
+        assert_eq!(opened.id, payload.id);
+        assert_eq!(opened.generation, payload.generation);
+        assert_eq!(opened.entries, payload.entries);
+        assert!(!opened.outdated);
+    }
+
+    #[test]
+    fn open_refuses_a_wrong_key() {
+        let line = test_payload(&["mail"]).seal(&test_key()).unwrap();
+        let other_key = Zeroizing::new([8u8; 32]);
+        assert!(matches!(Payload::open(&other_key, &line), Err(PayloadError::Unauthenticated)));
+    }
+
+    #[test]
+    fn open_refuses_spliced_and_missing_chunks() {
+        let key = test_key();
+        let old = test_payload(&["mail", "bank"]);
+        let current = old.next(vec![old.entries[0].clone()]);
+        let (_, old_chunks) = old.seal_parts(&key).unwrap();
+        let (manifest, chunks) = current.seal_parts(&key).unwrap();
+        // A chunk of an older write in place of the current one
+        let spliced = join_line(&manifest, &[old_chunks[0].clone()]);
+        assert!(matches!(Payload::open(&key, &spliced), Err(PayloadError::Unauthenticated)));
+        let missing = join_line(&manifest, &[]);
+        assert!(matches!(Payload::open(&key, &missing), Err(PayloadError::Unauthenticated)));
+        assert_eq!(services(&Payload::open(&key, &join_line(&manifest, &chunks)).unwrap()), ["mail"]);
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let slot = Slot {
+            kdf: Some(SlotKdf::generate(KdfParams::default())),
+            skey: make_skey(&test_key()),
+            payload: String::new(),
+        };
+        let parsed = Slot::from_lines(&slot.header(), "");
+        assert_eq!(parsed.kdf, slot.kdf);
+        assert_eq!(parsed.skey, slot.skey);
+    }
+
+    #[test]
+    fn header_with_excessive_costs_never_matches() {
+        for costs in ["m=4294967295,t=2,p=1", "m=19456,t=4294967295,p=1", "m=19456,t=2,p=255", "m=1,t=2,p=1"] {
+            let header = format!("{}{}$c2FsdHNhbHRzYWx0c2FsdA$abc==", HEADER_PREFIX, costs);
+            let slot = Slot::from_lines(&header, "");
+            assert!(slot.kdf.is_none(), "{}", costs);
+            // Written back unchanged
+            assert_eq!(slot.header(), header);
+        }
+    }
+
+    #[test]
+    fn salvage_drops_spliced_chunks() {
+        let key = test_key();
+        let old = test_payload(&["mail", "bank"]);
+        let current = old.next(vec![old.entries[0].clone()]);
+        let (_, old_chunks) = old.seal_parts(&key).unwrap();
+        let (manifest, mut chunks) = current.seal_parts(&key).unwrap();
+        // An older chunk sealed with the same key brings back the deleted entry
+        chunks.push(old_chunks[1].clone());
+        let line = join_line(&manifest, &chunks);
+        assert!(Payload::open(&key, &line).is_err());
+
+        let salvage = Payload::salvage(&key, &line);
+        assert_eq!(services(&salvage.payload), ["mail"]);
+        assert_eq!(salvage.dropped, 1);
+        assert!(salvage.lost.is_empty());
+        assert_eq!(salvage.payload.generation, current.generation);
+    }
+
+    #[test]
+    fn salvage_takes_repeated_chunks_once() {
+        let key = test_key();
+        let payload = test_payload(&["mail", "bank"]);
+        let (manifest, mut chunks) = payload.seal_parts(&key).unwrap();
+        chunks.push(chunks[0].clone());
+        let salvage = Payload::salvage(&key, &join_line(&manifest, &chunks));
+        assert_eq!(services(&salvage.payload), ["mail", "bank"]);
+        assert_eq!(salvage.dropped, 1);
+    }
+
+    #[test]
+    fn salvage_reports_damaged_chunks() {
+        let key = test_key();
+        let payload = test_payload(&["mail", "bank"]);
+        let (manifest, mut chunks) = payload.seal_parts(&key).unwrap();
+        chunks[1] = general_purpose::STANDARD.encode(random_bytes::<64>());
+        let salvage = Payload::salvage(&key, &join_line(&manifest, &chunks));
+        assert_eq!(services(&salvage.payload), ["mail"]);
+        assert_eq!(salvage.lost, ["bank / me"]);
+        assert_eq!(salvage.dropped, 0);
+        assert!(!salvage.manifest_lost);
+    }
+
+    #[test]
+    fn salvage_without_manifest_takes_every_chunk() {
+        let key = test_key();
+        let old = test_payload(&["mail", "bank"]);
+        let (_, mut chunks) = old.seal_parts(&key).unwrap();
+        chunks.push(general_purpose::STANDARD.encode(random_bytes::<64>()));
+        let damaged = general_purpose::STANDARD.encode(random_bytes::<64>());
+        let salvage = Payload::salvage(&key, &join_line(&damaged, &chunks));
+        assert!(salvage.manifest_lost);
+        assert_eq!(services(&salvage.payload), ["mail", "bank"]);
+        assert_eq!(salvage.lost, ["#3"]);
+        assert_eq!(salvage.recovered, 2);
+    }
+}
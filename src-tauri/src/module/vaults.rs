@@ -0,0 +1,277 @@
+//! # Named Vaults Module
+//!
+//! `vaults` keeps the list of named vaults a user owns next to the default vault,
+//! e.g. "Personal", "Ops" and "Clients".
+//!
+//! Every named vault is stored like the default one, under `data/<username>/<vault id>`,
+//! and is encrypted with its own random key. The list lives in `data/<username>.vaults`:
+//! one record per vault, holding its name and key sealed with the login key.
+//! A login only sees the vaults its own key opens, so the duress login never lists
+//! the vaults of the real one.
+
+use std::{
+    fs,
+    io,
+    env::current_dir,
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use super::{
+    crypto::mycrypt::{decrypt_data, encrypt_data, random_bytes},
+    lock,
+    session::VaultKey,
+};
+
+/// Id of the vault every account starts with, stored as `data/<username>`
+pub const DEFAULT_VAULT: &str = "default";
+/// Name of the default vault until it is renamed
+pub const DEFAULT_VAULT_NAME: &str = "기본 보관함";
+/// Longest vault name, in characters
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// Why a vault list operation failed.
+#[derive(Debug)]
+pub enum VaultsError {
+    /// No vault with the id is visible to the login
+    NotFound,
+    /// Another vault already has the name
+    DuplicateName,
+    /// The name is empty or too long
+    InvalidName,
+    /// The default vault cannot be deleted
+    DefaultVault,
+    /// Reading or writing the list failed
+    Io(io::Error),
+    /// Sealing a record failed
+    Crypto,
+}
+
+impl From<io::Error> for VaultsError {
+    fn from(e: io::Error) -> Self {
+        VaultsError::Io(e)
+    }
+}
+
+/// A vault as listed to the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultInfo {
+    pub id: String,
+    pub name: String,
+}
+
+/// A line of the vault list, sealed with the login key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    id: String,
+    sealed: String,
+}
+
+/// The sealed part of a record.
+#[derive(Serialize, Deserialize)]
+struct Secret {
+    name: String,
+    // Base64 of the vault key, empty for the default vault, whose key is the login key
+    #[serde(default)]
+    key: String,
+}
+
+/// Name of the storage of a vault, as used by the `store` module.
+pub fn store_name(username: &str, vault_id: &str) -> String {
+    if vault_id == DEFAULT_VAULT {
+        username.to_string()
+    } else {
+        format!("{}/{}", username, vault_id)
+    }
+}
+
+/// Username and vault id of a storage name.
+pub fn split_store_name(name: &str) -> (&str, &str) {
+    name.split_once('/').unwrap_or((name, DEFAULT_VAULT))
+}
+
+/// A new random vault id.
+pub fn new_id() -> String {
+    hex::encode(random_bytes::<8>())
+}
+
+/// A new random vault key.
+pub fn new_key() -> VaultKey {
+    Zeroizing::new(random_bytes::<32>())
+}
+
+/// Creates the directory of the named vaults of a user.
+pub fn create_dir(username: &str) -> io::Result<()> {
+    fs::create_dir_all(format!("{}/data/{}", current_dir()?.display(), username))
+}
+
+fn list_path(username: &str) -> String {
+    format!("{}/data/{}.vaults", current_dir().unwrap().display(), username)
+}
+
+// Reads the records, under the lock the caller holds
+fn read_records(path: &str) -> io::Result<Vec<Record>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_default()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn open_record(login_key: &VaultKey, record: &Record) -> Option<Secret> {
+    let encrypted = general_purpose::STANDARD.decode(&record.sealed).ok()?;
+    let plain = decrypt_data(login_key, &encrypted).ok()?;
+    serde_json::from_str(&plain).ok()
+}
+
+fn seal_record(login_key: &VaultKey, id: &str, secret: &Secret) -> Result<Record, VaultsError> {
+    let plain = serde_json::to_string(secret).map_err(|_| VaultsError::Crypto)?;
+    let encrypted = encrypt_data(login_key, &plain).map_err(|_| VaultsError::Crypto)?;
+    Ok(Record {
+        id: id.to_string(),
+        sealed: general_purpose::STANDARD.encode(encrypted),
+    })
+}
+
+/// The vaults of a login, the default vault first.
+pub fn list(username: &str, login_key: &VaultKey) -> Vec<VaultInfo> {
+    let path = list_path(username);
+    let records = lock::shared(&path).and_then(|_lock| read_records(&path)).unwrap_or_default();
+    let mut vaults = vec![VaultInfo {
+        id: DEFAULT_VAULT.to_string(),
+        name: DEFAULT_VAULT_NAME.to_string(),
+    }];
+    for record in &records {
+        let Some(secret) = open_record(login_key, record) else {
+            continue;
+        };
+        if record.id == DEFAULT_VAULT {
+            vaults[0].name = secret.name;
+        } else {
+            vaults.push(VaultInfo { id: record.id.clone(), name: secret.name });
+        }
+    }
+    vaults
+}
+
+/// Key of a vault of the login. The default vault is encrypted with the login key itself.
+pub fn key(username: &str, login_key: &VaultKey, vault_id: &str) -> Option<VaultKey> {
+    if vault_id == DEFAULT_VAULT {
+        return Some(login_key.clone());
+    }
+    let path = list_path(username);
+    let records = lock::shared(&path).and_then(|_lock| read_records(&path)).ok()?;
+    let record = records.iter().find(|record| record.id == vault_id)?;
+    let secret = open_record(login_key, record)?;
+    let bytes = Zeroizing::new(general_purpose::STANDARD.decode(&secret.key).ok()?);
+    let key: [u8; 32] = bytes.as_slice().try_into().ok()?;
+    Some(Zeroizing::new(key))
+}
+
+/// Trims a name and checks it against the other vaults of the login.
+fn check_name(username: &str, login_key: &VaultKey, vault_id: &str, name: &str) -> Result<String, VaultsError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(VaultsError::InvalidName);
+    }
+    if list(username, login_key).iter().any(|vault| vault.id != vault_id && vault.name == name) {
+        return Err(VaultsError::DuplicateName);
+    }
+    Ok(name.to_string())
+}
+
+/// Adds a vault whose storage was just created with `vault_key`.
+pub fn add(username: &str, login_key: &VaultKey, vault_id: &str, name: &str, vault_key: &VaultKey) -> Result<VaultInfo, VaultsError> {
+    let name = check_name(username, login_key, vault_id, name)?;
+    let secret = Secret {
+        name: name.clone(),
+        key: general_purpose::STANDARD.encode(vault_key.as_ref()),
+    };
+    let record = seal_record(login_key, vault_id, &secret)?;
+    let path = list_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut records = read_records(&path)?;
+    records.push(record);
+    fs::write(&path, serde_json::to_string(&records).unwrap_or_default())?;
+    Ok(VaultInfo { id: vault_id.to_string(), name })
+}
+
+/// Renames a vault of the login.
+pub fn rename(username: &str, login_key: &VaultKey, vault_id: &str, name: &str) -> Result<(), VaultsError> {
+    let name = check_name(username, login_key, vault_id, name)?;
+    let path = list_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut records = read_records(&path)?;
+    // Only the records this login opens, the default vault of another login has its own
+    let position = records.iter().position(|record| {
+        record.id == vault_id && open_record(login_key, record).is_some()
+    });
+    match position {
+        Some(index) => {
+            let mut secret = open_record(login_key, &records[index]).ok_or(VaultsError::Crypto)?;
+            secret.name = name;
+            records[index] = seal_record(login_key, vault_id, &secret)?;
+        },
+        None if vault_id == DEFAULT_VAULT => {
+            records.push(seal_record(login_key, vault_id, &Secret { name, key: String::new() })?);
+        },
+        None => return Err(VaultsError::NotFound),
+    }
+    fs::write(&path, serde_json::to_string(&records).unwrap_or_default())?;
+    Ok(())
+}
+
+/// Removes a vault of the login from the list. Its storage is left to the caller.
+pub fn remove(username: &str, login_key: &VaultKey, vault_id: &str) -> Result<(), VaultsError> {
+    if vault_id == DEFAULT_VAULT {
+        return Err(VaultsError::DefaultVault);
+    }
+    let path = list_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut records = read_records(&path)?;
+    let count = records.len();
+    records.retain(|record| !(record.id == vault_id && open_record(login_key, record).is_some()));
+    if records.len() == count {
+        return Err(VaultsError::NotFound);
+    }
+    fs::write(&path, serde_json::to_string(&records).unwrap_or_default())?;
+    Ok(())
+}
+
+/// Seals the records of a login again after its key changed, e.g. on a KDF upgrade.
+pub fn reseal(username: &str, old_key: &VaultKey, new_key: &VaultKey) -> Result<(), VaultsError> {
+    let path = list_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let mut records = read_records(&path)?;
+    let mut changed = false;
+    for record in records.iter_mut() {
+        if let Some(secret) = open_record(old_key, record) {
+            *record = seal_record(new_key, &record.id, &secret)?;
+            changed = true;
+        }
+    }
+    if changed {
+        fs::write(&path, serde_json::to_string(&records).unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+/// Removes the records the login does not open and returns their vault ids.
+/// Used once the other logins are wiped, their vaults can no longer be opened by anyone.
+pub fn remove_foreign(username: &str, login_key: &VaultKey) -> Result<Vec<String>, VaultsError> {
+    let path = list_path(username);
+    let _lock = lock::exclusive(&path)?;
+    let records = read_records(&path)?;
+    let (kept, foreign): (Vec<Record>, Vec<Record>) = records
+        .into_iter()
+        .partition(|record| open_record(login_key, record).is_some());
+    if foreign.is_empty() {
+        return Ok(Vec::new());
+    }
+    fs::write(&path, serde_json::to_string(&kept).unwrap_or_default())?;
+    Ok(foreign
+        .into_iter()
+        .map(|record| record.id)
+        .filter(|id| id != DEFAULT_VAULT)
+        .collect())
+}
@@ -0,0 +1,81 @@
+//! # Vault Watcher Module
+//!
+//! `watcher` watches the `data` directory for vault files changed by someone else while
+//! the app runs, e.g. another PassKeeper instance or a sync tool.
+//! Named vaults live one directory down, so the watch is recursive.
+//!
+//! Writers often touch a file several times in a row, so a change is reported once the
+//! directory has been quiet for [`QUIET_PERIOD`]. Changes made by this process are reported
+//! as well; the cached vault already has their stamp, so reloading them costs nothing.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    sync::{mpsc::{self, RecvTimeoutError}, Mutex},
+    thread,
+    time::Duration,
+};
+use once_cell::sync::Lazy;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Time without events before the changed vaults are reported
+pub const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Extensions of the vault files of the storage backends
+const VAULT_EXTENSIONS: [&str; 2] = ["dat", "db"];
+
+static WATCHER: Lazy<Mutex<Option<RecommendedWatcher>>> = Lazy::new(|| Mutex::new(None));
+
+/// The storage name of a vault file in `dir`, e.g. `alice` or `alice/<vault id>`.
+fn store_name(dir: &Path, path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    if !VAULT_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    let relative = path.strip_prefix(dir).ok()?.with_extension("");
+    let parts: Vec<&str> = relative.iter().map(|part| part.to_str()).collect::<Option<_>>()?;
+    Some(parts.join("/"))
+}
+
+/// Starts watching the vault files in `dir`, replacing an earlier watcher.
+/// `on_change` is called with the storage name of every changed vault, on a background thread.
+pub fn start<F>(dir: &Path, on_change: F) -> notify::Result<()>
+where
+    F: Fn(&str) + Send + 'static,
+{
+    fs::create_dir_all(dir)?;
+    let (sender, receiver) = mpsc::channel::<String>();
+    let root = dir.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        let Ok(event) = result else {
+            return;
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        for name in event.paths.iter().filter_map(|path| store_name(&root, path)) {
+            let _ = sender.send(name);
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+    thread::spawn(move || {
+        let mut changed = HashSet::new();
+        loop {
+            match receiver.recv_timeout(QUIET_PERIOD) {
+                Ok(name) => {
+                    changed.insert(name);
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    for name in changed.drain() {
+                        on_change(&name);
+                    }
+                },
+                // The watcher was dropped
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    *WATCHER.lock().unwrap() = Some(watcher);
+    Ok(())
+}
@@ -22,9 +22,10 @@ function getCurrentUser() {
   return raw ? JSON.parse(raw) : null;
 }
 
-async function createAccount(username, password) {
+// An administrator creates accounts when the policy restricts registration
+async function createAccount(username, password, duressPassword = null, duressWipe = false, admin = null) {
 //  const invoke = getInvoke();
-  const resultCode = await invoke("save_account", { username, password });
+  const resultCode = await invoke("save_account", { username, password, duressPassword, duressWipe, admin });
   let message = "";
   switch (resultCode) {
     case 0:
@@ -36,11 +37,27 @@ async function createAccount(username, password) {
     case 2:
       message = "Error: Account already exists.";
       break;
+    case 14:
+      message = "Error: Crypto self-test failed. Vaults are disabled.";
+      break;
+    case 13:
+      message = "Error: Duress password must differ from the password.";
+      break;
+    case 23:
+      message = "Error: The administration settings are not signed by an administrator.";
+      break;
+    case 26:
+      message = "Error: Only an administrator can create accounts.";
+      break;
+    case 27:
+      message = "Error: The password does not meet the password policy.";
+      break;
     default:
       message = "An unknown error occurred.";
   }
   
   console.log("Create Account: ", message);
+  return resultCode === 0;
 }
 
 async function login(username, password) {
@@ -58,15 +75,53 @@ async function login(username, password) {
   return false;
 }
 
-async function getData(username) {
+// Unlock with the key an earlier login left in the kernel keyring, while the keyring cache is on
+async function resumeSession(username) {
+  const isValid = await invoke("resume_session", { username });
+  if (isValid === true) {
+    setCurrentUser(username);
+    console.log("Resume Session: ", username, " resumed.");
+    return true;
+  }
+  console.log("Resume Session: ", "No cached key for ", username);
+  return false;
+}
+
+// Data commands use the selected vault unless a vault id is given
+// All entries, those directly in `folder` ("root" for the top), or with `tree` the folder tree
+// `sort` is "service", "created", "modified", "password_changed" or "accessed"
+async function getData(username, vaultId = null, folder = null, tree = false, sort = null, descending = false) {
 //  const invoke = getInvoke();
-  const data = await invoke("get_data", { username });
+  const data = await invoke("get_data", { username, vaultId, folder, tree, sort, descending });
   return data;
 }
 
-async function saveData(username, service, account, password) {
+// Entries matching the query and filters ({type, folder, tag, newer_than_days, older_than_days, limit}),
+// best first, as [{entry, score, highlights: [{field, value, ranges}]}]. Passwords are never searched.
+async function searchEntries(username, query, filters = null, vaultId = null) {
+  const data = await invoke("search_entries", { username, query, filters, vaultId });
+  return data ? JSON.parse(data) : [];
+}
+
+// Marks the entry as used when its password is revealed
+async function recordAccess(username, entryId, vaultId = null) {
+  const resultCode = await invoke("record_access", { username, entryId, vaultId });
+  if (resultCode !== 0) {
+    console.log("Record Access Result Code: ", resultCode);
+  }
+  return resultCode === 0;
+}
+
+// The entry types with their fields: [{type, account, password, fields, summary, secret}]
+async function getEntryTemplates() {
+  const data = await invoke("get_entry_templates");
+  return data ? JSON.parse(data) : [];
+}
+
+// `fields` holds {urls, notes, tags, fields, type, data} besides the login itself
+async function saveData(username, service, account, password, fields = null, folder = null, vaultId = null) {
 //  const invoke = getInvoke();
-  const resultCode = await invoke("save_data", { username, service, account, password });
+  const resultCode = await invoke("save_data", { username, service, account, password, fields, folder, vaultId });
   let message = "";
   switch (resultCode) {
     case 0:
@@ -90,6 +145,27 @@ async function saveData(username, service, account, password) {
     case 7:
       message = "Error: Data decryption failed.";
       break;
+    case 16:
+      message = "Error: The vault file failed its integrity check.";
+      break;
+    case 17:
+      message = "Error: The vault file is older than the last seen version.";
+      break;
+    case 18:
+      message = "Error: The vault is being written by another process.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for you.";
+      break;
+    case 30:
+      message = "Error: A URL, tag, custom field or typed value is invalid.";
+      break;
+    case 31:
+      message = "Error: The folder does not exist.";
+      break;
     default:
       message = "An unknown error occurred.";
   }
@@ -98,13 +174,13 @@ async function saveData(username, service, account, password) {
   return false;
 }
 
-async function removeData(username, service) {
+async function removeData(username, service, vaultId = null) {
 //  const invoke = getInvoke();
-  const resultCode = await invoke("remove_data", { username, service });
+  const resultCode = await invoke("remove_data", { username, service, vaultId });
   let message = "";
   switch (resultCode) {
     case 0:
-      message = "Data removed successfully.";
+      message = "Data moved to the trash.";
       console.log("Remove Data Result Message: ", message);
       return true;
     case 2:
@@ -116,6 +192,21 @@ async function removeData(username, service) {
     case 4:
       message = "Error: Failed to write data to file.";
       break;
+    case 16:
+      message = "Error: The vault file failed its integrity check.";
+      break;
+    case 17:
+      message = "Error: The vault file is older than the last seen version.";
+      break;
+    case 18:
+      message = "Error: The vault is being written by another process.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for you.";
+      break;
     default:
       message = "An unknown error occurred.";
   }
@@ -123,9 +214,10 @@ async function removeData(username, service) {
   return false;  
 }
 
-async function modifyData(username, service, account, password) {
+// newService and newAccount rename the entry of service and account
+async function modifyData(username, service, account, password, fields = null, vaultId = null, newService = null, newAccount = null) {
 //  const invoke = getInvoke();
-  const resultCode = await invoke("modify_data", { username, service, account, password });
+  const resultCode = await invoke("modify_data", { username, service, account, password, fields, vaultId, newService, newAccount });
   let message = "";
   switch (resultCode) {
     case 0:
@@ -135,11 +227,32 @@ async function modifyData(username, service, account, password) {
           message = "Error: User data file does not exist.";
           break;
         case 3:
-          message = "Error: User data file not found.";
+          message = "Error: No entry has this service and account.";
           break;
         case 4:
           message = "Error: Failed to write data to file.";
           break;
+        case 5:
+          message = "Error: Another entry has the same service and account.";
+          break;
+        case 16:
+          message = "Error: The vault file failed its integrity check.";
+          break;
+        case 17:
+          message = "Error: The vault file is older than the last seen version.";
+          break;
+        case 18:
+          message = "Error: The vault is being written by another process.";
+          break;
+        case 19:
+          message = "Error: The vault does not exist.";
+          break;
+        case 24:
+          message = "Error: The vault is read-only for you.";
+          break;
+        case 30:
+          message = "Error: A URL, tag, custom field or typed value is invalid, or a new name is empty.";
+          break;
         default:
           message = "An unknown error occurred.";
       }
@@ -147,6 +260,940 @@ async function modifyData(username, service, account, password) {
       return false;
 }
 
+async function setPin(username, pin) {
+  const resultCode = await invoke("set_pin", { username, pin });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "PIN set successfully.";
+      console.log("Set PIN Result Message: ", message);
+      return true;
+    case 8:
+      message = "Error: Log in with the master password first.";
+      break;
+    case 10:
+      message = "Error: PIN must be 4 to 8 digits.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Set PIN Result Message: ", message);
+  return false;
+}
+
+async function isPinSet(username) {
+  return await invoke("is_pin_set", { username });
+}
+
+async function unlockWithPin(username, pin) {
+  const resultCode = await invoke("unlock_with_pin", { username, pin });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Unlocked with PIN.";
+      console.log("Unlock Result Message: ", message);
+      return true;
+    case 9:
+      message = "Error: PIN is not set. Log in with the master password.";
+      break;
+    case 10:
+      message = "Error: Wrong PIN.";
+      break;
+    case 11:
+      message = "Error: Too many wrong PINs. Log in with the master password.";
+      break;
+    case 28:
+      message = "Error: The account is disabled.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Unlock Result Message: ", message);
+  return false;
+}
+
+async function lockVault(username) {
+  await invoke("lock_vault", { username });
+}
+
+async function logout(username) {
+  await invoke("logout", { username });
+  sessionStorage.removeItem("currentUser");
+}
+
+async function cryptoSelfTest() {
+  const data = await invoke("crypto_self_test");
+  return JSON.parse(data);
+}
+
+async function calibrateKdf(username, targetMs) {
+  const data = await invoke("calibrate_kdf", { username, targetMs });
+  return data ? JSON.parse(data) : null;
+}
+
+async function setKdfFloor(username, memoryKib, iterations, parallelism) {
+  const resultCode = await invoke("set_kdf_floor", { username, memoryKib, iterations, parallelism });
+  if (resultCode !== 0) {
+    console.log("Set KDF Floor Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function getSettings() {
+  const data = await invoke("get_settings");
+  return JSON.parse(data);
+}
+
+async function setKeyringCache(username, minutes) {
+  const resultCode = await invoke("set_keyring_cache", { username, minutes });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Keyring cache updated.";
+      console.log("Keyring Cache Result Message: ", message);
+      return true;
+    case 4:
+      message = "Error: Failed to write settings.";
+      break;
+    case 12:
+      message = "Error: The kernel keyring is only available on Linux.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Keyring Cache Result Message: ", message);
+  return false;
+}
+
+// How many previous passwords are kept per entry, 0 for none
+async function setPasswordHistorySize(username, size) {
+  const resultCode = await invoke("set_password_history_size", { username, size });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Password history size updated.";
+      console.log("Password History Result Message: ", message);
+      return true;
+    case 4:
+      message = "Error: Failed to write settings.";
+      break;
+    case 12:
+      message = "Error: At most 100 previous passwords are kept.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Password History Result Message: ", message);
+  return false;
+}
+
+// Entries in the trash, most recently deleted first, with the time they were `deleted`
+async function listTrash(username, vaultId = null) {
+  const data = await invoke("list_trash", { username, vaultId });
+  return data ? JSON.parse(data) : [];
+}
+
+async function restoreTrash(username, entryIds, vaultId = null) {
+  const resultCode = await invoke("restore_trash", { username, entryIds, vaultId });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Entries restored from the trash.";
+      console.log("Restore Trash Result Message: ", message);
+      return true;
+    case 3:
+      message = "Error: The entry is not in the trash.";
+      break;
+    case 5:
+      message = "Error: An entry with the same service and account already exists.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for this user.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Restore Trash Result Message: ", message);
+  return false;
+}
+
+// Deletes the given entries of the trash for good, or all of them without `entryIds`
+async function purgeTrash(username, entryIds = null, vaultId = null) {
+  const resultCode = await invoke("purge_trash", { username, entryIds, vaultId });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Trash purged.";
+      console.log("Purge Trash Result Message: ", message);
+      return true;
+    case 3:
+      message = "Error: The entry is not in the trash.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for this user.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Purge Trash Result Message: ", message);
+  return false;
+}
+
+// Days deleted entries stay in the trash, 0 to keep them until it is emptied
+async function setTrashRetention(username, days) {
+  const resultCode = await invoke("set_trash_retention", { username, days });
+  if (resultCode !== 0) {
+    console.log("Trash Retention Result Message: ", "Error: Failed to write settings.");
+    return false;
+  }
+  return true;
+}
+
+// Undo the last change to the vaults of the user, edits, moves and bulk changes included
+async function undoChange(username) {
+  const resultCode = await invoke("undo", { username });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Last change undone.";
+      console.log("Undo Result Message: ", message);
+      return true;
+    case 33:
+      message = "Error: There is nothing to undo.";
+      break;
+    case 34:
+      message = "Error: The entries changed since, nothing was written.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for this user.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Undo Result Message: ", message);
+  return false;
+}
+
+// Make the last undone change again
+async function redoChange(username) {
+  const resultCode = await invoke("redo", { username });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Last undone change made again.";
+      console.log("Redo Result Message: ", message);
+      return true;
+    case 33:
+      message = "Error: There is nothing to redo.";
+      break;
+    case 34:
+      message = "Error: The entries changed since, nothing was written.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for this user.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Redo Result Message: ", message);
+  return false;
+}
+
+// Previous values of the password and secrets of an entry: [{field, value, changed}], newest first
+async function getPasswordHistory(username, entryId, vaultId = null) {
+  const data = await invoke("get_password_history", { username, entryId, vaultId });
+  return data ? JSON.parse(data) : [];
+}
+
+async function restorePassword(username, entryId, index, vaultId = null) {
+  const resultCode = await invoke("restore_password", { username, entryId, index, vaultId });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Previous value restored.";
+      console.log("Restore Password Result Message: ", message);
+      return true;
+    case 3:
+      message = "Error: The entry or the previous value was not found.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for this user.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Restore Password Result Message: ", message);
+  return false;
+}
+
+async function checkVaultIntegrity(username) {
+  const data = await invoke("check_vault_integrity", { username });
+  return data ? JSON.parse(data) : null;
+}
+
+async function acceptVaultRollback(username) {
+  const resultCode = await invoke("accept_vault_rollback", { username });
+  if (resultCode !== 0) {
+    console.log("Accept Vault Rollback Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function repairVault(username) {
+  const data = await invoke("repair_vault", { username });
+  return data ? JSON.parse(data) : null;
+}
+
+async function setRollbackPolicy(username, refuse) {
+  const resultCode = await invoke("set_rollback_policy", { username, refuse });
+  if (resultCode !== 0) {
+    console.log("Set Rollback Policy Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function getVaultInfo(username) {
+  const data = await invoke("get_vault_info", { username });
+  return data ? JSON.parse(data) : null;
+}
+
+async function migrateVault(username, backend) {
+  const resultCode = await invoke("migrate_vault", { username, backend });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Vault migrated.";
+      console.log("Migrate Vault Result Message: ", message);
+      return true;
+    case 3:
+      message = "Error: User data file not found.";
+      break;
+    case 4:
+      message = "Error: Failed to write the migrated vault.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 12:
+      message = "Error: Unknown storage backend.";
+      break;
+    case 16:
+      message = "Error: The vault file failed its integrity check.";
+      break;
+    case 17:
+      message = "Error: The vault file is older than the last seen version.";
+      break;
+    case 18:
+      message = "Error: The vault is being written by another process.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for you.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Migrate Vault Result Message: ", message);
+  return false;
+}
+
+async function setStorageBackend(username, backend) {
+  const resultCode = await invoke("set_storage_backend", { username, backend });
+  if (resultCode !== 0) {
+    console.log("Set Storage Backend Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function resolveVaultConflict(username, entryId, keepMine, vaultId = null) {
+  const resultCode = await invoke("resolve_vault_conflict", { username, entryId, keepMine, vaultId });
+  if (resultCode !== 0) {
+    console.log("Resolve Vault Conflict Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function listVaults(username) {
+  const data = await invoke("list_vaults", { username });
+  return data ? JSON.parse(data) : [];
+}
+
+async function createVault(username, name) {
+  const result = JSON.parse(await invoke("create_vault", { username, name }));
+  let message = "";
+  switch (result.code) {
+    case 0:
+      message = "Vault created.";
+      console.log("Create Vault Result Message: ", message);
+      return result.id;
+    case 1:
+      message = "Error: Failed to create the vault directory.";
+      break;
+    case 4:
+      message = "Error: Failed to write the vault.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 14:
+      message = "Error: Crypto self-test failed. Vaults are disabled.";
+      break;
+    case 20:
+      message = "Error: Another vault has the same name.";
+      break;
+    case 21:
+      message = "Error: The vault name is empty or too long.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Create Vault Result Message: ", message);
+  return null;
+}
+
+async function renameVault(username, vaultId, name) {
+  const resultCode = await invoke("rename_vault", { username, vaultId, name });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Vault renamed.";
+      console.log("Rename Vault Result Message: ", message);
+      return true;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 20:
+      message = "Error: Another vault has the same name.";
+      break;
+    case 21:
+      message = "Error: The vault name is empty or too long.";
+      break;
+    case 25:
+      message = "Error: Only the owner can manage the team vault.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Rename Vault Result Message: ", message);
+  return false;
+}
+
+async function deleteVault(username, vaultId) {
+  const resultCode = await invoke("delete_vault", { username, vaultId });
+  if (resultCode !== 0) {
+    console.log("Delete Vault Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function switchVault(username, vaultId) {
+  const resultCode = await invoke("switch_vault", { username, vaultId });
+  if (resultCode !== 0) {
+    console.log("Switch Vault Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function getPublicKey(username) {
+  const data = await invoke("get_public_key", { username });
+  return data ? JSON.parse(data) : null;
+}
+
+async function shareEntries(username, entryIds, recipient, vaultId = null) {
+  const result = JSON.parse(await invoke("share_entries", { username, entryIds, recipient, vaultId }));
+  let message = "";
+  switch (result.code) {
+    case 0:
+      message = "Entries shared: " + result.path;
+      console.log("Share Entries Result Message: ", message);
+      return result.path;
+    case 3:
+      message = "Error: Entries not found.";
+      break;
+    case 4:
+      message = "Error: Failed to write the bundle.";
+      break;
+    case 6:
+      message = "Error: Failed to encrypt the bundle.";
+      break;
+    case 7:
+      message = "Error: This login has no sharing keys.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 18:
+      message = "Error: The vault is in use by another process.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 22:
+      message = "Error: The recipient has no PassKeeper account.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Share Entries Result Message: ", message);
+  return null;
+}
+
+async function listSharedBundles(username) {
+  const data = await invoke("list_shared_bundles", { username });
+  return data ? JSON.parse(data) : [];
+}
+
+async function importSharedBundle(username, bundleId, vaultId = null) {
+  const result = JSON.parse(await invoke("import_shared_bundle", { username, bundleId, vaultId }));
+  let message = "";
+  switch (result.code) {
+    case 0:
+      message = "Imported " + result.imported + " entries, skipped " + result.skipped + ", refused " + result.refused + ".";
+      console.log("Import Shared Bundle Result Message: ", message);
+      return result;
+    case 3:
+      message = "Error: Bundle not found.";
+      break;
+    case 7:
+      message = "Error: The bundle cannot be opened by this login.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 16:
+      message = "Error: The bundle or the vault is damaged.";
+      break;
+    case 18:
+      message = "Error: The vault is in use by another process.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 24:
+      message = "Error: The vault is read-only for you.";
+      break;
+    case 23:
+      message = "Error: The signature of the sender does not match.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Import Shared Bundle Result Message: ", message);
+  return result;
+}
+
+async function discardSharedBundle(username, bundleId) {
+  const resultCode = await invoke("discard_shared_bundle", { username, bundleId });
+  if (resultCode !== 0) {
+    console.log("Discard Shared Bundle Result Code: ", resultCode);
+    return false;
+  }
+  return true;
+}
+
+async function createTeamVault(username, name) {
+  const result = JSON.parse(await invoke("create_team_vault", { username, name }));
+  let message = "";
+  switch (result.code) {
+    case 0:
+      message = "Team vault created.";
+      console.log("Create Team Vault Result Message: ", message);
+      return result.id;
+    case 1:
+      message = "Error: Failed to create the team vault directory.";
+      break;
+    case 4:
+      message = "Error: Failed to write the team vault.";
+      break;
+    case 7:
+      message = "Error: This login has no sharing keys.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 14:
+      message = "Error: Crypto self-test failed. Vaults are disabled.";
+      break;
+    case 21:
+      message = "Error: The vault name is empty or too long.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Create Team Vault Result Message: ", message);
+  return null;
+}
+
+async function listTeamMembers(username, vaultId) {
+  const data = await invoke("list_team_members", { username, vaultId });
+  return data ? JSON.parse(data) : [];
+}
+
+async function inviteTeamMember(username, vaultId, member, role) {
+  const resultCode = await invoke("invite_team_member", { username, vaultId, member, role });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Member invited.";
+      console.log("Invite Team Member Result Message: ", message);
+      return true;
+    case 5:
+      message = "Error: The user is a member already.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 12:
+      message = "Error: Unknown role.";
+      break;
+    case 19:
+      message = "Error: The vault does not exist.";
+      break;
+    case 22:
+      message = "Error: The user has no PassKeeper account.";
+      break;
+    case 23:
+      message = "Error: The member list is not signed by its owner.";
+      break;
+    case 25:
+      message = "Error: Only the owner can manage the team vault.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Invite Team Member Result Message: ", message);
+  return false;
+}
+
+async function removeTeamMember(username, vaultId, member) {
+  const resultCode = await invoke("remove_team_member", { username, vaultId, member });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Member removed and the vault key rotated.";
+      console.log("Remove Team Member Result Message: ", message);
+      return true;
+    case 4:
+      message = "Error: Failed to encrypt the vault with the new key.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 12:
+      message = "Error: The owner cannot be removed.";
+      break;
+    case 19:
+      message = "Error: The vault or the member does not exist.";
+      break;
+    case 22:
+      message = "Error: A remaining member has no sharing keys.";
+      break;
+    case 23:
+      message = "Error: The member list is not signed by its owner.";
+      break;
+    case 25:
+      message = "Error: Only the owner can manage the team vault.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Remove Team Member Result Message: ", message);
+  return false;
+}
+
+// Policies in force, readable before login
+async function getPolicy() {
+  const data = await invoke("get_policy");
+  return data ? JSON.parse(data) : null;
+}
+
+async function getAccountStatus(username) {
+  const data = await invoke("get_account_status", { username });
+  return data ? JSON.parse(data) : null;
+}
+
+// Checks a master password against the policy before sending it
+function checkPasswordPolicy(policy, password) {
+  if (!policy) {
+    return true;
+  }
+  const classes = [/[a-z]/, /[A-Z]/, /[0-9]/, /[^a-zA-Z0-9]/].filter(re => re.test(password)).length;
+  return password.length >= policy.min_password_length && classes >= policy.min_character_classes;
+}
+
+async function claimAdmin(username) {
+  const resultCode = await invoke("claim_admin", { username });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "You are the administrator now.";
+      console.log("Claim Admin Result Message: ", message);
+      return true;
+    case 2:
+      message = "Error: There is an administrator already.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 23:
+      message = "Error: The administration settings are not signed by an administrator.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Claim Admin Result Message: ", message);
+  return false;
+}
+
+async function listAccounts(username) {
+  const data = await invoke("list_accounts", { username });
+  return data ? JSON.parse(data) : [];
+}
+
+async function setAdmin(username, target, admin) {
+  const resultCode = await invoke("set_admin", { username, target, admin });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Administrators updated.";
+      console.log("Set Admin Result Message: ", message);
+      return true;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 12:
+      message = "Error: Administrators cannot step down themselves.";
+      break;
+    case 22:
+      message = "Error: The user has no PassKeeper account.";
+      break;
+    case 23:
+      message = "Error: The administration settings are not signed by an administrator.";
+      break;
+    case 26:
+      message = "Error: Only an administrator can do this.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Set Admin Result Message: ", message);
+  return false;
+}
+
+async function setAccountDisabled(username, target, disabled) {
+  const resultCode = await invoke("set_account_disabled", { username, target, disabled });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = disabled ? "Account disabled." : "Account enabled.";
+      console.log("Set Account Disabled Result Message: ", message);
+      return true;
+    case 3:
+      message = "Error: The account does not exist.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 12:
+      message = "Error: Administrators cannot disable themselves.";
+      break;
+    case 23:
+      message = "Error: The administration settings are not signed by an administrator.";
+      break;
+    case 26:
+      message = "Error: Only an administrator can do this.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Set Account Disabled Result Message: ", message);
+  return false;
+}
+
+async function resetMasterPassword(username, target, newPassword) {
+  const resultCode = await invoke("reset_master_password", { username, target, newPassword });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Master password reset.";
+      console.log("Reset Master Password Result Message: ", message);
+      return true;
+    case 3:
+      message = "Error: The account does not exist.";
+      break;
+    case 6:
+      message = "Error: Failed to encrypt the vault with the new password.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 23:
+      message = "Error: The administration settings are not signed by an administrator.";
+      break;
+    case 26:
+      message = "Error: Only an administrator can do this.";
+      break;
+    case 27:
+      message = "Error: The password does not meet the password policy.";
+      break;
+    case 29:
+      message = "Error: The key of the account is not escrowed for you.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Reset Master Password Result Message: ", message);
+  return false;
+}
+
+async function changeMasterPassword(username, oldPassword, newPassword) {
+  const resultCode = await invoke("change_master_password", { username, oldPassword, newPassword });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Master password changed.";
+      console.log("Change Master Password Result Message: ", message);
+      return true;
+    case 6:
+      message = "Error: Failed to encrypt the vault with the new password.";
+      break;
+    case 7:
+      message = "Error: The current password is incorrect.";
+      break;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 18:
+      message = "Error: The vault is busy.";
+      break;
+    case 27:
+      message = "Error: The new password does not meet the password policy.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Change Master Password Result Message: ", message);
+  return false;
+}
+
+async function setPolicy(username, policy) {
+  const resultCode = await invoke("set_policy", {
+    username,
+    adminOnlyRegistration: policy.admin_only_registration,
+    minPasswordLength: policy.min_password_length,
+    minCharacterClasses: policy.min_character_classes,
+    autoLockMinutes: policy.auto_lock_minutes,
+    keyEscrow: policy.key_escrow,
+  });
+  let message = "";
+  switch (resultCode) {
+    case 0:
+      message = "Policy saved.";
+      console.log("Set Policy Result Message: ", message);
+      return true;
+    case 8:
+      message = "Error: The vault is locked.";
+      break;
+    case 12:
+      message = "Error: There are only four kinds of characters.";
+      break;
+    case 23:
+      message = "Error: The administration settings are not signed by an administrator.";
+      break;
+    case 26:
+      message = "Error: Only an administrator can do this.";
+      break;
+    default:
+      message = "An unknown error occurred.";
+  }
+  console.log("Set Policy Result Message: ", message);
+  return false;
+}
+
+// Messages of the folder commands by result code
+function folderMessage(resultCode) {
+  switch (resultCode) {
+    case 0:
+      return "Done.";
+    case 3:
+      return "Error: The entry does not exist.";
+    case 5:
+      return "Error: A folder with the same name exists there.";
+    case 8:
+      return "Error: The vault is locked.";
+    case 16:
+      return "Error: The vault file failed its integrity check.";
+    case 18:
+      return "Error: The vault is being written by another process.";
+    case 24:
+      return "Error: The vault is read-only for you.";
+    case 31:
+      return "Error: The folder does not exist.";
+    case 32:
+      return "Error: The folder name is empty or too long, or the folder cannot move into itself.";
+    default:
+      return "An unknown error occurred.";
+  }
+}
+
+async function createFolder(username, name, parent = null, vaultId = null) {
+  const result = JSON.parse(await invoke("create_folder", { username, name, parent, vaultId }));
+  console.log("Create Folder Result Message: ", folderMessage(result.code));
+  return result.code === 0 ? result.id : null;
+}
+
+async function renameFolder(username, folderId, name, vaultId = null) {
+  const resultCode = await invoke("rename_folder", { username, folderId, name, vaultId });
+  console.log("Rename Folder Result Message: ", folderMessage(resultCode));
+  return resultCode === 0;
+}
+
+async function moveFolder(username, folderId, parent = null, vaultId = null) {
+  const resultCode = await invoke("move_folder", { username, folderId, parent, vaultId });
+  console.log("Move Folder Result Message: ", folderMessage(resultCode));
+  return resultCode === 0;
+}
+
+async function moveEntries(username, entryIds, folder = null, vaultId = null) {
+  const resultCode = await invoke("move_entries", { username, entryIds, folder, vaultId });
+  console.log("Move Entries Result Message: ", folderMessage(resultCode));
+  return resultCode === 0;
+}
+
+// Deletes a folder; `keepContents` moves what it holds into its parent instead
+async function deleteFolder(username, folderId, keepContents, vaultId = null) {
+  const resultCode = await invoke("delete_folder", { username, folderId, keepContents, vaultId });
+  console.log("Delete Folder Result Message: ", folderMessage(resultCode));
+  return resultCode === 0;
+}
+
 function get_current_user() {
   return getCurrentUser();
 }